@@ -0,0 +1,153 @@
+//! Measures what a task costs the current-thread runtime: spawning and
+//! completing one, polling one that keeps yielding, and cloning and waking
+//! its waker.
+//!
+//! It also compares the two task layouts the scheduler has used, driving
+//! the same futures through a bare run queue: the old one shared every
+//! task as `Rc<RefCell<Pin<Box<..>>>>` so the queue could hold clones, the
+//! current one owns a plain `Pin<Box<..>>` in a slab and queues its key.
+//!
+//! Run with `cargo bench --bench tasks`.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    hint::black_box,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use mini_rt::{Handle, MiniRuntime, future::poll_fn, yield_now};
+
+const TASKS: u32 = 200_000;
+const YIELDS: u32 = 1_000_000;
+const WAKERS: u32 = 1_000_000;
+
+/// Spawns `TASKS` tasks that finish on their first poll and waits for them.
+fn spawn_and_complete() -> Duration {
+    MiniRuntime::new().block_on(async {
+        let start = Instant::now();
+        let handle = Handle::current();
+        let tasks: Vec<_> = (0..TASKS).map(|i| handle.spawn(async move { i })).collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        start.elapsed()
+    })
+}
+
+/// Polls one task `YIELDS` times, each yield requeueing it.
+fn poll_yielding() -> Duration {
+    MiniRuntime::new().block_on(async {
+        let start = Instant::now();
+        for _ in 0..YIELDS {
+            yield_now().await;
+        }
+        start.elapsed()
+    })
+}
+
+/// Clones the running task's waker `WAKERS` times, waking and dropping
+/// each clone.
+fn clone_and_wake() -> Duration {
+    MiniRuntime::new().block_on(poll_fn(|cx| {
+        let start = Instant::now();
+        for _ in 0..WAKERS {
+            black_box(cx.waker().clone()).wake_by_ref();
+        }
+        Poll::Ready(start.elapsed())
+    }))
+}
+
+/// Polls per task in the layout comparison: the first and one per yield.
+const LAYOUT_POLLS: u32 = 4;
+
+type ErasedFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Returns `Pending` as many times as its count, then `Ready`. It never
+/// wakes itself: the run queue requeues it.
+struct Countdown(u32);
+
+impl Future for Countdown {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+        if black_box(self.0) == 0 {
+            return Poll::Ready(());
+        }
+        self.0 -= 1;
+        Poll::Pending
+    }
+}
+
+fn countdown() -> ErasedFuture {
+    Box::pin(Countdown(LAYOUT_POLLS - 1))
+}
+
+/// The old layout: each task is shared, and the queue holds clones of it.
+fn layout_shared() -> Duration {
+    let mut cx = Context::from_waker(Waker::noop());
+    let start = Instant::now();
+    let tasks: Vec<Rc<RefCell<ErasedFuture>>> = (0..TASKS)
+        .map(|_| Rc::new(RefCell::new(countdown())))
+        .collect();
+    let mut queue: VecDeque<_> = tasks.iter().cloned().collect();
+    drop(tasks);
+    while let Some(task) = queue.pop_front() {
+        if task.borrow_mut().as_mut().poll(&mut cx).is_pending() {
+            queue.push_back(task);
+        }
+    }
+    start.elapsed()
+}
+
+/// The current layout: the slab owns each future, and the queue holds keys.
+fn layout_owned() -> Duration {
+    let mut cx = Context::from_waker(Waker::noop());
+    let start = Instant::now();
+    let mut slab: Vec<Option<ErasedFuture>> = (0..TASKS).map(|_| Some(countdown())).collect();
+    let mut queue: VecDeque<usize> = (0..slab.len()).collect();
+    while let Some(key) = queue.pop_front() {
+        let task = slab[key].as_mut().unwrap();
+        if task.as_mut().poll(&mut cx).is_pending() {
+            queue.push_back(key);
+        } else {
+            slab[key] = None;
+        }
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let spawn = spawn_and_complete();
+    println!(
+        "spawn: {spawn:>10.2?} for {TASKS} tasks, {:>8.1} ns/task",
+        spawn.as_nanos() as f64 / f64::from(TASKS)
+    );
+    let poll = poll_yielding();
+    println!(
+        " poll: {poll:>10.2?} for {YIELDS} polls, {:>8.1} ns/poll",
+        poll.as_nanos() as f64 / f64::from(YIELDS)
+    );
+    let wake = clone_and_wake();
+    println!(
+        " wake: {wake:>10.2?} for {WAKERS} wakers, {:>8.1} ns/wake",
+        wake.as_nanos() as f64 / f64::from(WAKERS)
+    );
+
+    let polls = f64::from(TASKS * LAYOUT_POLLS);
+    let shared = layout_shared();
+    let owned = layout_owned();
+    println!(
+        "layout: Rc<RefCell<Pin<Box<..>>>> {shared:>10.2?}, {:>6.1} ns/poll",
+        shared.as_nanos() as f64 / polls
+    );
+    println!(
+        "layout: slab-owned Pin<Box<..>>   {owned:>10.2?}, {:>6.1} ns/poll ({:.2}x)",
+        owned.as_nanos() as f64 / polls,
+        shared.as_secs_f64() / owned.as_secs_f64()
+    );
+}
@@ -0,0 +1,40 @@
+//! Sets 100k short timers on a runtime with and without
+//! [`Builder::timer_wheel`] and reports how fast each gets through them.
+//!
+//! Time is paused, so the numbers measure timer bookkeeping rather than
+//! sleeping. Run with `cargo bench --bench timers`.
+
+use std::time::{Duration, Instant};
+
+use mini_rt::{Builder, Handle, sleep, time};
+
+const TIMERS: u64 = 100_000;
+
+/// Spawns `TIMERS` tasks sleeping up to a second each, then waits for all
+/// of them.
+fn run(builder: Builder) -> Duration {
+    let mut rt = builder.build();
+    rt.block_on(async {
+        time::pause();
+        let start = Instant::now();
+        let handle = Handle::current();
+        let sleepers: Vec<_> = (0..TIMERS)
+            .map(|i| handle.spawn(sleep(Duration::from_micros(i * 7919 % 1_000_000))))
+            .collect();
+        for sleeper in sleepers {
+            sleeper.await.unwrap();
+        }
+        start.elapsed()
+    })
+}
+
+fn main() {
+    for (name, builder) in [
+        ("heap", Builder::new as fn() -> Builder),
+        ("wheel", || Builder::new().timer_wheel()),
+    ] {
+        let elapsed = run(builder());
+        let rate = TIMERS as f64 / elapsed.as_secs_f64();
+        println!("{name:>5}: {elapsed:>10.2?} for {TIMERS} timers, {rate:>12.0} timers/s");
+    }
+}
@@ -0,0 +1,76 @@
+//! Queues every task on one worker's deque at once, then reports how the
+//! work spread over the pool and how long it took.
+//!
+//! A worker's wakes go to its own deque, so a task waking many others is
+//! the most lopsided submission there is; without stealing, the one worker
+//! would run them all. Run with `cargo bench --bench work_stealing`.
+
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    task::{Poll, Waker},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use mini_rt::{MiniRuntime, future};
+
+const TASKS: usize = 1_000;
+const ROUNDS: u64 = 200_000;
+
+/// Runs `TASKS` CPU-bound tasks on `workers` threads, all woken from one
+/// worker, returning the elapsed time and how many tasks each thread ran.
+fn run(workers: usize) -> (Duration, Vec<usize>) {
+    let rt = MiniRuntime::multi_thread(workers);
+    let ran: Arc<Mutex<HashMap<ThreadId, usize>>> = Arc::default();
+    let (parked_tx, parked_rx) = mpsc::channel::<Waker>();
+
+    // Park each task once, handing its waker over, so the first poll does
+    // not count as submission.
+    let handles: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let ran = ran.clone();
+            let parked_tx = parked_tx.clone();
+            let polled = AtomicBool::new(false);
+            rt.spawn(future::poll_fn(move |cx| {
+                if !polled.swap(true, Ordering::SeqCst) {
+                    parked_tx.send(cx.waker().clone()).unwrap();
+                    return Poll::Pending;
+                }
+                black_box((0..ROUNDS).fold(0u64, |acc, i| acc.wrapping_mul(31) ^ i));
+                *ran.lock()
+                    .unwrap()
+                    .entry(thread::current().id())
+                    .or_default() += 1;
+                Poll::Ready(())
+            }))
+        })
+        .collect();
+    let wakers: Vec<_> = parked_rx.iter().take(TASKS).collect();
+
+    let start = Instant::now();
+    rt.block_on(async move {
+        for waker in wakers {
+            waker.wake();
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
+    let elapsed = start.elapsed();
+    let mut counts: Vec<_> = ran.lock().unwrap().values().copied().collect();
+    counts.sort_unstable_by(|a, b| b.cmp(a));
+    (elapsed, counts)
+}
+
+fn main() {
+    for workers in [1, 2, 4] {
+        let (elapsed, counts) = run(workers);
+        println!("{workers} workers: {elapsed:>10.2?}, tasks per thread {counts:?}");
+    }
+}
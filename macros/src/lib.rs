@@ -0,0 +1,231 @@
+//! Attribute macros re-exported by `mini_rt`: `mini_rt::main` and
+//! `mini_rt::test`.
+//!
+//! The parsing is done by hand on the raw token stream rather than with
+//! `syn`: the macros only need to find `async fn`, the argument list, the
+//! return type, and the body, and this keeps the runtime dependency-free.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Turns `async fn main() -> R { .. }` into a synchronous `main` that runs
+/// the body on a fresh runtime and returns its output.
+///
+/// `#[mini_rt::main(worker_threads = N)]` with `N > 1` uses a multi-thread
+/// runtime instead, which requires the body to be `Send`.
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(args, item, Kind::Main).unwrap_or_else(|error| error.into_compile_error())
+}
+
+/// Turns an `async fn` into a `#[test]` that runs the body on a fresh
+/// runtime. Like a plain test it may return a `Result`, failing on `Err`.
+///
+/// Accepts the same `worker_threads = N` argument as [`main`](macro@main).
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(args, item, Kind::Test).unwrap_or_else(|error| error.into_compile_error())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Main,
+    Test,
+}
+
+fn expand(args: TokenStream, item: TokenStream, kind: Kind) -> Result<TokenStream, Error> {
+    let worker_threads = parse_args(args)?;
+    let mut function = AsyncFn::parse(item)?;
+    if !function.args.stream().is_empty() {
+        let message = match kind {
+            Kind::Main => "the main function cannot take arguments",
+            Kind::Test => "test functions cannot take arguments",
+        };
+        return Err(Error::new(function.args.span(), message));
+    }
+    if kind == Kind::Test {
+        let mut prefix: Vec<TokenTree> = "#[::core::prelude::v1::test]"
+            .parse::<TokenStream>()
+            .unwrap()
+            .into_iter()
+            .collect();
+        prefix.append(&mut function.prefix);
+        function.prefix = prefix;
+    }
+    let runtime = match worker_threads {
+        Some(n) if n > 1 => {
+            format!("::mini_rt::Builder::new().worker_threads({n}).build()")
+        }
+        _ => "::mini_rt::MiniRuntime::new()".to_owned(),
+    };
+    let mut body: TokenStream = format!("let mut runtime = {runtime}; runtime.block_on")
+        .parse()
+        .unwrap();
+    body.extend([parenthesized(async_block(function.body.clone()))]);
+    Ok(function.rebuild(body))
+}
+
+/// `async move { .. }` around a function body.
+fn async_block(body: Group) -> TokenStream {
+    let mut block: TokenStream = "async move".parse().unwrap();
+    block.extend([TokenTree::Group(body)]);
+    block
+}
+
+fn parenthesized(tokens: TokenStream) -> TokenTree {
+    TokenTree::Group(Group::new(Delimiter::Parenthesis, tokens))
+}
+
+/// Parses `worker_threads = N`, the only supported argument.
+fn parse_args(args: TokenStream) -> Result<Option<usize>, Error> {
+    let mut tokens = args.into_iter().peekable();
+    let mut worker_threads = None;
+    while let Some(token) = tokens.next() {
+        let TokenTree::Ident(key) = &token else {
+            return Err(Error::new(token.span(), "expected `worker_threads = N`"));
+        };
+        if key.to_string() != "worker_threads" {
+            return Err(Error::new(
+                key.span(),
+                format!("unknown argument `{key}`, expected `worker_threads`"),
+            ));
+        }
+        match tokens.next() {
+            Some(TokenTree::Punct(eq)) if eq.as_char() == '=' => {}
+            _ => {
+                return Err(Error::new(
+                    key.span(),
+                    "expected `=` after `worker_threads`",
+                ));
+            }
+        }
+        let value = match tokens.next() {
+            Some(TokenTree::Literal(value)) => value,
+            _ => {
+                return Err(Error::new(
+                    key.span(),
+                    "expected an integer for `worker_threads`",
+                ));
+            }
+        };
+        let count = value
+            .to_string()
+            .parse::<usize>()
+            .ok()
+            .filter(|&count| count > 0)
+            .ok_or_else(|| {
+                Error::new(value.span(), "`worker_threads` must be a positive integer")
+            })?;
+        worker_threads = Some(count);
+        match tokens.next() {
+            None => {}
+            Some(TokenTree::Punct(comma)) if comma.as_char() == ',' => {}
+            Some(other) => return Err(Error::new(other.span(), "expected `,`")),
+        }
+    }
+    Ok(worker_threads)
+}
+
+/// An `async fn` split into the pieces the macros rewrite.
+struct AsyncFn {
+    /// Attributes, visibility, and qualifiers before `fn`, minus `async`.
+    prefix: Vec<TokenTree>,
+    name: Ident,
+    args: Group,
+    /// Everything between the argument list and the body: the return type
+    /// and any `where` clause.
+    signature: Vec<TokenTree>,
+    body: Group,
+}
+
+impl AsyncFn {
+    fn parse(item: TokenStream) -> Result<Self, Error> {
+        let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+        let body = match tokens.pop() {
+            Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => body,
+            other => {
+                let span = other.map_or_else(Span::call_site, |token| token.span());
+                return Err(Error::new(span, "expected a function with a body"));
+            }
+        };
+        let fn_index = tokens
+            .iter()
+            .position(|token| matches!(token, TokenTree::Ident(ident) if ident.to_string() == "fn"))
+            .ok_or_else(|| Error::new(Span::call_site(), "expected a function"))?;
+        let async_index = tokens[..fn_index]
+            .iter()
+            .position(
+                |token| matches!(token, TokenTree::Ident(ident) if ident.to_string() == "async"),
+            )
+            .ok_or_else(|| Error::new(tokens[fn_index].span(), "the function must be `async`"))?;
+
+        let mut rest = tokens.split_off(fn_index).into_iter().skip(1);
+        tokens.remove(async_index);
+        let name = match rest.next() {
+            Some(TokenTree::Ident(name)) => name,
+            _ => return Err(Error::new(body.span(), "expected a function name")),
+        };
+        let args = match rest.next() {
+            Some(TokenTree::Group(args)) if args.delimiter() == Delimiter::Parenthesis => args,
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '<' => {
+                return Err(Error::new(punct.span(), "the function cannot be generic"));
+            }
+            _ => return Err(Error::new(name.span(), "expected an argument list")),
+        };
+        Ok(Self {
+            prefix: tokens,
+            name,
+            args,
+            signature: rest.collect(),
+            body,
+        })
+    }
+
+    /// Emits the function as a plain `fn` with the same signature and the
+    /// given body.
+    fn rebuild(self, body: TokenStream) -> TokenStream {
+        let mut output: TokenStream = self.prefix.into_iter().collect();
+        output.extend([
+            TokenTree::Ident(Ident::new("fn", self.name.span())),
+            TokenTree::Ident(self.name),
+            TokenTree::Group(self.args),
+        ]);
+        output.extend(self.signature);
+        let mut body = Group::new(Delimiter::Brace, body);
+        body.set_span(self.body.span());
+        output.extend([TokenTree::Group(body)]);
+        output
+    }
+}
+
+/// A compile error to report at `span`.
+struct Error {
+    span: Span,
+    message: String,
+}
+
+impl Error {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// Expands to `::core::compile_error! { "message" }` at the error's span.
+    fn into_compile_error(self) -> TokenStream {
+        let mut bang = Punct::new('!', Spacing::Alone);
+        bang.set_span(self.span);
+        let mut message = Literal::string(&self.message);
+        message.set_span(self.span);
+        let mut group = Group::new(Delimiter::Brace, TokenTree::Literal(message).into());
+        group.set_span(self.span);
+        let path: TokenStream = "::core::compile_error".parse().unwrap();
+        path.into_iter()
+            .map(|mut token| {
+                token.set_span(self.span);
+                token
+            })
+            .chain([TokenTree::Punct(bang), TokenTree::Group(group)])
+            .collect()
+    }
+}
@@ -0,0 +1,260 @@
+//! A small thread pool for running blocking work off the executor thread.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+use crate::RUNTIME;
+
+/// How many pool threads may exist at once unless configured otherwise.
+pub(crate) const DEFAULT_MAX_THREADS: usize = 16;
+
+/// How long an idle pool thread waits for more work before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs closures on up to `max_threads` background threads, reusing idle
+/// threads before spawning new ones. Dropping the pool lets the threads
+/// finish the queued jobs and exit.
+pub(crate) struct BlockingPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: Mutex<PoolState>,
+    condvar: Condvar,
+    thread_name: String,
+}
+
+struct PoolState {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+    max_threads: usize,
+    shutdown: bool,
+}
+
+impl BlockingPool {
+    /// Creates an empty pool; threads are named `thread_name`, or
+    /// `mini-rt-blocking` by default.
+    pub(crate) fn new(max_threads: usize, thread_name: Option<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(PoolState {
+                    jobs: VecDeque::new(),
+                    threads: 0,
+                    idle: 0,
+                    max_threads: max_threads.max(1),
+                    shutdown: false,
+                }),
+                condvar: Condvar::new(),
+                thread_name: thread_name.unwrap_or_else(|| "mini-rt-blocking".into()),
+            }),
+        }
+    }
+
+    pub(crate) fn set_max_threads(&self, max_threads: usize) {
+        self.inner.state.lock().unwrap().max_threads = max_threads.max(1);
+    }
+
+    /// Returns `true` if no job is queued or running.
+    pub(crate) fn is_idle(&self) -> bool {
+        let state = self.inner.state.lock().unwrap();
+        state.jobs.is_empty() && state.idle == state.threads
+    }
+
+    /// Runs `f` on a pool thread and returns a future for its result. A
+    /// panic in `f` is re-raised where the future is awaited.
+    pub(crate) fn run<F, T>(&self, f: F) -> BlockingTask<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            output: None,
+            waker: None,
+        }));
+        let done = slot.clone();
+        self.execute(Box::new(move || {
+            let output = panic::catch_unwind(AssertUnwindSafe(f));
+            let mut slot = done.lock().unwrap();
+            slot.output = Some(output);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }));
+        BlockingTask { slot }
+    }
+
+    fn execute(&self, job: Job) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.jobs.push_back(job);
+        if state.idle > 0 {
+            self.inner.condvar.notify_one();
+        }
+        // Only grow the pool if the idle threads can't cover the backlog.
+        if state.jobs.len() > state.idle && state.threads < state.max_threads {
+            state.threads += 1;
+            let inner = self.inner.clone();
+            thread::Builder::new()
+                .name(self.inner.thread_name.clone())
+                .spawn(move || inner.work())
+                .expect("failed to spawn blocking pool thread");
+        }
+    }
+}
+
+/// Runs `f` on the current runtime's blocking pool. For crate helpers that
+/// await the result directly instead of through a `JoinHandle`.
+///
+/// # Panics
+///
+/// Panics if called outside of `block_on`.
+pub(crate) fn run<F, T>(f: F) -> BlockingTask<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    RUNTIME.with(|rt| {
+        rt.borrow()
+            .as_ref()
+            .expect("blocking I/O used outside of a runtime")
+            .borrow()
+            .blocking
+            .run(f)
+    })
+}
+
+impl Drop for BlockingPool {
+    fn drop(&mut self) {
+        self.inner.state.lock().unwrap().shutdown = true;
+        self.inner.condvar.notify_all();
+    }
+}
+
+impl Inner {
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            if state.shutdown {
+                break;
+            }
+            state.idle += 1;
+            let (next, wait) = self.condvar.wait_timeout(state, KEEP_ALIVE).unwrap();
+            state = next;
+            state.idle -= 1;
+            if wait.timed_out() && state.jobs.is_empty() {
+                break;
+            }
+        }
+        state.threads -= 1;
+    }
+}
+
+struct Slot<T> {
+    output: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the result of a closure handed to [`BlockingPool::run`].
+pub(crate) struct BlockingTask<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.output.take() {
+            Some(Ok(output)) => Poll::Ready(output),
+            Some(Err(payload)) => {
+                drop(slot);
+                panic::resume_unwind(payload)
+            }
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        collections::HashSet,
+        rc::Rc,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{Builder, Handle, MiniRuntime, sleep};
+
+    #[test]
+    fn async_tasks_keep_running_during_blocking_work() {
+        let mut rt = MiniRuntime::new();
+        let progress = Rc::new(Cell::new(0));
+        let counter = progress.clone();
+        let (seen_during, result) = rt.block_on(async move {
+            let blocking = Handle::current().spawn_blocking(|| {
+                thread::sleep(Duration::from_millis(100));
+                7
+            });
+            let ticker = Handle::current().spawn(async move {
+                for _ in 0..5 {
+                    sleep(Duration::from_millis(5)).await;
+                    counter.set(counter.get() + 1);
+                }
+            });
+            ticker.await.unwrap();
+            let seen_during = progress.get();
+            (seen_during, blocking.await.unwrap())
+        });
+        // The ticker finished while the closure was still sleeping.
+        assert_eq!((seen_during, result), (5, 7));
+    }
+
+    #[test]
+    fn the_pool_caps_and_reuses_its_threads() {
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static PEAK: AtomicUsize = AtomicUsize::new(0);
+        let mut rt = Builder::new().max_blocking_threads(2).build();
+        let threads = rt.block_on(async {
+            let jobs: Vec<_> = (0..6)
+                .map(|_| {
+                    Handle::current().spawn_blocking(|| {
+                        let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+                        PEAK.fetch_max(running, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(10));
+                        RUNNING.fetch_sub(1, Ordering::SeqCst);
+                        thread::current().id()
+                    })
+                })
+                .collect();
+            let mut threads = HashSet::new();
+            for job in jobs {
+                threads.insert(job.await.unwrap());
+            }
+            threads
+        });
+        assert!(threads.len() <= 2, "used {} threads", threads.len());
+        assert_eq!(PEAK.load(Ordering::SeqCst), 2);
+    }
+}
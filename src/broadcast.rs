@@ -0,0 +1,260 @@
+//! A multi-producer, multi-consumer channel where every receiver sees every
+//! value.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker, ready},
+};
+
+use crate::coop;
+
+/// Creates a broadcast channel that keeps the last `capacity` values for
+/// receivers that have not seen them yet.
+///
+/// A receiver that falls more than `capacity` values behind misses the
+/// oldest ones and is told so with [`RecvError::Lagged`].
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast channel capacity must be positive");
+    let state = Rc::new(RefCell::new(State {
+        buffer: VecDeque::with_capacity(capacity),
+        capacity,
+        head: 0,
+        wakers: Vec::new(),
+        senders: 1,
+        receivers: 1,
+    }));
+    (
+        Sender {
+            state: state.clone(),
+        },
+        Receiver { state, next: 0 },
+    )
+}
+
+/// The sending half of a broadcast channel. Clone it to get more producers.
+pub struct Sender<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// A receiving half of a broadcast channel. Get more with
+/// [`Sender::subscribe`].
+pub struct Receiver<T> {
+    state: Rc<RefCell<State<T>>>,
+    /// Position of the next value this receiver will see.
+    next: u64,
+}
+
+/// Future returned by [`Receiver::recv`].
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+/// Error returned by [`Sender::send`] when there are no receivers, carrying
+/// the value that could not be delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Why [`Receiver::recv`] did not produce a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// Every sender is gone and the receiver has seen everything sent.
+    Closed,
+    /// The receiver fell behind and this many values were dropped before it
+    /// saw them. The next `recv` continues with the oldest value still kept.
+    Lagged(u64),
+}
+
+struct State<T> {
+    /// The last `capacity` values, oldest first.
+    buffer: VecDeque<T>,
+    capacity: usize,
+    /// Position of `buffer[0]` among all values ever sent.
+    head: u64,
+    /// Wakers of receivers waiting for the next value.
+    wakers: Vec<Waker>,
+    senders: usize,
+    receivers: usize,
+}
+
+impl<T> State<T> {
+    /// Position the next sent value will get.
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends `value` to every current receiver, waking those that are
+    /// waiting. Returns how many receivers there are.
+    ///
+    /// Returns the value back if there are no receivers.
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = self.state.borrow_mut();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == state.capacity {
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        state.wake_all();
+        Ok(state.receivers)
+    }
+
+    /// Creates a receiver that sees every value sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.state.borrow_mut();
+        state.receivers += 1;
+        Receiver {
+            state: self.state.clone(),
+            next: state.tail(),
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.state.borrow().receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.state.borrow_mut().senders += 1;
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.wake_all();
+        }
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Receives the next value this receiver has not seen yet.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.state.borrow_mut().receivers -= 1;
+    }
+}
+
+impl<T: Clone> Future for Recv<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(coop::poll_proceed(cx));
+        let receiver = &mut *self.get_mut().receiver;
+        let mut state = receiver.state.borrow_mut();
+        if receiver.next < state.head {
+            let missed = state.head - receiver.next;
+            receiver.next = state.head;
+            return Poll::Ready(Err(RecvError::Lagged(missed)));
+        }
+        if receiver.next < state.tail() {
+            let value = state.buffer[(receiver.next - state.head) as usize].clone();
+            receiver.next += 1;
+            return Poll::Ready(Ok(value));
+        }
+        if state.senders == 0 {
+            return Poll::Ready(Err(RecvError::Closed));
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a broadcast channel with no receivers")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Closed => f.write_str("broadcast channel closed"),
+            RecvError::Lagged(missed) => write!(f, "receiver lagged by {missed} values"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Handle, MiniRuntime};
+
+    #[test]
+    fn every_receiver_gets_every_message() {
+        let mut rt = MiniRuntime::new();
+        let (first, second) = rt.block_on(async {
+            let (tx, rx) = channel(4);
+            let other = tx.subscribe();
+            let drain = |mut rx: Receiver<&'static str>| async move {
+                let mut seen = Vec::new();
+                while let Ok(value) = rx.recv().await {
+                    seen.push(value);
+                }
+                seen
+            };
+            let first = Handle::current().spawn(drain(rx));
+            let second = Handle::current().spawn(drain(other));
+            for value in ["a", "b", "c"] {
+                assert_eq!(tx.send(value), Ok(2));
+                crate::yield_now().await;
+            }
+            drop(tx);
+            (first.await.unwrap(), second.await.unwrap())
+        });
+        assert_eq!(first, ["a", "b", "c"]);
+        assert_eq!(second, first);
+    }
+
+    #[test]
+    fn a_slow_receiver_is_told_how_far_it_lagged() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let (tx, mut rx) = channel(2);
+            for value in 0..5 {
+                tx.send(value).unwrap();
+            }
+            assert_eq!(rx.recv().await, Err(RecvError::Lagged(3)));
+            assert_eq!(rx.recv().await, Ok(3));
+            assert_eq!(rx.recv().await, Ok(4));
+            drop(tx);
+            assert_eq!(rx.recv().await, Err(RecvError::Closed));
+        });
+    }
+}
@@ -0,0 +1,247 @@
+//! Configuration for constructing a runtime.
+
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::{
+    MiniRuntime, MultiThreadRuntime, blocking,
+    time::{Clock, SystemClock},
+};
+
+/// Configures and creates a runtime; see [`MiniRuntime::builder`].
+///
+/// The flavor parameter records which runtime [`build`](Builder::build)
+/// returns: a [`MiniRuntime`] by default, or a [`MultiThreadRuntime`] once
+/// [`worker_threads`](Builder::worker_threads) has been set.
+///
+/// ```
+/// let mut rt = mini_rt::MiniRuntime::builder()
+///     .max_blocking_threads(4)
+///     .thread_name("my-app")
+///     .build();
+/// assert_eq!(rt.block_on(async { 1 + 1 }), 2);
+///
+/// let pool = mini_rt::MiniRuntime::builder()
+///     .worker_threads(4)
+///     .max_blocking_threads(2)
+///     .thread_name("my-pool")
+///     .build();
+/// assert_eq!(pool.num_workers(), 4);
+/// ```
+pub struct Builder<Flavor = CurrentThread> {
+    pub(crate) worker_threads: usize,
+    pub(crate) max_blocking_threads: usize,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) max_concurrent_tasks: Option<usize>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) timer_wheel: bool,
+    flavor: PhantomData<Flavor>,
+}
+
+/// [`Builder`] flavor that builds a [`MiniRuntime`], polling every task on
+/// the thread calling `block_on`.
+pub struct CurrentThread;
+
+/// [`Builder`] flavor that builds a [`MultiThreadRuntime`], polling `Send`
+/// tasks on a pool of worker threads.
+pub struct MultiThread;
+
+impl Builder {
+    /// Starts from the defaults: a current-thread runtime, up to sixteen
+    /// blocking threads, the runtime's own thread names, and the system clock.
+    pub fn new() -> Self {
+        Self {
+            worker_threads: 1,
+            max_blocking_threads: blocking::DEFAULT_MAX_THREADS,
+            thread_name: None,
+            max_concurrent_tasks: None,
+            clock: Arc::new(SystemClock),
+            timer_wheel: false,
+            flavor: PhantomData,
+        }
+    }
+
+    /// Caps how many spawned tasks a current-thread runtime keeps live at
+    /// once. Tasks spawned beyond the cap wait, unpolled, until one of the
+    /// live tasks finishes, so a burst of spawns cannot run away with
+    /// memory. Unlimited by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_concurrent_tasks(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_concurrent_tasks must be at least 1");
+        self.max_concurrent_tasks = Some(max);
+        self
+    }
+
+    /// Creates a current-thread runtime, which polls every task on the
+    /// thread calling `block_on`.
+    pub fn build(self) -> MiniRuntime {
+        MiniRuntime::from_builder(&self)
+    }
+}
+
+impl Builder<MultiThread> {
+    /// Creates a runtime that polls `Send` tasks on `worker_threads`
+    /// threads. It has no task cap, so there is no `max_concurrent_tasks`
+    /// to set.
+    pub fn build(self) -> MultiThreadRuntime {
+        MultiThreadRuntime::new(&self)
+    }
+}
+
+impl<Flavor> Builder<Flavor> {
+    /// Switches to a multi-thread runtime that polls tasks on `count`
+    /// worker threads. Without this call the builder creates a
+    /// current-thread runtime, the equivalent of a single worker.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is zero.
+    pub fn worker_threads(self, count: usize) -> Builder<MultiThread> {
+        assert!(count > 0, "worker_threads must be at least 1");
+        Builder {
+            worker_threads: count,
+            max_blocking_threads: self.max_blocking_threads,
+            thread_name: self.thread_name,
+            max_concurrent_tasks: None,
+            clock: self.clock,
+            timer_wheel: self.timer_wheel,
+            flavor: PhantomData,
+        }
+    }
+
+    /// Caps how many threads `spawn_blocking` may use at once; see
+    /// [`MiniRuntime::set_max_blocking_threads`].
+    pub fn max_blocking_threads(mut self, max: usize) -> Self {
+        self.max_blocking_threads = max;
+        self
+    }
+
+    /// Names every thread the runtime starts: worker threads and blocking
+    /// pool threads.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Sets the clock timers are measured against, such as a
+    /// [`MockClock`](crate::time::MockClock) for tests.
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Keeps timers in a hashed timer wheel rather than a binary heap, so
+    /// that setting and cancelling one takes constant time however many are
+    /// pending, as with a timeout per connection. The price is resolution:
+    /// the wheel turns in whole milliseconds, so a timer may fire up to a
+    /// millisecond after its deadline, never before. Deadlines more than
+    /// about four and a half hours ahead still go in the heap.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::builder().timer_wheel().build();
+    /// rt.block_on(async {
+    ///     let start = mini_rt::time::now();
+    ///     mini_rt::sleep(Duration::from_micros(1500)).await;
+    ///     assert!(mini_rt::time::now() - start >= Duration::from_micros(1500));
+    /// });
+    /// ```
+    pub fn timer_wheel(mut self) -> Self {
+        self.timer_wheel = true;
+        self
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::Handle;
+
+    /// Thread names and ids seen by blocking jobs.
+    type Seen = Arc<Mutex<Vec<(Option<String>, thread::ThreadId)>>>;
+
+    /// Holds its blocking thread long enough that overlapping jobs would
+    /// each need their own without a cap, then records where it ran.
+    fn record(seen: &Seen) -> impl FnOnce() + Send + 'static {
+        let seen = seen.clone();
+        move || {
+            thread::sleep(Duration::from_millis(10));
+            let current = thread::current();
+            seen.lock()
+                .unwrap()
+                .push((current.name().map(str::to_owned), current.id()));
+        }
+    }
+
+    fn threads_and_names(seen: &Seen) -> (usize, HashSet<Option<String>>) {
+        let seen = seen.lock().unwrap();
+        let threads: HashSet<_> = seen.iter().map(|(_, id)| *id).collect();
+        let names = seen.iter().map(|(name, _)| name.clone()).collect();
+        (threads.len(), names)
+    }
+
+    #[test]
+    fn a_current_thread_runtime_honors_its_settings() {
+        let mut rt = MiniRuntime::builder()
+            .max_blocking_threads(2)
+            .thread_name("configured")
+            .build();
+        let seen = Seen::default();
+        let caller = thread::current().id();
+        let polled_on = rt.block_on({
+            let seen = seen.clone();
+            async move {
+                let jobs: Vec<_> = (0..6)
+                    .map(|_| Handle::current().spawn_blocking(record(&seen)))
+                    .collect();
+                for job in jobs {
+                    job.await.unwrap();
+                }
+                thread::current().id()
+            }
+        });
+        // Without `worker_threads`, tasks run on the calling thread.
+        assert_eq!(polled_on, caller);
+        let (threads, names) = threads_and_names(&seen);
+        assert!(threads <= 2, "used {threads} blocking threads");
+        assert_eq!(names, HashSet::from([Some("configured".to_owned())]));
+    }
+
+    #[test]
+    fn a_multi_thread_runtime_honors_its_settings() {
+        let rt = MiniRuntime::builder()
+            .worker_threads(3)
+            .max_blocking_threads(1)
+            .thread_name("pooled")
+            .build();
+        assert_eq!(rt.num_workers(), 3);
+        let seen = Seen::default();
+        let jobs: Vec<_> = (0..3).map(|_| rt.spawn_blocking(record(&seen))).collect();
+        let worker = rt.block_on(async move {
+            for job in jobs {
+                job.await.unwrap();
+            }
+            thread::current().name().map(str::to_owned)
+        });
+        assert_eq!(worker.as_deref(), Some("pooled"));
+        let (threads, names) = threads_and_names(&seen);
+        assert_eq!(threads, 1);
+        assert_eq!(names, HashSet::from([Some("pooled".to_owned())]));
+    }
+}
@@ -0,0 +1,587 @@
+//! Multi-producer, single-consumer channels for passing values between tasks,
+//! and an adapter for awaiting std channels.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::mpsc,
+    task::{Context, Poll, Waker, ready},
+    time::Duration,
+};
+
+use crate::{
+    JoinHandle, coop,
+    stream::Stream,
+    time::{Sleep, sleep},
+};
+
+/// How long a [`StdReceiver`] first waits before looking again, and how far
+/// the wait may back off while nothing arrives.
+const STD_POLL_MIN: Duration = Duration::from_millis(1);
+const STD_POLL_MAX: Duration = Duration::from_millis(32);
+
+/// Creates an unbounded channel, returning a cloneable sender and the
+/// receiver.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let state = State::new(None);
+    (
+        Sender {
+            state: state.clone(),
+        },
+        Receiver { state },
+    )
+}
+
+/// Creates a channel holding at most `capacity` queued values. Sending on a
+/// full channel waits until the receiver makes room.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn bounded<T>(capacity: usize) -> (BoundedSender<T>, Receiver<T>) {
+    assert!(capacity > 0, "bounded channel capacity must be positive");
+    let state = State::new(Some(capacity));
+    (
+        BoundedSender {
+            state: state.clone(),
+        },
+        Receiver { state },
+    )
+}
+
+/// The sending half of a channel. Clone it to get more producers.
+pub struct Sender<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// The sending half of a bounded channel. Clone it to get more producers.
+pub struct BoundedSender<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// Future returned by [`BoundedSender::send`].
+pub struct BoundedSend<'a, T> {
+    sender: &'a BoundedSender<T>,
+    value: Option<T>,
+    /// Our place among the parked senders, once we have had to park.
+    ticket: Option<u64>,
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// Future returned by [`Receiver::recv`].
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+/// Error returned by `send` once the receiver has been dropped, carrying the
+/// value that could not be delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+struct State<T> {
+    queue: VecDeque<T>,
+    /// `None` for unbounded channels.
+    capacity: Option<usize>,
+    /// The waker of a receiver parked on an empty queue.
+    waker: Option<Waker>,
+    /// Bounded senders parked on a full queue as `(ticket, waker)`, oldest
+    /// first. Each keeps its entry until it sends or is dropped.
+    send_wakers: VecDeque<(u64, Waker)>,
+    next_ticket: u64,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+impl<T> State<T> {
+    fn new(capacity: Option<usize>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            queue: VecDeque::new(),
+            capacity,
+            waker: None,
+            send_wakers: VecDeque::new(),
+            next_ticket: 0,
+            senders: 1,
+            receiver_alive: true,
+        }))
+    }
+
+    fn push(&mut self, value: T) {
+        self.queue.push_back(value);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.queue.len() >= capacity)
+    }
+
+    /// Lets the longest-waiting sender know there is room for it.
+    fn wake_first_sender(&self) {
+        if !self.is_full()
+            && let Some((_, waker)) = self.send_wakers.front()
+        {
+            waker.wake_by_ref();
+        }
+    }
+
+    fn drop_sender(&mut self) {
+        self.senders -= 1;
+        if self.senders == 0
+            && let Some(waker) = self.waker.take()
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Queues `value` for the receiver, waking it if it is waiting.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.state.borrow_mut();
+        if !state.receiver_alive {
+            return Err(SendError(value));
+        }
+        state.push(value);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.state.borrow_mut().senders += 1;
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.state.borrow_mut().drop_sender();
+    }
+}
+
+impl<T> BoundedSender<T> {
+    /// Queues `value`, first waiting for room if the channel is full.
+    pub fn send(&self, value: T) -> BoundedSend<'_, T> {
+        BoundedSend {
+            sender: self,
+            value: Some(value),
+            ticket: None,
+        }
+    }
+}
+
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> Self {
+        self.state.borrow_mut().senders += 1;
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> Drop for BoundedSender<T> {
+    fn drop(&mut self) {
+        self.state.borrow_mut().drop_sender();
+    }
+}
+
+impl<T> Unpin for BoundedSend<'_, T> {}
+
+impl<T> Future for BoundedSend<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(coop::poll_proceed(cx));
+        let value = self
+            .value
+            .take()
+            .expect("BoundedSend polled after completion");
+        let this = &mut *self;
+        let mut state = this.sender.state.borrow_mut();
+        if !state.receiver_alive {
+            return Poll::Ready(Err(SendError(value)));
+        }
+        // Room only goes to a newcomer when nobody is parked ahead of it.
+        let first_in_line = match this.ticket {
+            None => state.send_wakers.is_empty(),
+            Some(ticket) => state
+                .send_wakers
+                .front()
+                .is_some_and(|(first, _)| *first == ticket),
+        };
+        if state.is_full() || !first_in_line {
+            match this.ticket {
+                Some(ticket) => {
+                    if let Some((_, waker)) =
+                        state.send_wakers.iter_mut().find(|(t, _)| *t == ticket)
+                    {
+                        waker.clone_from(cx.waker());
+                    }
+                }
+                None => {
+                    let ticket = state.next_ticket;
+                    state.next_ticket += 1;
+                    state.send_wakers.push_back((ticket, cx.waker().clone()));
+                    this.ticket = Some(ticket);
+                }
+            }
+            drop(state);
+            this.value = Some(value);
+            return Poll::Pending;
+        }
+        if this.ticket.take().is_some() {
+            state.send_wakers.pop_front();
+        }
+        state.push(value);
+        // A receiver that drained several values leaves room for more.
+        state.wake_first_sender();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Drop for BoundedSend<'_, T> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else { return };
+        let mut state = self.sender.state.borrow_mut();
+        let Some(index) = state.send_wakers.iter().position(|(t, _)| *t == ticket) else {
+            return;
+        };
+        state.send_wakers.remove(index);
+        // If we were next in line, pass our turn on.
+        if index == 0 {
+            state.wake_first_sender();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, or `None` once every sender is dropped and
+    /// the queue is drained.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.receiver_alive = false;
+        // Parked senders must observe the closed channel.
+        for (_, waker) in state.send_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(coop::poll_proceed(cx));
+        let mut state = self.receiver.state.borrow_mut();
+        if let Some(value) = state.queue.pop_front() {
+            // One slot just freed up; let the longest-waiting sender take it.
+            state.wake_first_sender();
+            Poll::Ready(Some(value))
+        } else if state.senders == 0 {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Lets tasks await a [`std::sync::mpsc::Receiver`], such as one fed by a
+/// plain thread.
+///
+/// A std channel cannot wake a task, so the receiver checks it on a timer
+/// instead: every millisecond at first, backing off to every 32ms while the
+/// channel stays empty, and starting over once a value arrives.
+///
+/// With time [paused](crate::time::pause), a timer would only make the
+/// runtime jump its clock ahead and check again straight away, spinning
+/// until some other thread sends. The receiver instead waits out each
+/// backoff in real time on the blocking pool, which also keeps the paused
+/// clock where it is.
+///
+/// ```
+/// use std::{sync::mpsc, thread};
+/// use mini_rt::{StreamExt, channel};
+///
+/// let (tx, rx) = mpsc::channel();
+/// let producer = thread::spawn(move || {
+///     for n in 0..5 {
+///         tx.send(n).unwrap();
+///     }
+/// });
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let received: Vec<_> = rt.block_on(channel::from_std(rx).collect());
+/// assert_eq!(received, [0, 1, 2, 3, 4]);
+/// producer.join().unwrap();
+/// ```
+pub fn from_std<T>(receiver: mpsc::Receiver<T>) -> StdReceiver<T> {
+    StdReceiver {
+        receiver,
+        backoff: STD_POLL_MIN,
+        retry: None,
+    }
+}
+
+/// Receiver returned by [`from_std`]; a [`Stream`] of the values sent.
+pub struct StdReceiver<T> {
+    receiver: mpsc::Receiver<T>,
+    /// How long to wait after the next empty check.
+    backoff: Duration,
+    /// The pending wait before checking again.
+    retry: Option<Retry>,
+}
+
+/// A [`StdReceiver`]'s wait between checks.
+enum Retry {
+    Timer(Sleep),
+    /// A real-time sleep on the blocking pool, used while time is paused.
+    Thread(JoinHandle<()>),
+}
+
+impl Retry {
+    fn new(backoff: Duration) -> Self {
+        if crate::time_paused() {
+            Retry::Thread(crate::spawn_blocking(move || std::thread::sleep(backoff)))
+        } else {
+            Retry::Timer(sleep(backoff))
+        }
+    }
+}
+
+impl Future for Retry {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match self.get_mut() {
+            Retry::Timer(sleep) => Pin::new(sleep).poll(cx),
+            Retry::Thread(handle) => Pin::new(handle).poll(cx).map(drop),
+        }
+    }
+}
+
+impl<T> StdReceiver<T> {
+    /// Receives the next value, or `None` once every std sender is dropped
+    /// and the channel is drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        crate::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<T> Unpin for StdReceiver<T> {}
+
+impl<T> Stream for StdReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        ready!(coop::poll_proceed(cx));
+        loop {
+            match self.receiver.try_recv() {
+                Ok(value) => {
+                    self.backoff = STD_POLL_MIN;
+                    self.retry = None;
+                    return Poll::Ready(Some(value));
+                }
+                Err(mpsc::TryRecvError::Disconnected) => return Poll::Ready(None),
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+            let backoff = self.backoff;
+            let retry = self.retry.get_or_insert_with(|| Retry::new(backoff));
+            ready!(Pin::new(retry).poll(cx));
+            self.retry = None;
+            self.backoff = (backoff * 2).min(STD_POLL_MAX);
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a channel whose receiver was dropped")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        thread,
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, time};
+
+    #[test]
+    fn send_and_recv_interleave_across_tasks() {
+        let mut rt = MiniRuntime::new();
+        let (tx, mut rx) = unbounded();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let sends = log.clone();
+        rt.spawn(async move {
+            for n in 0..3 {
+                sends.borrow_mut().push(format!("send {n}"));
+                tx.send(n).unwrap();
+                crate::yield_now().await;
+            }
+        });
+        let recvs = log.clone();
+        rt.spawn(async move {
+            while let Some(n) = rx.recv().await {
+                recvs.borrow_mut().push(format!("recv {n}"));
+            }
+        });
+        rt.run_until_stalled();
+        assert_eq!(
+            *log.borrow(),
+            ["send 0", "recv 0", "send 1", "recv 1", "send 2", "recv 2"]
+        );
+        assert_eq!(rt.active_tasks(), 0);
+    }
+
+    #[test]
+    fn recv_drains_the_queue_then_returns_none_once_senders_are_gone() {
+        let mut rt = MiniRuntime::new();
+        let (tx, mut rx) = unbounded();
+        let other = tx.clone();
+        tx.send(1).unwrap();
+        other.send(2).unwrap();
+        drop(tx);
+        let received = rt.block_on(async move {
+            let waiter = Handle::current().spawn(async move {
+                let mut received = Vec::new();
+                while let Some(n) = rx.recv().await {
+                    received.push(n);
+                }
+                received
+            });
+            // Let the receiver park on the empty queue before the last
+            // sender goes away.
+            crate::yield_now().await;
+            crate::yield_now().await;
+            drop(other);
+            waiter.await.unwrap()
+        });
+        assert_eq!(received, [1, 2]);
+    }
+
+    #[test]
+    fn bounded_send_parks_until_the_receiver_makes_room() {
+        let mut rt = MiniRuntime::new();
+        let (tx, mut rx) = bounded(1);
+        let sent = Rc::new(Cell::new(0));
+        let received = Rc::new(Cell::new(0));
+
+        let counter = sent.clone();
+        let producer = rt.spawn(async move {
+            for n in 0..5 {
+                tx.send(n).await.unwrap();
+                counter.set(counter.get() + 1);
+            }
+        });
+        rt.run_until_stalled();
+        assert_eq!(sent.get(), 1, "the second send waits for room");
+        assert!(!producer.is_finished());
+
+        let (sent_seen, counter) = (sent.clone(), received.clone());
+        rt.spawn(async move {
+            while let Some(n) = rx.recv().await {
+                assert_eq!(n, counter.get());
+                counter.set(counter.get() + 1);
+                // Never more than the one buffered value ahead.
+                assert!(sent_seen.get() - counter.get() <= 1);
+            }
+        });
+        rt.block_on(producer).unwrap();
+        assert_eq!((sent.get(), received.get()), (5, 5));
+    }
+
+    #[test]
+    fn cancelled_send_does_not_swallow_the_wake_up() {
+        let mut rt = MiniRuntime::new();
+        let (tx, mut rx) = bounded(1);
+        let outcome = rt.block_on_timeout(
+            async move {
+                tx.send(0).await.unwrap();
+                let gave_up = time::timeout(Duration::from_millis(10), tx.send(1)).await;
+                assert!(gave_up.is_err());
+
+                let live = tx.clone();
+                let sender = Handle::current().spawn(async move { live.send(2).await });
+                crate::yield_now().await;
+                assert_eq!(rx.recv().await, Some(0));
+                assert_eq!(rx.recv().await, Some(2));
+                sender.await.unwrap().unwrap();
+            },
+            Duration::from_millis(500),
+        );
+        assert!(outcome.is_ok(), "the live sender was never woken");
+    }
+
+    #[test]
+    fn std_receiver_yields_a_threads_values_then_ends() {
+        let (tx, rx) = mpsc::channel();
+        let producer = thread::spawn(move || {
+            for n in 0..3 {
+                thread::sleep(Duration::from_millis(5));
+                tx.send(n).unwrap();
+            }
+        });
+        let mut rt = MiniRuntime::new();
+        let received = rt.block_on(async move {
+            let mut rx = from_std(rx);
+            let mut received = Vec::new();
+            while let Some(n) = rx.recv().await {
+                received.push(n);
+            }
+            received
+        });
+        assert_eq!(received, [0, 1, 2]);
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn std_receiver_waits_in_real_time_while_time_is_paused() {
+        let (tx, rx) = mpsc::channel();
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(40));
+            tx.send("late").unwrap();
+        });
+        let mut rt = MiniRuntime::new();
+        let start = Instant::now();
+        let (value, moved) = rt.block_on(async move {
+            time::pause();
+            let begin = time::now();
+            let value = from_std(rx).recv().await;
+            (value, time::now() - begin)
+        });
+        assert_eq!(value, Some("late"));
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        // Neither a clock racing ahead nor a spin: a handful of checks as
+        // the backoff grows.
+        assert_eq!(moved, Duration::ZERO);
+        assert!(rt.metrics().total_polls < 20, "{:?}", rt.metrics());
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+//! Cooperative scheduling: a budget of operations per task poll.
+//!
+//! A task whose channel always has another value, or whose stream never
+//! runs dry, would otherwise loop inside a single poll forever and starve
+//! every other task. Each poll by a runtime therefore starts with a fresh
+//! budget; channel and stream operations spend one unit each, and once the
+//! budget is gone they report `Pending` after asking for the task to be
+//! polled again, handing the thread back to the scheduler.
+
+use std::{
+    cell::Cell,
+    task::{Context, Poll},
+};
+
+/// Operations a task may complete per poll before it has to yield.
+const BUDGET: u8 = 128;
+
+thread_local! {
+    /// What is left of the budget of the task being polled, or `None`
+    /// outside of a task poll, where nothing is limited.
+    static REMAINING: Cell<Option<u8>> = const { Cell::new(None) };
+}
+
+/// Runs `poll` with a fresh budget, restoring the previous one afterwards.
+pub(crate) fn budget<R>(poll: impl FnOnce() -> R) -> R {
+    struct Restore(Option<u8>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REMAINING.with(|remaining| remaining.set(self.0));
+        }
+    }
+
+    let _restore = Restore(REMAINING.with(|remaining| remaining.replace(Some(BUDGET))));
+    poll()
+}
+
+/// Spends one unit of the current task's budget. Once it is used up, wakes
+/// the task and returns `Pending`, so the caller should return `Pending`
+/// too.
+pub(crate) fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    REMAINING.with(|remaining| match remaining.get() {
+        Some(0) => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Some(left) => {
+            remaining.set(Some(left - 1));
+            Poll::Ready(())
+        }
+        None => Poll::Ready(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, StreamExt, stream};
+
+    #[test]
+    fn an_always_ready_stream_lets_other_tasks_run() {
+        let mut rt = MiniRuntime::new();
+        let consumed = rt.block_on(async {
+            let stop = Rc::new(Cell::new(false));
+            let flag = stop.clone();
+            Handle::current().spawn(async move { flag.set(true) });
+            // Never pending by itself: only the budget hands the thread back.
+            let mut items = stream::iter(0..);
+            let mut consumed = 0;
+            while !stop.get() {
+                items.next().await;
+                consumed += 1;
+            }
+            consumed
+        });
+        // A budget's worth in the first poll, then the one that had to wait.
+        assert_eq!(consumed, usize::from(BUDGET) + 1);
+    }
+
+    #[test]
+    fn nothing_is_limited_outside_a_task_poll() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        for _ in 0..usize::from(BUDGET) * 2 {
+            assert!(poll_proceed(&mut cx).is_ready());
+        }
+    }
+}
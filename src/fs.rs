@@ -0,0 +1,69 @@
+//! Filesystem access that does not stall the executor.
+//!
+//! Regular files are always "ready" as far as `poll(2)` is concerned, so
+//! these functions run the matching `std::fs` call on the blocking pool
+//! instead and wait for it there.
+
+use std::{io, path::Path};
+
+use crate::blocking;
+
+/// Reads the whole file at `path`; see [`std::fs::read`].
+pub async fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let path = path.as_ref().to_owned();
+    blocking::run(move || std::fs::read(path)).await
+}
+
+/// Reads the whole file at `path` as UTF-8; see [`std::fs::read_to_string`].
+pub async fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    let path = path.as_ref().to_owned();
+    blocking::run(move || std::fs::read_to_string(path)).await
+}
+
+/// Writes `contents` to the file at `path`, replacing it if it exists; see
+/// [`std::fs::write`].
+pub async fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref().to_owned();
+    let contents = contents.as_ref().to_owned();
+    blocking::run(move || std::fs::write(path, contents)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::MiniRuntime;
+
+    /// A path in the temp directory unique to this process and `name`.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mini-rt-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn written_file_reads_back_the_same() {
+        let path = temp_path("round-trip");
+        let mut rt = MiniRuntime::new();
+        let target = path.clone();
+        let (bytes, text) = rt.block_on(async move {
+            write(&target, b"hello from the blocking pool\n")
+                .await
+                .unwrap();
+            (
+                read(&target).await.unwrap(),
+                read_to_string(&target).await.unwrap(),
+            )
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes, b"hello from the blocking pool\n");
+        assert_eq!(text, "hello from the blocking pool\n");
+    }
+
+    #[test]
+    fn reading_a_missing_file_fails() {
+        let path = temp_path("missing");
+        let mut rt = MiniRuntime::new();
+        let err = rt.block_on(async move { read(path).await.unwrap_err() });
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,618 @@
+//! Small building blocks for writing futures by hand, and combinators for
+//! existing ones.
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use crate::slab::{self, Slab};
+
+/// Creates a future that calls `f` each time it is polled, resolving once
+/// `f` returns `Ready`.
+///
+/// The future owns the closure, so state it captures by value lives across
+/// polls. For example, a countdown that is pending twice before it
+/// completes:
+///
+/// ```
+/// use std::task::Poll;
+/// use mini_rt::future::poll_fn;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let mut remaining = 2;
+/// let outcome = rt.block_on(poll_fn(move |cx| {
+///     if remaining == 0 {
+///         return Poll::Ready("liftoff");
+///     }
+///     remaining -= 1;
+///     // Nothing else will wake us, so ask to be polled again.
+///     cx.waker().wake_by_ref();
+///     Poll::Pending
+/// }));
+/// assert_eq!(outcome, "liftoff");
+/// ```
+pub fn poll_fn<T, F: FnMut(&mut Context<'_>) -> Poll<T>>(f: F) -> PollFn<F> {
+    PollFn(f)
+}
+
+/// Future returned by [`poll_fn`].
+pub struct PollFn<F>(F);
+
+impl<F> Unpin for PollFn<F> {}
+
+impl<T, F: FnMut(&mut Context<'_>) -> Poll<T>> Future for PollFn<F> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+/// Creates a future that resolves to `value` on its first poll.
+pub fn ready<T>(value: T) -> Ready<T> {
+    Ready(Some(value))
+}
+
+/// Future returned by [`ready`].
+pub struct Ready<T>(Option<T>);
+
+impl<T> Unpin for Ready<T> {}
+
+impl<T> Future for Ready<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<T> {
+        Poll::Ready(self.0.take().expect("Ready polled after completion"))
+    }
+}
+
+/// Creates a future that never resolves, such as a branch that should
+/// never win a `select!`.
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::{future, time};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// assert_eq!(rt.block_on(future::ready(5)), 5);
+/// let never = future::pending::<()>();
+/// let result = rt.block_on(time::timeout(Duration::from_millis(10), never));
+/// assert_eq!(result, Err(time::Elapsed));
+/// ```
+pub fn pending<T>() -> Pending<T> {
+    Pending(PhantomData)
+}
+
+/// Future returned by [`pending`].
+pub struct Pending<T>(PhantomData<fn() -> T>);
+
+impl<T> Future for Pending<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<T> {
+        Poll::Pending
+    }
+}
+
+/// Runs a runtime-sized set of futures concurrently: the dynamic analogue
+/// of [`join_all!`](crate::join_all). Resolves once all of them have
+/// finished, to their outputs in input order.
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::{future::join_all, sleep};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let delays = [50, 10, 40, 20, 30];
+/// let outputs = rt.block_on(join_all(delays.map(|ms| async move {
+///     sleep(Duration::from_millis(ms)).await;
+///     ms
+/// })));
+/// assert_eq!(outputs, delays);
+/// ```
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let futures: Vec<_> = futures.into_iter().map(|f| Some(Box::pin(f))).collect();
+    JoinAll {
+        outputs: futures.iter().map(|_| None).collect(),
+        futures,
+    }
+}
+
+/// Future returned by [`join_all`].
+pub struct JoinAll<F: Future> {
+    /// Each future until it finishes, and then its output in the slot at
+    /// the same index of `outputs`.
+    futures: Vec<Option<Pin<Box<F>>>>,
+    outputs: Vec<Option<F::Output>>,
+}
+
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut done = true;
+        for (slot, output) in this.futures.iter_mut().zip(&mut this.outputs) {
+            let Some(future) = slot else { continue };
+            match future.as_mut().poll(cx) {
+                Poll::Ready(value) => {
+                    *output = Some(value);
+                    *slot = None;
+                }
+                Poll::Pending => done = false,
+            }
+        }
+        if !done {
+            return Poll::Pending;
+        }
+        this.futures.clear();
+        Poll::Ready(this.outputs.drain(..).map(Option::unwrap).collect())
+    }
+}
+
+/// Like [`join_all`], but for futures yielding `Result<T, E>`: resolves to
+/// `Ok` with every `T` in input order, or to the first `Err` as soon as any
+/// future fails, dropping the rest unfinished. The dynamic analogue of
+/// [`try_join!`](crate::try_join).
+///
+/// ```
+/// use std::{cell::Cell, rc::Rc, time::Duration};
+/// use mini_rt::{future::try_join_all, sleep};
+///
+/// async fn fetch(ms: u64, finished: Rc<Cell<u32>>) -> Result<u64, &'static str> {
+///     sleep(Duration::from_millis(ms)).await;
+///     finished.set(finished.get() + 1);
+///     if ms == 0 { Err("bad request") } else { Ok(ms) }
+/// }
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let finished = Rc::new(Cell::new(0));
+/// let all = rt.block_on(try_join_all([20, 10].map(|ms| fetch(ms, finished.clone()))));
+/// assert_eq!(all, Ok(vec![20, 10]));
+///
+/// finished.set(0);
+/// let failed = rt.block_on(try_join_all([20, 0, 10].map(|ms| fetch(ms, finished.clone()))));
+/// assert_eq!(failed, Err("bad request"));
+/// assert_eq!(finished.get(), 1, "the slower futures were dropped");
+/// ```
+pub fn try_join_all<I, T, E>(futures: I) -> TryJoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = Result<T, E>>,
+{
+    TryJoinAll(join_all(futures))
+}
+
+/// Future returned by [`try_join_all`].
+pub struct TryJoinAll<F: Future>(JoinAll<F>);
+
+impl<F: Future> Unpin for TryJoinAll<F> {}
+
+impl<T, E, F: Future<Output = Result<T, E>>> Future for TryJoinAll<F> {
+    type Output = Result<Vec<T>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut self.0;
+        let mut done = true;
+        for (slot, output) in this.futures.iter_mut().zip(&mut this.outputs) {
+            let Some(future) = slot else { continue };
+            match future.as_mut().poll(cx) {
+                Poll::Ready(Err(error)) => {
+                    this.futures.clear();
+                    this.outputs.clear();
+                    return Poll::Ready(Err(error));
+                }
+                Poll::Ready(value) => {
+                    *output = Some(value);
+                    *slot = None;
+                }
+                Poll::Pending => done = false,
+            }
+        }
+        if !done {
+            return Poll::Pending;
+        }
+        this.futures.clear();
+        Poll::Ready(this.outputs.drain(..).map(Option::unwrap).collect())
+    }
+}
+
+/// Races a runtime-sized set of futures: the dynamic analogue of
+/// [`select!`](crate::select). Resolves to the output of the first to
+/// finish, its index, and the rest in their original order, still pending
+/// and ready to be raced again.
+///
+/// The futures are polled in index order, so if several are ready at once
+/// the lowest index wins.
+///
+/// # Panics
+///
+/// Panics if `futures` is empty, as nothing could ever win.
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::{future::select_all, sleep};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let sleeps = [30, 10, 20].map(|ms| sleep(Duration::from_millis(ms)));
+/// let ((), index, rest) = rt.block_on(select_all(sleeps));
+/// assert_eq!(index, 1);
+/// assert_eq!(rest.len(), 2);
+/// let ((), index, _) = rt.block_on(select_all(rest));
+/// assert_eq!(index, 1, "the 20ms sleep is second of what was left");
+/// ```
+pub fn select_all<I>(futures: I) -> SelectAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future + Unpin,
+{
+    let futures: Vec<_> = futures.into_iter().collect();
+    assert!(!futures.is_empty(), "select_all called with no futures");
+    SelectAll(futures)
+}
+
+/// Future returned by [`select_all`].
+pub struct SelectAll<F>(Vec<F>);
+
+impl<F> Unpin for SelectAll<F> {}
+
+impl<F: Future + Unpin> Future for SelectAll<F> {
+    type Output = (F::Output, usize, Vec<F>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some((index, output)) = self.0.iter_mut().enumerate().find_map(|(index, future)| {
+            match Pin::new(future).poll(cx) {
+                Poll::Ready(output) => Some((index, output)),
+                Poll::Pending => None,
+            }
+        }) else {
+            return Poll::Pending;
+        };
+        let mut rest = mem::take(&mut self.0);
+        rest.remove(index);
+        Poll::Ready((output, index, rest))
+    }
+}
+
+/// Extra combinators for every [`Future`].
+pub trait FutureExt: Future {
+    /// Wraps the future so that polling it after it has completed returns
+    /// `Pending` instead of breaking the future's contract.
+    ///
+    /// This lets a future stay a `select!` branch across loop iterations
+    /// after it has already won once:
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use mini_rt::{future::FutureExt, time};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// rt.block_on(async {
+    ///     let mut deadline = time::sleep(Duration::from_millis(5)).fuse();
+    ///     let mut ticks = time::interval(Duration::from_millis(1));
+    ///     let mut expired = false;
+    ///     for _ in 0..20 {
+    ///         mini_rt::select! {
+    ///             _ = &mut deadline => expired = true,
+    ///             _ = ticks.tick() => {}
+    ///         }
+    ///     }
+    ///     assert!(expired && deadline.is_terminated());
+    /// });
+    /// ```
+    fn fuse(self) -> Fuse<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        Fuse(Some(self))
+    }
+
+    /// Turns the future into one that can be cloned, with every clone
+    /// resolving to a clone of the same output. The future itself runs only
+    /// once, polled by whichever clone is polled, and wakes every waiting
+    /// clone when it changes.
+    ///
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    /// use mini_rt::{Handle, future::FutureExt};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let runs = Rc::new(Cell::new(0));
+    /// let counter = runs.clone();
+    /// let config = async move {
+    ///     counter.set(counter.get() + 1);
+    ///     mini_rt::yield_now().await;
+    ///     String::from("loaded")
+    /// }
+    /// .shared();
+    /// let outputs = rt.block_on(async move {
+    ///     let waiters: Vec<_> = (0..3)
+    ///         .map(|_| Handle::current().spawn(config.clone()))
+    ///         .collect();
+    ///     let mut outputs = Vec::new();
+    ///     for waiter in waiters {
+    ///         outputs.push(waiter.await.unwrap());
+    ///     }
+    ///     outputs
+    /// });
+    /// assert_eq!(outputs, ["loaded"; 3]);
+    /// assert_eq!(runs.get(), 1);
+    /// ```
+    fn shared(self) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Output: Clone,
+    {
+        Shared {
+            inner: Rc::new(SharedInner {
+                state: RefCell::new(SharedState::Pending(Box::pin(self))),
+                waiters: Arc::default(),
+            }),
+            key: None,
+        }
+    }
+}
+
+impl<F: Future + ?Sized> FutureExt for F {}
+
+/// Future returned by [`FutureExt::fuse`].
+pub struct Fuse<F>(Option<F>);
+
+impl<F> Fuse<F> {
+    /// Returns `true` once the inner future has completed.
+    pub fn is_terminated(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+impl<F: Future + Unpin> Future for Fuse<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let Some(future) = &mut self.0 else {
+            return Poll::Pending;
+        };
+        let output = Pin::new(future).poll(cx);
+        if output.is_ready() {
+            self.0 = None;
+        }
+        output
+    }
+}
+
+/// Future returned by [`FutureExt::shared`].
+pub struct Shared<F: Future> {
+    inner: Rc<SharedInner<F>>,
+    /// This clone's waker in `waiters`, once it has been polled.
+    key: Option<slab::Key>,
+}
+
+struct SharedInner<F: Future> {
+    state: RefCell<SharedState<F>>,
+    waiters: Arc<Waiters>,
+}
+
+enum SharedState<F: Future> {
+    Pending(Pin<Box<F>>),
+    Done(F::Output),
+}
+
+/// The wakers of every clone waiting on a [`Shared`]; the inner future is
+/// polled with a waker that wakes them all.
+#[derive(Default)]
+struct Waiters(Mutex<Slab<Waker>>);
+
+impl Wake for Waiters {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in self.0.lock().unwrap().iter() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl<F: Future> Clone for Shared<F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            key: None,
+        }
+    }
+}
+
+impl<F: Future> Unpin for Shared<F> {}
+
+impl<F: Future<Output: Clone>> Future for Shared<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let inner = self.inner.clone();
+        let mut state = inner.state.borrow_mut();
+        let future = match &mut *state {
+            SharedState::Done(output) => return Poll::Ready(output.clone()),
+            SharedState::Pending(future) => future,
+        };
+        {
+            let mut waiters = inner.waiters.0.lock().unwrap();
+            match self.key.and_then(|key| waiters.get_mut(key)) {
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => self.key = Some(waiters.insert(cx.waker().clone())),
+            }
+        }
+        let waker = Waker::from(inner.waiters.clone());
+        let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) else {
+            return Poll::Pending;
+        };
+        *state = SharedState::Done(output.clone());
+        drop(state);
+        // The other clones are still waiting to hear about it.
+        waker.wake();
+        Poll::Ready(output)
+    }
+}
+
+impl<F: Future> Drop for Shared<F> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.inner.waiters.0.lock().unwrap().remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, task::Waker, time::Duration};
+
+    use super::*;
+    use crate::{Handle, MiniRuntime};
+
+    #[test]
+    fn fused_future_stays_pending_after_completion() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut fused = ready(3).fuse();
+        assert!(!fused.is_terminated());
+        assert_eq!(Pin::new(&mut fused).poll(&mut cx), Poll::Ready(3));
+        assert!(fused.is_terminated());
+        for _ in 0..3 {
+            // `Ready` itself would panic if polled again.
+            assert!(Pin::new(&mut fused).poll(&mut cx).is_pending());
+        }
+    }
+
+    #[test]
+    fn shared_clones_in_many_tasks_run_the_inner_future_once() {
+        let mut rt = MiniRuntime::new();
+        let (started, finished) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+        let (start, finish) = (started.clone(), finished.clone());
+        let shared = async move {
+            start.set(start.get() + 1);
+            crate::sleep(Duration::from_millis(10)).await;
+            finish.set(finish.get() + 1);
+            7
+        }
+        .shared();
+        let outputs = rt.block_on(async move {
+            let waiters: Vec<_> = (0..4)
+                .map(|_| Handle::current().spawn(shared.clone()))
+                .collect();
+            let mut outputs = Vec::new();
+            for waiter in waiters {
+                outputs.push(waiter.await.unwrap());
+            }
+            // A clone first polled after completion gets the stored output.
+            outputs.push(shared.await);
+            outputs
+        });
+        assert_eq!(outputs, [7; 5]);
+        assert_eq!((started.get(), finished.get()), (1, 1));
+    }
+
+    #[test]
+    fn select_all_returns_the_winner_its_index_and_the_rest() {
+        let mut rt = MiniRuntime::new();
+        let (winner, index, rest, later) = rt.block_on(async {
+            crate::time::pause();
+            let racers: Vec<_> = [30, 10, 20, 40]
+                .into_iter()
+                .map(|ms| {
+                    Box::pin(async move {
+                        crate::sleep(Duration::from_millis(ms)).await;
+                        ms
+                    })
+                })
+                .collect();
+            let (winner, index, rest) = select_all(racers).await;
+            let left = rest.len();
+            // The losers were left mid-sleep, in their original order.
+            let later = join_all(rest).await;
+            (winner, index, left, later)
+        });
+        assert_eq!((winner, index, rest), (10, 1, 3));
+        assert_eq!(later, [30, 20, 40]);
+    }
+
+    #[test]
+    fn join_all_returns_outputs_in_input_order_not_finish_order() {
+        let mut rt = MiniRuntime::new();
+        let (outputs, finished, took) = rt.block_on(async {
+            crate::time::pause();
+            let start = crate::time::now();
+            let finished = RefCell::new(Vec::new());
+            let sleeps: Vec<_> = (1..=5)
+                .rev()
+                .map(|ms| {
+                    let finished = &finished;
+                    async move {
+                        crate::sleep(Duration::from_millis(ms * 10)).await;
+                        finished.borrow_mut().push(ms);
+                        ms
+                    }
+                })
+                .collect();
+            let outputs = join_all(sleeps).await;
+            (outputs, finished.take(), crate::time::now() - start)
+        });
+        assert_eq!(finished, [1, 2, 3, 4, 5]);
+        assert_eq!(outputs, [5, 4, 3, 2, 1]);
+        // Concurrent, so only as long as the longest.
+        assert_eq!(took, Duration::from_millis(50));
+    }
+
+    /// Sleeps `ms` milliseconds, then fails if `ms` is odd, recording in
+    /// `finished` that it got that far.
+    async fn even(ms: u64, finished: &RefCell<Vec<u64>>) -> Result<u64, u64> {
+        crate::sleep(Duration::from_millis(ms)).await;
+        finished.borrow_mut().push(ms);
+        if ms.is_multiple_of(2) {
+            Ok(ms)
+        } else {
+            Err(ms)
+        }
+    }
+
+    #[test]
+    fn try_join_all_collects_every_ok_in_input_order() {
+        let mut rt = MiniRuntime::new();
+        let (all, finished) = rt.block_on(async {
+            crate::time::pause();
+            let finished = RefCell::new(Vec::new());
+            let all = try_join_all([40, 20, 30].map(|ms| even(ms, &finished))).await;
+            (all, finished.take())
+        });
+        assert_eq!(all, Ok(vec![40, 20, 30]));
+        assert_eq!(finished, [20, 30, 40]);
+    }
+
+    #[test]
+    fn try_join_all_stops_at_the_first_err_and_drops_the_rest() {
+        let mut rt = MiniRuntime::new();
+        let (failed, took, finished) = rt.block_on(async {
+            crate::time::pause();
+            let start = crate::time::now();
+            let finished = RefCell::new(Vec::new());
+            let failed = try_join_all([40, 10, 25, 15].map(|ms| even(ms, &finished))).await;
+            (failed, crate::time::now() - start, finished.take())
+        });
+        assert_eq!(failed, Err(15));
+        assert_eq!(took, Duration::from_millis(15));
+        assert_eq!(finished, [10, 15]);
+        // The unfinished sleeps were dropped along with their timers.
+        assert_eq!(rt.pending_timers(), 0);
+    }
+}
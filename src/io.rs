@@ -0,0 +1,665 @@
+//! Traits for reading and writing byte streams asynchronously.
+//!
+//! [`AsyncRead`] and [`AsyncWrite`] are the poll-based building blocks that
+//! byte streams such as [`TcpStream`](crate::net::TcpStream) and
+//! [`DuplexStream`] implement, so code can be written once for any of them.
+//! [`AsyncReadExt`] and [`AsyncWriteExt`] add the async methods most code
+//! actually calls:
+//!
+//! ```
+//! use mini_rt::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+//!
+//! /// Copies everything from `from` into `to`, then closes `to`.
+//! async fn forward(
+//!     from: &mut (impl AsyncRead + Unpin),
+//!     to: &mut (impl AsyncWrite + Unpin),
+//! ) -> std::io::Result<()> {
+//!     let mut data = Vec::new();
+//!     from.read_to_end(&mut data).await?;
+//!     to.write_all(&data).await?;
+//!     to.shutdown().await
+//! }
+//!
+//! let mut rt = mini_rt::MiniRuntime::new();
+//! let received = rt.block_on(async {
+//!     // Small buffers, so writers have to wait for readers.
+//!     let (mut client, mut relay_in) = io::duplex(4);
+//!     let (mut relay_out, mut server) = io::duplex(4);
+//!     let relay = mini_rt::spawn_local(async move {
+//!         forward(&mut relay_in, &mut relay_out).await
+//!     });
+//!     let sender = mini_rt::spawn_local(async move {
+//!         client.write_all(b"hello through the relay").await?;
+//!         client.shutdown().await
+//!     });
+//!     let mut received = Vec::new();
+//!     server.read_to_end(&mut received).await.unwrap();
+//!     sender.await.unwrap().unwrap();
+//!     relay.await.unwrap().unwrap();
+//!     received
+//! });
+//! assert_eq!(received, b"hello through the relay");
+//! ```
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use crate::{Stream, future::poll_fn};
+
+mod buf_reader;
+mod buf_writer;
+mod duplex;
+
+pub use buf_reader::BufReader;
+pub use buf_writer::BufWriter;
+pub use duplex::{DuplexStream, duplex};
+
+/// How big a buffer [`BufReader`] and [`BufWriter`] get unless told
+/// otherwise.
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+/// A source of bytes that can be read without blocking the thread, the
+/// async analogue of `std::io::Read`.
+pub trait AsyncRead {
+    /// Attempts to read into `buf`, returning how many bytes were read, and
+    /// registers the task to be woken when more may be available if there
+    /// are none yet. `Ready(Ok(0))` means the end of the stream, unless
+    /// `buf` is empty.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>;
+}
+
+/// A sink for bytes that can be written without blocking the thread, the
+/// async analogue of `std::io::Write`.
+pub trait AsyncWrite {
+    /// Attempts to write from `buf`, returning how many bytes were written,
+    /// and registers the task to be woken when there may be room if there is
+    /// none yet.
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>;
+
+    /// Attempts to push any buffered bytes through to where they are going.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Attempts to flush and then close the writing side, so the reader
+    /// sees the end of the stream.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+/// An [`AsyncRead`] with an internal buffer, which can be read from without
+/// copying, such as a [`BufReader`].
+pub trait AsyncBufRead: AsyncRead {
+    /// Returns the buffered bytes, first refilling the buffer from the
+    /// stream if it is empty. An empty slice means the end of the stream.
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>>;
+
+    /// Marks the first `amount` bytes returned by
+    /// [`poll_fill_buf`](AsyncBufRead::poll_fill_buf) as read.
+    fn consume(self: Pin<&mut Self>, amount: usize);
+}
+
+/// Async methods for every [`AsyncRead`].
+pub trait AsyncReadExt: AsyncRead {
+    /// Reads some bytes into `buf`, returning how many were read. `Ok(0)`
+    /// means the end of the stream.
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>>
+    where
+        Self: Unpin,
+    {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_read(cx, buf))
+    }
+
+    /// Reads until the end of the stream, appending everything to `buf`,
+    /// and returns how many bytes were read.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> impl Future<Output = io::Result<usize>>
+    where
+        Self: Unpin,
+    {
+        async move {
+            let start = buf.len();
+            let mut chunk = [0; 4096];
+            loop {
+                match self.read(&mut chunk).await? {
+                    0 => return Ok(buf.len() - start),
+                    n => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + ?Sized> AsyncReadExt for R {}
+
+/// Async methods for every [`AsyncBufRead`], for line-oriented protocols.
+pub trait AsyncBufReadExt: AsyncBufRead {
+    /// Reads up to and including the next `byte`, or to the end of the
+    /// stream, appending what was read to `buf`. Returns how many bytes
+    /// that was; `Ok(0)` means the stream had already ended.
+    ///
+    /// Dropping the future keeps every byte read so far in `buf`.
+    fn read_until<'a>(
+        &'a mut self,
+        byte: u8,
+        buf: &'a mut Vec<u8>,
+    ) -> impl Future<Output = io::Result<usize>>
+    where
+        Self: Unpin,
+    {
+        let mut read = 0;
+        poll_fn(move |cx| poll_read_until(Pin::new(&mut *self), cx, byte, buf, &mut read))
+    }
+
+    /// Reads up to and including the next `\n`, or to the end of the
+    /// stream, and appends it to `line`. Returns how many bytes were read;
+    /// `Ok(0)` means the stream had already ended.
+    ///
+    /// Text that is not UTF-8 fails with `InvalidData`, leaving `line` as it
+    /// was. Dropping the future loses whatever part of the line it had
+    /// read.
+    fn read_line<'a>(&'a mut self, line: &'a mut String) -> impl Future<Output = io::Result<usize>>
+    where
+        Self: Unpin,
+    {
+        async move {
+            let mut bytes = Vec::new();
+            let read = self.read_until(b'\n', &mut bytes).await?;
+            line.push_str(&into_string(bytes)?);
+            Ok(read)
+        }
+    }
+
+    /// Turns the reader into a [`Stream`] of its lines, without their
+    /// `\n` or `\r\n` endings. A line read only partly when the stream is
+    /// not ready is picked up where it left off on the next poll.
+    fn lines(self) -> Lines<Self>
+    where
+        Self: Sized,
+    {
+        Lines {
+            reader: self,
+            line: Vec::new(),
+            read: 0,
+        }
+    }
+}
+
+impl<R: AsyncBufRead + ?Sized> AsyncBufReadExt for R {}
+
+/// Reads from `reader` until the end of the stream, writing everything to
+/// `writer` through a reusable buffer, then flushes `writer`. Returns how
+/// many bytes were copied.
+///
+/// ```
+/// use mini_rt::io::{self, AsyncReadExt, AsyncWriteExt};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     let data = b"bytes to be piped along".repeat(4);
+///     let (mut source, mut reader) = io::duplex(data.len());
+///     source.write_all(&data).await.unwrap();
+///     drop(source);
+///
+///     let (mut writer, mut sink) = io::duplex(data.len());
+///     let copied = io::copy(&mut reader, &mut writer).await.unwrap();
+///     assert_eq!(copied, data.len() as u64);
+///     drop(writer);
+///
+///     let mut received = Vec::new();
+///     sink.read_to_end(&mut received).await.unwrap();
+///     assert_eq!(received, data);
+/// });
+/// ```
+pub async fn copy<R, W>(reader: &mut R, writer: &mut W) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = vec![0; DEFAULT_BUF_SIZE];
+    let mut copied = 0;
+    loop {
+        match reader.read(&mut buf).await? {
+            0 => break,
+            n => {
+                writer.write_all(&buf[..n]).await?;
+                copied += n as u64;
+            }
+        }
+    }
+    writer.flush().await?;
+    Ok(copied)
+}
+
+/// Moves bytes from `reader`'s buffer into `buf` until one of them is
+/// `byte` or the stream ends, counting them in `read` so a call that
+/// returns `Pending` can be resumed.
+fn poll_read_until<R: AsyncBufRead + ?Sized>(
+    mut reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    byte: u8,
+    buf: &mut Vec<u8>,
+    read: &mut usize,
+) -> Poll<io::Result<usize>> {
+    loop {
+        let available = ready!(reader.as_mut().poll_fill_buf(cx))?;
+        let (done, used) = match available.iter().position(|&b| b == byte) {
+            Some(index) => (true, index + 1),
+            None => (available.is_empty(), available.len()),
+        };
+        buf.extend_from_slice(&available[..used]);
+        reader.as_mut().consume(used);
+        *read += used;
+        if done {
+            return Poll::Ready(Ok(std::mem::take(read)));
+        }
+    }
+}
+
+fn into_string(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })
+}
+
+/// Stream returned by [`AsyncBufReadExt::lines`].
+pub struct Lines<R> {
+    reader: R,
+    /// The line being read.
+    line: Vec<u8>,
+    read: usize,
+}
+
+impl<R> Lines<R> {
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for Lines<R> {
+    type Item = io::Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let read = ready!(poll_read_until(
+            Pin::new(&mut this.reader),
+            cx,
+            b'\n',
+            &mut this.line,
+            &mut this.read,
+        ));
+        match read {
+            Ok(0) => Poll::Ready(None),
+            Ok(_) => {
+                let mut line = std::mem::take(&mut this.line);
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                }
+                Poll::Ready(Some(into_string(line)))
+            }
+            Err(err) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
+/// Async methods for every [`AsyncWrite`].
+pub trait AsyncWriteExt: AsyncWrite {
+    /// Writes some bytes from `buf`, returning how many were written.
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = io::Result<usize>>
+    where
+        Self: Unpin,
+    {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_write(cx, buf))
+    }
+
+    /// Writes all of `buf`, waiting for room as often as needed.
+    fn write_all(&mut self, mut buf: &[u8]) -> impl Future<Output = io::Result<()>>
+    where
+        Self: Unpin,
+    {
+        async move {
+            while !buf.is_empty() {
+                match self.write(buf).await? {
+                    0 => return Err(io::ErrorKind::WriteZero.into()),
+                    n => buf = &buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = io::Result<()>>
+    where
+        Self: Unpin,
+    {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_flush(cx))
+    }
+
+    /// Flushes and closes the writing side; see
+    /// [`poll_shutdown`](AsyncWrite::poll_shutdown).
+    fn shutdown(&mut self) -> impl Future<Output = io::Result<()>>
+    where
+        Self: Unpin,
+    {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_shutdown(cx))
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWriteExt for W {}
+
+impl<R: AsyncRead + Unpin + ?Sized> AsyncRead for &mut R {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> AsyncRead for Box<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> AsyncBufRead for &mut R {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut **self.get_mut()).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amount: usize) {
+        Pin::new(&mut **self).consume(amount)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> AsyncBufRead for Box<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut **self.get_mut()).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amount: usize) {
+        Pin::new(&mut **self).consume(amount)
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWrite for &mut W {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWrite for Box<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::VecDeque,
+        rc::Rc,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        task::{Wake, Waker},
+    };
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, yield_now};
+
+    /// Records whether it has been woken.
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// A hand-fed byte stream: reads wait for [`feed`](Fake::feed), writes
+    /// wait for [`grant`](Fake::grant), and either wakes the task parked on
+    /// the other end.
+    #[derive(Clone, Default)]
+    struct Fake(Rc<RefCell<FakeState>>);
+
+    #[derive(Default)]
+    struct FakeState {
+        incoming: VecDeque<u8>,
+        eof: bool,
+        written: Vec<u8>,
+        room: usize,
+        flushed: bool,
+        shut_down: bool,
+        reader: Option<Waker>,
+        writer: Option<Waker>,
+    }
+
+    impl Fake {
+        fn feed(&self, bytes: &[u8]) {
+            let mut state = self.0.borrow_mut();
+            state.incoming.extend(bytes);
+            if let Some(waker) = state.reader.take() {
+                waker.wake();
+            }
+        }
+
+        fn close(&self) {
+            let mut state = self.0.borrow_mut();
+            state.eof = true;
+            if let Some(waker) = state.reader.take() {
+                waker.wake();
+            }
+        }
+
+        fn grant(&self, room: usize) {
+            let mut state = self.0.borrow_mut();
+            state.room += room;
+            if let Some(waker) = state.writer.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl AsyncRead for Fake {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut state = self.0.borrow_mut();
+            if state.incoming.is_empty() && !state.eof {
+                state.reader = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let n = buf.len().min(state.incoming.len());
+            for (slot, byte) in buf.iter_mut().zip(state.incoming.drain(..n)) {
+                *slot = byte;
+            }
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    impl AsyncWrite for Fake {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut state = self.0.borrow_mut();
+            if state.room == 0 {
+                state.writer = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let n = buf.len().min(state.room);
+            state.room -= n;
+            state.written.extend(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.0.borrow_mut().flushed = true;
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.0.borrow_mut().shut_down = true;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn a_pending_read_is_woken_and_then_completes() {
+        let mut fake = Fake::default();
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut buf = [0; 8];
+        assert!(
+            Pin::new(&mut fake)
+                .poll_read(&mut cx, &mut buf)
+                .is_pending()
+        );
+        assert!(!flag.0.load(Ordering::SeqCst));
+
+        fake.feed(b"hi");
+        assert!(flag.0.load(Ordering::SeqCst));
+        let read = Pin::new(&mut fake).poll_read(&mut cx, &mut buf);
+        assert!(matches!(read, Poll::Ready(Ok(2))));
+        assert_eq!(&buf[..2], b"hi");
+    }
+
+    #[test]
+    fn a_pending_write_is_woken_and_then_completes() {
+        let mut fake = Fake::default();
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut fake).poll_write(&mut cx, b"abc").is_pending());
+
+        fake.grant(2);
+        assert!(flag.0.load(Ordering::SeqCst));
+        let written = Pin::new(&mut fake).poll_write(&mut cx, b"abc");
+        assert!(matches!(written, Poll::Ready(Ok(2))));
+        assert_eq!(fake.0.borrow().written, b"ab");
+    }
+
+    #[test]
+    fn read_and_read_to_end_wait_out_a_slow_source() {
+        let mut rt = MiniRuntime::new();
+        let (first, rest) = rt.block_on(async {
+            let mut reader = Fake::default();
+            let feeder = reader.clone();
+            Handle::current().spawn(async move {
+                for chunk in [&b"one "[..], b"two ", b"three"] {
+                    yield_now().await;
+                    feeder.feed(chunk);
+                }
+                feeder.close();
+            });
+            let mut first = [0; 16];
+            let n = reader.read(&mut first).await.unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).await.unwrap();
+            (first[..n].to_vec(), rest)
+        });
+        assert_eq!(first, b"one ");
+        assert_eq!(rest, b"two three");
+    }
+
+    #[test]
+    fn write_all_keeps_writing_as_room_is_granted() {
+        let mut rt = MiniRuntime::new();
+        let writer = Fake::default();
+        let state = writer.clone();
+        rt.block_on(async move {
+            let granter = writer.clone();
+            Handle::current().spawn(async move {
+                for _ in 0..4 {
+                    yield_now().await;
+                    granter.grant(3);
+                }
+            });
+            let mut writer = writer;
+            writer.write_all(b"hello world").await.unwrap();
+            writer.flush().await.unwrap();
+            writer.shutdown().await.unwrap();
+        });
+        let state = state.0.borrow();
+        assert_eq!(state.written, b"hello world");
+        assert!(state.flushed && state.shut_down);
+    }
+
+    #[test]
+    fn copy_streams_several_buffers_worth_through_duplex_pipes() {
+        let mut rt = MiniRuntime::new();
+        let data: Vec<u8> = (0..3 * DEFAULT_BUF_SIZE + 123).map(|n| n as u8).collect();
+        let expected = data.clone();
+        let (copied, received) = rt.block_on(async move {
+            // Pipes much smaller than the data, so every stage has to wait
+            // on the next one.
+            let (mut source, mut reader) = duplex(1024);
+            let (mut writer, mut sink) = duplex(1024);
+            let producer = Handle::current().spawn(async move {
+                source.write_all(&data).await.unwrap();
+            });
+            let consumer = Handle::current().spawn(async move {
+                let mut received = Vec::new();
+                sink.read_to_end(&mut received).await.unwrap();
+                received
+            });
+            let copied = copy(&mut reader, &mut writer).await.unwrap();
+            drop(writer);
+            producer.await.unwrap();
+            (copied, consumer.await.unwrap())
+        });
+        assert_eq!(copied, expected.len() as u64);
+        assert_eq!(received, expected);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use super::{AsyncBufRead, AsyncRead, DEFAULT_BUF_SIZE};
+
+/// Adds a buffer to an [`AsyncRead`], so that many small reads, such as
+/// those of [`read_line`](super::AsyncBufReadExt::read_line), become a few
+/// large ones on the stream underneath.
+///
+/// ```
+/// use mini_rt::{
+///     StreamExt,
+///     io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
+/// };
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let lines = rt.block_on(async {
+///     let (mut writer, reader) = io::duplex(64);
+///     let sender = mini_rt::spawn_local(async move {
+///         // Lines split across writes, and writes holding several lines.
+///         for piece in ["hel", "lo\nwor", "ld\r\n\nsecond to ", "last\nlast"] {
+///             writer.write_all(piece.as_bytes()).await?;
+///             mini_rt::yield_now().await;
+///         }
+///         writer.shutdown().await
+///     });
+///     let mut lines = BufReader::new(reader).lines();
+///     let mut received = Vec::new();
+///     while let Some(line) = lines.next().await {
+///         received.push(line.unwrap());
+///     }
+///     sender.await.unwrap().unwrap();
+///     received
+/// });
+/// assert_eq!(lines, ["hello", "world", "", "second to last", "last"]);
+/// ```
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    /// The unread bytes are `buf[pos..filled]`.
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: AsyncRead> BufReader<R> {
+    /// Wraps `inner` with an 8 KiB buffer.
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Wraps `inner` with a buffer of `capacity` bytes.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+}
+
+impl<R> BufReader<R> {
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Reading from the stream directly skips whatever is buffered.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the bytes read from the stream but not yet from the buffer.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Unwraps the stream, discarding whatever is buffered.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BufReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // Reads at least as big as the buffer gain nothing from it.
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for BufReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos == this.filled {
+            this.filled = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut this.buf))?;
+            this.pos = 0;
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amount: usize) {
+        self.pos = (self.pos + amount).min(self.filled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MiniRuntime, StreamExt,
+        io::{AsyncBufReadExt, AsyncWriteExt, DuplexStream, duplex},
+    };
+
+    /// Writes each of `pieces` to a duplex in turn, yielding in between,
+    /// and returns what `read` makes of the other end.
+    fn read_pieces<T: 'static>(
+        pieces: &'static [&'static str],
+        read: impl AsyncFnOnce(BufReader<DuplexStream>) -> T + 'static,
+    ) -> T {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async move {
+            let (mut writer, reader) = duplex(64);
+            let sender = crate::spawn_local(async move {
+                for piece in pieces {
+                    writer.write_all(piece.as_bytes()).await.unwrap();
+                    crate::yield_now().await;
+                }
+                writer.shutdown().await.unwrap();
+            });
+            // A small buffer, so lines also straddle refills.
+            let output = read(BufReader::with_capacity(4, reader)).await;
+            sender.await.unwrap();
+            output
+        })
+    }
+
+    #[test]
+    fn read_line_joins_a_line_split_across_reads() {
+        let lines = read_pieces(&["he", "llo wo", "rld\nbye\n"], async |mut reader| {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    return lines;
+                }
+                lines.push(line);
+            }
+        });
+        assert_eq!(lines, ["hello world\n", "bye\n"]);
+    }
+
+    #[test]
+    fn read_line_keeps_crlf_and_lines_strips_it() {
+        let kept = read_pieces(&["one\r", "\ntwo\r\n"], async |mut reader| {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            line
+        });
+        assert_eq!(kept, "one\r\n");
+        let stripped = read_pieces(&["one\r", "\ntwo\r\n", "\r\n"], async |reader| {
+            reader.lines().map(Result::unwrap).collect::<Vec<_>>().await
+        });
+        assert_eq!(stripped, ["one", "two", ""]);
+    }
+
+    #[test]
+    fn a_final_line_without_a_newline_is_still_read() {
+        let (line, at_end) = read_pieces(&["first\nla", "st"], async |mut reader| {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            line.clear();
+            let read = reader.read_line(&mut line).await.unwrap();
+            assert_eq!(read, 4);
+            (line, reader.read_line(&mut String::new()).await.unwrap())
+        });
+        assert_eq!(line, "last");
+        assert_eq!(at_end, 0);
+    }
+}
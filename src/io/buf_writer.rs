@@ -0,0 +1,176 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use super::{AsyncWrite, DEFAULT_BUF_SIZE};
+
+/// Adds a buffer to an [`AsyncWrite`], so that many small writes become a
+/// few large ones on the stream underneath.
+///
+/// Buffered bytes reach the stream once the buffer fills up, or on
+/// [`flush`](super::AsyncWriteExt::flush) or
+/// [`shutdown`](super::AsyncWriteExt::shutdown). Dropping the writer
+/// without either loses them.
+///
+/// ```
+/// use mini_rt::io::{self, AsyncReadExt, AsyncWriteExt, BufWriter};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     let (writer, mut reader) = io::duplex(64);
+///     let mut writer = BufWriter::new(writer);
+///     for word in ["one ", "two ", "three"] {
+///         writer.write_all(word.as_bytes()).await.unwrap();
+///     }
+///     assert_eq!(writer.buffer(), b"one two three");
+///     writer.shutdown().await.unwrap();
+///
+///     let mut received = Vec::new();
+///     reader.read_to_end(&mut received).await.unwrap();
+///     assert_eq!(received, b"one two three");
+/// });
+/// ```
+pub struct BufWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: AsyncWrite> BufWriter<W> {
+    /// Wraps `inner` with an 8 KiB buffer.
+    pub fn new(inner: W) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Wraps `inner` with a buffer of `capacity` bytes.
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+}
+
+impl<W> BufWriter<W> {
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writing to the stream directly jumps ahead of whatever is buffered.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the bytes written to the buffer but not yet to the stream.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Unwraps the stream, discarding whatever is buffered.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BufWriter<W> {
+    /// Writes out the whole buffer, keeping what is left of it when the
+    /// stream is not ready.
+    fn poll_flush_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buf.is_empty() {
+            match ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => drop(self.buf.drain(..n)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BufWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.buf.len() + buf.len() > this.buf.capacity() {
+            ready!(this.poll_flush_buf(cx))?;
+        }
+        // Writes at least as big as the buffer gain nothing from it.
+        if buf.len() >= this.buf.capacity() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        this.buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_flush_buf(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_flush_buf(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MiniRuntime, future,
+        io::{AsyncRead, AsyncReadExt, AsyncWriteExt, DuplexStream, duplex},
+    };
+
+    /// Returns what the reader has available right now, without waiting.
+    async fn available(reader: &mut DuplexStream) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let read =
+            future::poll_fn(|cx| Poll::Ready(Pin::new(&mut *reader).poll_read(cx, &mut buf))).await;
+        match read {
+            Poll::Ready(read) => buf[..read.unwrap()].to_vec(),
+            Poll::Pending => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn small_writes_wait_in_the_buffer_until_a_flush() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let (writer, mut reader) = duplex(64);
+            let mut writer = BufWriter::with_capacity(16, writer);
+            writer.write_all(b"ab").await.unwrap();
+            writer.write_all(b"cd").await.unwrap();
+            assert_eq!(available(&mut reader).await, b"");
+            assert_eq!(writer.buffer(), b"abcd");
+            writer.flush().await.unwrap();
+            assert!(writer.buffer().is_empty());
+            assert_eq!(available(&mut reader).await, b"abcd");
+        });
+    }
+
+    #[test]
+    fn a_full_buffer_is_written_out_before_taking_more() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let (writer, mut reader) = duplex(64);
+            let mut writer = BufWriter::with_capacity(4, writer);
+            writer.write_all(b"ab").await.unwrap();
+            writer.write_all(b"cde").await.unwrap();
+            assert_eq!(available(&mut reader).await, b"ab");
+            assert_eq!(writer.buffer(), b"cde");
+            // Writes as big as the buffer go straight through, after what
+            // was buffered ahead of them.
+            writer.write_all(b"long write").await.unwrap();
+            assert!(writer.buffer().is_empty());
+            writer.shutdown().await.unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).await.unwrap();
+            assert_eq!(rest, b"cdelong write");
+        });
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker, ready},
+};
+
+use super::{AsyncRead, AsyncWrite};
+use crate::coop;
+
+/// Creates a pair of connected in-memory byte streams: what is written to
+/// one can be read from the other, in both directions, like the two ends of
+/// a socket. Each direction buffers at most `max_buf_size` bytes, after
+/// which writers wait for the reader to catch up.
+///
+/// Shutting one end down, or dropping it, ends the stream its peer reads;
+/// writing to a dropped peer fails with `BrokenPipe`.
+///
+/// ```
+/// use std::{future::Future, pin::pin, task::Poll};
+/// use mini_rt::{
+///     future::poll_fn,
+///     io::{self, AsyncReadExt, AsyncWriteExt},
+/// };
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     let (mut a, mut b) = io::duplex(4);
+///     a.write_all(b"ping").await.unwrap();
+///     let mut buf = [0; 8];
+///     assert_eq!(b.read(&mut buf).await.unwrap(), 4);
+///     assert_eq!(&buf[..4], b"ping");
+///
+///     // A full buffer holds writers back until the reader makes room.
+///     assert_eq!(a.write(b"abcdef").await.unwrap(), 4);
+///     {
+///         let mut blocked = pin!(a.write(b"ef"));
+///         let polled = poll_fn(|cx| Poll::Ready(blocked.as_mut().poll(cx))).await;
+///         assert!(polled.is_pending());
+///         assert_eq!(b.read(&mut buf[..2]).await.unwrap(), 2);
+///         assert_eq!(blocked.await.unwrap(), 2);
+///     }
+///
+///     // Once a half is gone, its peer drains what is left and then sees EOF.
+///     drop(a);
+///     let mut rest = Vec::new();
+///     b.read_to_end(&mut rest).await.unwrap();
+///     assert_eq!(rest, b"cdef");
+///     assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if `max_buf_size` is zero.
+pub fn duplex(max_buf_size: usize) -> (DuplexStream, DuplexStream) {
+    assert!(max_buf_size > 0, "duplex needs room for at least one byte");
+    let pipe = || {
+        Arc::new(Mutex::new(Pipe {
+            buffer: VecDeque::new(),
+            max_buf_size,
+            closed: false,
+            read_waker: None,
+            write_waker: None,
+        }))
+    };
+    let (there, back) = (pipe(), pipe());
+    (
+        DuplexStream {
+            read: back.clone(),
+            write: there.clone(),
+        },
+        DuplexStream {
+            read: there,
+            write: back,
+        },
+    )
+}
+
+/// One end of a [`duplex`] pipe.
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// The bytes in flight in one direction.
+struct Pipe {
+    buffer: VecDeque<u8>,
+    max_buf_size: usize,
+    /// Set once the writer shuts down or either end is dropped.
+    closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn close(&mut self) {
+        self.closed = true;
+        wake(&mut self.read_waker);
+        wake(&mut self.write_waker);
+    }
+}
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(coop::poll_proceed(cx));
+        let mut pipe = self.read.lock().unwrap();
+        if pipe.buffer.is_empty() && !pipe.closed && !buf.is_empty() {
+            pipe.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.len().min(pipe.buffer.len());
+        for (slot, byte) in buf.iter_mut().zip(pipe.buffer.drain(..n)) {
+            *slot = byte;
+        }
+        if n > 0 {
+            wake(&mut pipe.write_waker);
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(coop::poll_proceed(cx));
+        let mut pipe = self.write.lock().unwrap();
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let room = pipe.max_buf_size - pipe.buffer.len();
+        if room == 0 && !buf.is_empty() {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.len().min(room);
+        pipe.buffer.extend(&buf[..n]);
+        if n > 0 {
+            wake(&mut pipe.read_waker);
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    /// Written bytes are readable straight away, so there is nothing to
+    /// flush.
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.read.lock().unwrap().close();
+        self.write.lock().unwrap().close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        task::Wake,
+    };
+
+    use super::*;
+    use crate::{
+        Handle, MiniRuntime,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    /// Records whether it has been woken.
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn bytes_written_to_one_end_are_read_from_the_other() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let (mut a, mut b) = duplex(64);
+            a.write_all(b"ping").await.unwrap();
+            b.write_all(b"pong").await.unwrap();
+            let mut buf = [0; 8];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 4);
+            assert_eq!(&buf[..4], b"ping");
+            assert_eq!(a.read(&mut buf).await.unwrap(), 4);
+            assert_eq!(&buf[..4], b"pong");
+        });
+    }
+
+    #[test]
+    fn dropping_a_peer_ends_the_stream_after_the_buffered_bytes() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let (mut a, mut b) = duplex(64);
+            a.write_all(b"last words").await.unwrap();
+            drop(a);
+            let mut rest = Vec::new();
+            assert_eq!(b.read_to_end(&mut rest).await.unwrap(), 10);
+            assert_eq!(rest, b"last words");
+            let mut buf = [0; 4];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+            let err = b.write(b"anyone?").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    #[test]
+    fn a_full_pipe_parks_the_writer_until_the_reader_makes_room() {
+        let (mut a, mut b) = duplex(4);
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let poll_write =
+            |a: &mut DuplexStream, cx: &mut Context<'_>| Pin::new(a).poll_write(cx, b"abcdef");
+        assert!(matches!(poll_write(&mut a, &mut cx), Poll::Ready(Ok(4))));
+        assert!(poll_write(&mut a, &mut cx).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+
+        let mut buf = [0; 3];
+        let read = Pin::new(&mut b).poll_read(&mut Context::from_waker(Waker::noop()), &mut buf);
+        assert!(matches!(read, Poll::Ready(Ok(3))));
+        assert!(
+            flag.0.load(Ordering::SeqCst),
+            "the reader did not wake the writer"
+        );
+        assert!(matches!(poll_write(&mut a, &mut cx), Poll::Ready(Ok(3))));
+    }
+
+    #[test]
+    fn write_all_larger_than_the_buffer_completes_as_the_reader_drains() {
+        let mut rt = MiniRuntime::new();
+        let received = rt.block_on(async {
+            let (mut a, mut b) = duplex(4);
+            let writer = Handle::current().spawn(async move {
+                a.write_all(&[7; 32]).await.unwrap();
+            });
+            let mut received = Vec::new();
+            b.read_to_end(&mut received).await.unwrap();
+            writer.await.unwrap();
+            received
+        });
+        assert_eq!(received, [7; 32]);
+    }
+}
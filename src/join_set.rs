@@ -0,0 +1,173 @@
+//! A group of spawned tasks awaited as they finish.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use crate::{AbortHandle, FuturesUnordered, Handle, JoinError, JoinHandle, StreamExt, TaskId};
+
+/// A set of tasks spawned on the current runtime, whose results are
+/// collected in completion order.
+///
+/// ```
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let total = rt.block_on(async {
+///     let mut set = mini_rt::JoinSet::new();
+///     for n in 1..=5 {
+///         set.spawn(async move { n * 10 });
+///     }
+///     let mut total = 0;
+///     while let Some(result) = set.join_next().await {
+///         total += result.unwrap();
+///     }
+///     total
+/// });
+/// assert_eq!(total, 150);
+/// ```
+///
+/// Dropping the set aborts every task still in it.
+pub struct JoinSet<T> {
+    handles: FuturesUnordered<Tagged<T>>,
+    aborts: HashMap<TaskId, AbortHandle>,
+}
+
+/// A `JoinHandle` that also reports which task it belonged to.
+struct Tagged<T>(JoinHandle<T>);
+
+impl<T> Future for Tagged<T> {
+    type Output = (TaskId, Result<T, JoinError>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let id = self.0.id();
+        let output = ready!(Pin::new(&mut self.0).poll(cx));
+        Poll::Ready((id, output))
+    }
+}
+
+impl<T: 'static> JoinSet<T> {
+    pub fn new() -> Self {
+        Self {
+            handles: FuturesUnordered::new(),
+            aborts: HashMap::new(),
+        }
+    }
+
+    /// Spawns `future` on the current runtime and adds it to the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a runtime.
+    pub fn spawn<F>(&mut self, future: F) -> AbortHandle
+    where
+        F: Future<Output = T> + 'static,
+    {
+        let handle = Handle::current().spawn(future);
+        let abort = handle.abort_handle();
+        self.aborts.insert(handle.id(), abort.clone());
+        self.handles.push(Tagged(handle));
+        abort
+    }
+
+    /// Waits for the next task to finish and returns its result, or `None`
+    /// once the set is empty.
+    pub async fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+        let (id, output) = self.handles.next().await?;
+        self.aborts.remove(&id);
+        Some(output)
+    }
+
+    /// Aborts every task in the set. They stay in it until collected,
+    /// resolving to `Err(JoinError::Cancelled)` unless they had already
+    /// finished.
+    pub fn abort_all(&self) {
+        for abort in self.aborts.values() {
+            abort.abort();
+        }
+    }
+
+    /// Returns the number of tasks not yet collected by
+    /// [`join_next`](JoinSet::join_next).
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl<T: 'static> Default for JoinSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for JoinSet<T> {
+    fn drop(&mut self) {
+        for abort in self.aborts.values() {
+            abort.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{MiniRuntime, sleep, yield_now};
+
+    #[test]
+    fn join_next_on_an_empty_set_is_none() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let mut set = JoinSet::<u8>::new();
+            assert!(set.join_next().await.is_none());
+            set.spawn(async { 1 });
+            assert_eq!(set.join_next().await.unwrap().unwrap(), 1);
+            assert!(set.is_empty());
+            assert!(set.join_next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn abort_all_cancels_unfinished_tasks_but_keeps_finished_results() {
+        let mut rt = MiniRuntime::new();
+        let woke = Rc::new(Cell::new(false));
+        let flag = woke.clone();
+        rt.block_on(async move {
+            let mut set = JoinSet::new();
+            set.spawn(async { "done" });
+            for _ in 0..3 {
+                let flag = flag.clone();
+                set.spawn(async move {
+                    sleep(Duration::from_secs(60)).await;
+                    flag.set(true);
+                    "slept"
+                });
+            }
+            // Lets the quick task finish and the sleepers park.
+            yield_now().await;
+            set.abort_all();
+            assert_eq!(set.len(), 4);
+
+            let (mut finished, mut cancelled) = (Vec::new(), 0);
+            while let Some(result) = set.join_next().await {
+                match result {
+                    Ok(output) => finished.push(output),
+                    Err(err) => {
+                        assert!(err.is_cancelled());
+                        cancelled += 1;
+                    }
+                }
+            }
+            assert_eq!(finished, ["done"]);
+            assert_eq!(cancelled, 3);
+        });
+        assert!(!woke.get());
+        assert_eq!(rt.active_tasks(), 0);
+    }
+}
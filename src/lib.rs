@@ -0,0 +1,2033 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    pin::{Pin, pin},
+    rc::{Rc, Weak},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+/// Emits a trace-level `tracing` event with the `tracing` feature, and
+/// nothing at all without it.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*)
+    };
+}
+
+use blocking::BlockingPool;
+use slab::Slab;
+use time::{Clock, RuntimeClock, TimerEntry, Timers};
+
+mod blocking;
+pub mod broadcast;
+mod builder;
+pub mod channel;
+mod coop;
+pub mod fs;
+pub mod future;
+pub mod io;
+mod join_set;
+mod local_set;
+mod metrics;
+pub mod multi_thread;
+#[cfg(unix)]
+pub mod net;
+pub mod oneshot;
+#[cfg(unix)]
+mod reactor;
+mod scope;
+#[cfg(unix)]
+pub mod signal;
+mod slab;
+pub mod stream;
+pub mod sync;
+pub mod time;
+pub mod watch;
+
+#[cfg(test)]
+mod tests;
+
+pub use builder::{Builder, CurrentThread, MultiThread};
+pub use future::FutureExt;
+pub use io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+pub use join_set::JoinSet;
+pub use local_set::LocalSet;
+pub use metrics::RuntimeMetrics;
+pub use mini_rt_macros::{main, test};
+pub use multi_thread::MultiThreadRuntime;
+pub use scope::{Scope, scope};
+pub use stream::{FuturesUnordered, Stream, StreamExt};
+pub use sync::{
+    Barrier, CancellationToken, Condvar, Mutex, MutexGuard, Notify, OnceCell, RwLock, Semaphore,
+    SemaphorePermit, WaitGroup,
+};
+pub use time::{RateLimiter, sleep, sleep_until, timeout};
+
+/// How many polls a woken task waits at most before it goes next regardless
+/// of priority.
+const MAX_READY_WAIT: u64 = 64;
+
+/// How many busy scheduler ticks pass between non-blocking reactor turns.
+#[cfg(unix)]
+const IO_INTERVAL: u32 = 61;
+
+thread_local! {
+    static RUNTIME: RefCell<Option<Rc<RefCell<Shared>>>> = const { RefCell::new(None) };
+}
+
+/// A runtime that polls every task on the thread calling
+/// [`block_on`](MiniRuntime::block_on).
+///
+/// # Fairness
+///
+/// Woken tasks of the same [`Priority`] are polled strictly in the order
+/// they were woken, and a task that wakes itself while being polled, as
+/// [`yield_now`] does, goes to the back of the queue. A task that is
+/// continuously ready is therefore polled at most once before every other
+/// ready task of its priority has had a turn; timers that fire join the
+/// back of the queue the same way. The only task polled out of turn is
+/// one started by [`Handle::spawn_eager`].
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use mini_rt::Handle;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let turns = Rc::new(RefCell::new(Vec::new()));
+/// let log = turns.clone();
+/// rt.block_on(async move {
+///     let workers: Vec<_> = (0..3)
+///         .map(|worker| {
+///             let log = log.clone();
+///             Handle::current().spawn(async move {
+///                 for _ in 0..4 {
+///                     log.borrow_mut().push(worker);
+///                     mini_rt::yield_now().await;
+///                 }
+///             })
+///         })
+///         .collect();
+///     for worker in workers {
+///         worker.await.unwrap();
+///     }
+/// });
+/// assert_eq!(*turns.borrow(), [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2]);
+/// ```
+///
+/// # Threads
+///
+/// A runtime stays on the thread that created it, but any number of threads
+/// can each run one of their own, say a dedicated I/O thread beside the main
+/// one. They are fully isolated: `block_on` installs its runtime for the
+/// calling thread only, so [`Handle::current`], [`spawn_local`] and timers
+/// always refer to the runtime of the thread they are called on. Values
+/// cross between them over std channels, which
+/// [`channel::from_std`] makes awaitable.
+///
+/// ```
+/// use std::{sync::mpsc, thread};
+/// use mini_rt::{Handle, StreamExt, channel};
+///
+/// let (to_io, requests) = mpsc::channel::<u32>();
+/// let (to_main, replies) = mpsc::channel();
+/// let io_thread = thread::spawn(move || {
+///     let mut io_rt = mini_rt::MiniRuntime::new();
+///     io_rt.block_on(async move {
+///         let mut requests = channel::from_std(requests);
+///         while let Some(n) = requests.next().await {
+///             // Spawns onto this thread's runtime, not the main one.
+///             let doubled = Handle::current().spawn(async move { n * 2 });
+///             to_main.send(doubled.await.unwrap()).unwrap();
+///         }
+///     });
+///     io_rt.metrics().total_spawned
+/// });
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let replies: Vec<_> = rt.block_on(async move {
+///     for n in 1..=3 {
+///         to_io.send(n).unwrap();
+///     }
+///     drop(to_io);
+///     channel::from_std(replies).collect().await
+/// });
+/// assert_eq!(replies, [2, 4, 6]);
+/// // The I/O thread ran its root and one task per request; the main runtime
+/// // only its root.
+/// assert_eq!(io_thread.join().unwrap(), 4);
+/// assert_eq!(rt.metrics().total_spawned, 1);
+/// ```
+pub struct MiniRuntime {
+    shared: Rc<RefCell<Shared>>,
+}
+
+/// Scheduler state shared between the `block_on` loop and the futures it
+/// drives (through the `RUNTIME` thread-local), so both always see the same
+/// queues.
+struct Shared {
+    /// Every spawned task that has not finished, under the key its waker
+    /// pushes onto `ready`. A task keeps its slot while it is being polled,
+    /// with `None` standing in for it meanwhile; only keys pushed onto
+    /// `ready` are polled.
+    tasks: Slab<Option<Task>>,
+    /// Tasks spawned while `max_tasks` were already live, in spawn order.
+    /// They sit in `tasks` but are neither polled nor counted until a slot
+    /// frees up.
+    deferred: VecDeque<slab::Key>,
+    max_tasks: Option<usize>,
+    ready: Arc<ReadyQueue>,
+    timers: Timers,
+    /// What timers measure deadlines against.
+    clock: Arc<RuntimeClock>,
+    /// The task currently being polled.
+    running: Option<TaskId>,
+    /// Set by `MiniRuntime::shutdown`; new tasks are dropped unpolled.
+    closed: bool,
+    /// Set while `block_on` is driving the tasks, as opposed to the runtime
+    /// merely being entered.
+    driving: bool,
+    /// Set while `Handle::spawn_eager` polls a new task inline, so that
+    /// tasks spawned eagerly from there are queued instead of nesting.
+    polling_inline: bool,
+    /// Run around every sleep of the `block_on` loop.
+    on_park: Option<Rc<dyn Fn()>>,
+    on_unpark: Option<Rc<dyn Fn()>>,
+    /// Polls taking longer than the threshold are reported to the hook.
+    on_slow_poll: Option<(Duration, SlowPollHook)>,
+    /// Running totals reported by `MiniRuntime::metrics`.
+    total_spawned: u64,
+    total_polls: u64,
+    blocking: BlockingPool,
+    #[cfg(unix)]
+    reactor: reactor::Reactor,
+    this: Weak<RefCell<Shared>>,
+}
+
+/// Told which task took how long over a poll; see
+/// `MiniRuntime::on_slow_poll`.
+type SlowPollHook = Rc<dyn Fn(TaskId, Duration)>;
+
+/// Keys of woken tasks, in wake order per priority. Wakers are `Send + Sync`,
+/// so this is the only piece of scheduler state they are allowed to touch.
+struct ReadyQueue {
+    lanes: std::sync::Mutex<Lanes>,
+    /// The thread running `block_on`, unparked whenever a task is woken.
+    thread: Thread,
+    /// Set while `block_on` sleeps in the reactor rather than parked, so a
+    /// wake must go through the reactor's notifier instead.
+    #[cfg(unix)]
+    polling: AtomicBool,
+    #[cfg(unix)]
+    notifier: reactor::Notifier,
+}
+
+/// One FIFO queue per [`Priority`], indexed by it. Each key is stamped with
+/// the pop count when it was pushed, so long waits can be spotted.
+#[derive(Default)]
+struct Lanes {
+    queues: [VecDeque<(slab::Key, u64)>; 3],
+    pops: u64,
+}
+
+impl ReadyQueue {
+    fn push(&self, key: slab::Key, priority: Priority) {
+        {
+            let mut lanes = self.lanes.lock().unwrap();
+            let stamp = lanes.pops;
+            lanes.queues[priority as usize].push_back((key, stamp));
+        }
+        self.thread.unpark();
+        #[cfg(unix)]
+        if self.polling.load(Ordering::SeqCst) {
+            self.notifier.notify();
+        }
+    }
+
+    /// Takes the oldest key of the highest priority, unless a lower priority
+    /// task has waited `MAX_READY_WAIT` pops, in which case it goes first.
+    fn pop(&self) -> Option<slab::Key> {
+        let mut lanes = self.lanes.lock().unwrap();
+        let pops = lanes.pops;
+        lanes.pops += 1;
+        let starved = (0..lanes.queues.len())
+            .filter_map(|lane| {
+                let &(_, stamp) = lanes.queues[lane].front()?;
+                (pops - stamp >= MAX_READY_WAIT).then_some((stamp, lane))
+            })
+            .min();
+        let lane = match starved {
+            Some((_, lane)) => lane,
+            None => lanes.queues.iter().rposition(|queue| !queue.is_empty())?,
+        };
+        lanes.queues[lane].pop_front().map(|(key, _)| key)
+    }
+
+    fn is_empty(&self) -> bool {
+        let lanes = self.lanes.lock().unwrap();
+        lanes.queues.iter().all(VecDeque::is_empty)
+    }
+}
+
+/// A spawned future with its output type erased.
+///
+/// The scheduler only ever stores `dyn Future<Output = ()>`. `Shared::spawn`
+/// makes any future fit that shape by wrapping it in a unit future that, on
+/// completion, moves the real output into a `JoinState<T>` shared with the
+/// task's `JoinHandle<T>`. The output type therefore lives only in the slot
+/// and the handle; nothing in the run loop depends on it.
+struct Task {
+    future: ErasedFuture,
+    waker: Arc<TaskWaker>,
+}
+
+type ErasedFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+impl Task {
+    fn poll(&mut self) -> Poll<()> {
+        // Clear the flag first so a wake issued during this poll re-queues us.
+        self.waker.queued.store(false, Ordering::SeqCst);
+        let waker = Waker::from(self.waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        coop::budget(|| self.future.as_mut().poll(&mut cx))
+    }
+}
+
+impl MiniRuntime {
+    /// Creates a runtime with the default settings; see [`Builder`].
+    pub fn new() -> Self {
+        Builder::new().build()
+    }
+
+    /// Returns a [`Builder`] for configuring a runtime before creating it.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    fn from_builder(builder: &Builder) -> Self {
+        #[cfg(unix)]
+        let (reactor, notifier) = reactor::Reactor::new();
+        let clock = Arc::new(RuntimeClock::new(builder.clock.clone()));
+        Self {
+            shared: Rc::new_cyclic(|this| {
+                RefCell::new(Shared {
+                    tasks: Slab::new(),
+                    deferred: VecDeque::new(),
+                    max_tasks: builder.max_concurrent_tasks,
+                    ready: Arc::new(ReadyQueue {
+                        lanes: std::sync::Mutex::default(),
+                        thread: thread::current(),
+                        #[cfg(unix)]
+                        polling: AtomicBool::new(false),
+                        #[cfg(unix)]
+                        notifier,
+                    }),
+                    timers: Timers::new(builder.timer_wheel.then(|| clock.now())),
+                    clock,
+                    running: None,
+                    closed: false,
+                    driving: false,
+                    polling_inline: false,
+                    on_park: None,
+                    on_unpark: None,
+                    on_slow_poll: None,
+                    total_spawned: 0,
+                    total_polls: 0,
+                    blocking: BlockingPool::new(
+                        builder.max_blocking_threads,
+                        builder.thread_name.clone(),
+                    ),
+                    #[cfg(unix)]
+                    reactor,
+                    this: this.clone(),
+                })
+            }),
+        }
+    }
+
+    /// Runs `future` to completion, along with every task it spawns, and
+    /// returns its output.
+    ///
+    /// A panic in a spawned task is reported through its `JoinHandle`; a
+    /// panic in `future` itself is resumed here.
+    ///
+    /// Timers alone never keep the runtime going: it returns as soon as the
+    /// last task is done, whatever deadlines are still pending.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let start = Instant::now();
+    /// rt.block_on(async {
+    ///     let long = mini_rt::sleep(Duration::from_secs(60));
+    ///     let _ = mini_rt::timeout(Duration::from_millis(5), long).await;
+    /// });
+    /// assert!(start.elapsed() < Duration::from_secs(60));
+    /// ```
+    pub fn block_on<F>(&mut self, future: F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let root = self.spawn(future);
+        // A panic in the root future is the caller's panic.
+        self.run(
+            || matches!(&root.state.borrow().output, Some(Err(JoinError::Panic(..)))),
+            None,
+        );
+        // The loop only exits once every task is done, or the root panicked.
+        match root.state.borrow_mut().output.take() {
+            Some(Ok(output)) => output,
+            Some(Err(JoinError::Panic(_, payload))) => panic::resume_unwind(payload),
+            _ => unreachable!("root task finished without an output"),
+        }
+    }
+
+    /// Like [`block_on`](MiniRuntime::block_on), but gives up once
+    /// `duration` of real time has passed, dropping `future` and returning
+    /// `Err(Elapsed)` if it has not completed by then.
+    ///
+    /// Unlike wrapping `future` in [`timeout`], this bounds the whole run,
+    /// including time the runtime spends parked or busy with other tasks.
+    /// It returns as soon as `future` completes, without waiting for spawned
+    /// tasks; those still unfinished stay on the runtime for the next call.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use mini_rt::{future, time::Elapsed};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let limit = Duration::from_millis(20);
+    /// assert_eq!(rt.block_on_timeout(future::ready(1), limit), Ok(1));
+    /// let stuck = rt.block_on_timeout(future::pending::<()>(), limit);
+    /// assert_eq!(stuck, Err(Elapsed));
+    /// ```
+    pub fn block_on_timeout<F>(
+        &mut self,
+        future: F,
+        duration: Duration,
+    ) -> Result<F::Output, time::Elapsed>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let root = self.spawn(future);
+        self.run(
+            || root.state.borrow().output.is_some(),
+            Some(Instant::now() + duration),
+        );
+        let output = root.state.borrow_mut().output.take();
+        match output {
+            Some(Ok(output)) => Ok(output),
+            Some(Err(JoinError::Panic(_, payload))) => panic::resume_unwind(payload),
+            _ => {
+                root.abort_handle().abort();
+                Err(time::Elapsed)
+            }
+        }
+    }
+
+    /// Stops accepting new tasks, runs the ones already spawned to
+    /// completion, and drops the runtime.
+    ///
+    /// Tasks spawned from here on are dropped immediately; their handles
+    /// resolve to `Err(JoinError::Cancelled)`.
+    pub fn shutdown(mut self) {
+        self.shared.borrow_mut().closed = true;
+        self.run(|| false, None);
+    }
+
+    /// Like [`shutdown`](MiniRuntime::shutdown), but gives up waiting once
+    /// `timeout` has elapsed and drops whatever is still unfinished. A zero
+    /// timeout drops every task without polling it again.
+    pub fn shutdown_timeout(mut self, timeout: Duration) {
+        self.shared.borrow_mut().closed = true;
+        if !timeout.is_zero() {
+            self.run(|| false, Some(Instant::now() + timeout));
+        }
+        let mut shared = self.shared.borrow_mut();
+        let tasks = std::mem::take(&mut shared.tasks);
+        let deferred = std::mem::take(&mut shared.deferred);
+        drop(shared);
+        drop(tasks);
+        drop(deferred);
+    }
+
+    /// Polls the task at the front of the ready queue, if any, and then
+    /// fires the timers that are due, without ever waiting. Returns whether
+    /// a task was polled.
+    ///
+    /// This is one step of the loop [`block_on`](MiniRuntime::block_on)
+    /// runs, for tests that need to control the scheduler exactly.
+    ///
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let steps = Rc::new(Cell::new(0));
+    /// let counter = steps.clone();
+    /// rt.spawn(async move {
+    ///     for _ in 0..3 {
+    ///         counter.set(counter.get() + 1);
+    ///         mini_rt::yield_now().await;
+    ///     }
+    /// });
+    /// assert!(rt.tick());
+    /// assert_eq!(steps.get(), 1);
+    /// assert!(rt.tick());
+    /// assert_eq!(steps.get(), 2);
+    /// // Two more polls finish the task; then there is nothing left to do.
+    /// assert!(rt.tick());
+    /// assert!(rt.tick());
+    /// assert_eq!(rt.active_tasks(), 0);
+    /// assert!(!rt.tick());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within a task of a runtime on this thread.
+    pub fn tick(&mut self) -> bool {
+        let previous = self.start_driving();
+        let ready = self.shared.borrow().ready.clone();
+        let polled = self.poll_next(&ready);
+        let now = self.shared.borrow().clock.now();
+        self.shared.borrow_mut().fire_timers(now);
+        self.stop_driving(previous);
+        polled
+    }
+
+    /// Polls tasks until none is ready, firing due timers along the way,
+    /// and returns without waiting for time to pass or for I/O that is not
+    /// ready yet. Tasks blocked on those stay pending; with time paused, the
+    /// clock stays where it is.
+    ///
+    /// ```
+    /// use std::{cell::Cell, rc::Rc, time::Duration};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let progress = Rc::new(Cell::new(0));
+    /// let counter = progress.clone();
+    /// let sleeper = rt.spawn(async move {
+    ///     for _ in 0..3 {
+    ///         counter.set(counter.get() + 1);
+    ///         mini_rt::yield_now().await;
+    ///     }
+    ///     mini_rt::sleep(Duration::from_secs(60)).await;
+    ///     counter.set(counter.get() + 1);
+    /// });
+    /// rt.run_until_stalled();
+    /// assert_eq!(progress.get(), 3);
+    /// assert_eq!(rt.active_tasks(), 1);
+    /// assert_eq!(rt.pending_timers(), 1);
+    /// assert!(!sleeper.is_finished());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within a task of a runtime on this thread.
+    pub fn run_until_stalled(&mut self) {
+        let previous = self.start_driving();
+        let ready = self.shared.borrow().ready.clone();
+        loop {
+            self.poll_next(&ready);
+            let mut shared = self.shared.borrow_mut();
+            let now = shared.clock.now();
+            shared.fire_timers(now);
+            // Sockets that are ready already count as progress to be made.
+            #[cfg(unix)]
+            if ready.is_empty() && !shared.reactor.is_idle() {
+                shared.reactor.turn(Some(Duration::ZERO));
+            }
+            if ready.is_empty() {
+                break;
+            }
+        }
+        self.stop_driving(previous);
+    }
+
+    /// Makes this the runtime driving the thread, returning the one it
+    /// replaces for [`stop_driving`](MiniRuntime::stop_driving).
+    fn start_driving(&self) -> Option<Rc<RefCell<Shared>>> {
+        let previous = RUNTIME.with(|rt| {
+            let mut rt = rt.borrow_mut();
+            // The outer loop is stuck inside the poll of the task calling us
+            // and would never see its own tasks again. A runtime that is only
+            // entered is fine to shadow.
+            assert!(
+                !rt.as_ref().is_some_and(|shared| shared
+                    .try_borrow()
+                    .map_or(true, |shared| shared.driving)),
+                "cannot call block_on from within a runtime: a runtime is already \
+                 running tasks on this thread"
+            );
+            rt.replace(self.shared.clone())
+        });
+        self.shared.borrow_mut().driving = true;
+        previous
+    }
+
+    fn stop_driving(&self, previous: Option<Rc<RefCell<Shared>>>) {
+        self.shared.borrow_mut().driving = false;
+        RUNTIME.with(|rt| *rt.borrow_mut() = previous);
+    }
+
+    /// Polls the task at the front of `ready`, returning `false` if there
+    /// was none to poll.
+    fn poll_next(&self, ready: &ReadyQueue) -> bool {
+        // Take the task out before polling: it may spawn or schedule
+        // timers, which needs its own borrow of the shared state.
+        let next = ready.pop().and_then(|key| {
+            let task = self.shared.borrow_mut().start(key)?;
+            Some((key, task))
+        });
+        let Some((key, task)) = next else {
+            return false;
+        };
+        poll_started(&self.shared, key, task, None);
+        true
+    }
+
+    /// Drives tasks until none are left, `stop` returns `true`, or `until`
+    /// passes.
+    fn run(&mut self, stop: impl Fn() -> bool, until: Option<Instant>) {
+        let previous = self.start_driving();
+        let ready = self.shared.borrow().ready.clone();
+        #[cfg(unix)]
+        let mut busy_ticks = 0u32;
+
+        loop {
+            self.poll_next(&ready);
+
+            if stop() {
+                break;
+            }
+
+            let mut shared = self.shared.borrow_mut();
+            let now = shared.clock.now();
+            shared.fire_timers(now);
+            if shared.tasks.is_empty() || until.is_some_and(|until| Instant::now() >= until) {
+                break;
+            }
+            if !ready.is_empty() {
+                // Tasks that keep waking each other must not starve sockets,
+                // so check the reactor without blocking every so often.
+                #[cfg(unix)]
+                {
+                    busy_ticks = busy_ticks.wrapping_add(1);
+                    if busy_ticks.is_multiple_of(IO_INTERVAL) && !shared.reactor.is_idle() {
+                        shared.reactor.turn(Some(Duration::ZERO));
+                    }
+                }
+                continue;
+            }
+
+            // With time paused, only a timer can make progress, so jump the
+            // clock to the next one rather than wait for it.
+            if shared.clock.is_paused()
+                && shared.waits_only_on_timers()
+                && let Some(when) = shared.timers.next_deadline()
+            {
+                shared.clock.advance_to(when);
+                continue;
+            }
+
+            // Nothing is runnable: sleep until the next timer is due or a
+            // waker unparks us, instead of spinning on the clock. Timer
+            // deadlines follow the runtime's clock; `until` is real time.
+            let timeout = shared
+                .timers
+                .next_deadline()
+                .map(|when| when.saturating_duration_since(now))
+                .into_iter()
+                .chain(until.map(|until| until.saturating_duration_since(Instant::now())))
+                .min();
+            // Hooks may spawn or wake tasks, so run them without a borrow.
+            let on_park = shared.on_park.clone();
+            drop(shared);
+            if let Some(on_park) = on_park {
+                on_park();
+            }
+            self.park(&ready, timeout);
+            let on_unpark = self.shared.borrow().on_unpark.clone();
+            if let Some(on_unpark) = on_unpark {
+                on_unpark();
+            }
+        }
+
+        self.stop_driving(previous);
+    }
+
+    /// Sleeps until a task is woken or `timeout` passes.
+    fn park(&self, ready: &ReadyQueue, timeout: Option<Duration>) {
+        #[cfg(unix)]
+        {
+            let mut shared = self.shared.borrow_mut();
+            if !shared.reactor.is_idle() {
+                // Some task waits on a socket, so sleep in the reactor. Raise
+                // the flag before the last look at the queue so that any wake
+                // after it goes through the notifier.
+                ready.polling.store(true, Ordering::SeqCst);
+                if ready.is_empty() {
+                    shared.reactor.turn(timeout);
+                }
+                ready.polling.store(false, Ordering::SeqCst);
+                return;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = ready;
+        match timeout {
+            Some(timeout) => thread::park_timeout(timeout),
+            None => thread::park(),
+        }
+    }
+
+    /// Registers `hook` to run each time the runtime is about to sleep
+    /// because no task is ready, replacing any earlier one. Handy for
+    /// flushing buffers or feeding a watchdog while the program is idle.
+    ///
+    /// ```
+    /// use std::{cell::Cell, rc::Rc, time::Duration};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let parks = Rc::new(Cell::new(0));
+    /// let unparks = Rc::new(Cell::new(0));
+    /// let counter = parks.clone();
+    /// rt.on_park(move || counter.set(counter.get() + 1));
+    /// let counter = unparks.clone();
+    /// rt.on_unpark(move || counter.set(counter.get() + 1));
+    /// rt.block_on(async {
+    ///     mini_rt::sleep(Duration::from_millis(5)).await;
+    ///     mini_rt::sleep(Duration::from_millis(5)).await;
+    /// });
+    /// assert!(parks.get() >= 2);
+    /// assert_eq!(parks.get(), unparks.get());
+    /// ```
+    pub fn on_park(&mut self, hook: impl Fn() + 'static) {
+        self.shared.borrow_mut().on_park = Some(Rc::new(hook));
+    }
+
+    /// Registers `hook` to run each time the runtime wakes from the sleep
+    /// that [`on_park`](MiniRuntime::on_park) announces, replacing any
+    /// earlier one.
+    pub fn on_unpark(&mut self, hook: impl Fn() + 'static) {
+        self.shared.borrow_mut().on_unpark = Some(Rc::new(hook));
+    }
+
+    /// Registers `hook` to run, with the task's id and how long the poll
+    /// took, whenever polling a spawned task takes longer than `threshold`,
+    /// replacing any earlier one. Every other task waits out such a poll, so
+    /// this is the way to find the task making a blocking call, such as
+    /// synchronous file I/O or a long computation. The `tracing` feature
+    /// also reports slow polls as warnings.
+    ///
+    /// ```
+    /// use std::{cell::RefCell, rc::Rc, time::Duration};
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let slow = Rc::new(RefCell::new(Vec::new()));
+    /// let reported = slow.clone();
+    /// rt.on_slow_poll(Duration::from_millis(20), move |id, took| {
+    ///     reported.borrow_mut().push((id, took));
+    /// });
+    /// let blocking = rt.spawn(async { std::thread::sleep(Duration::from_millis(50)) });
+    /// let blocking_id = blocking.id();
+    /// rt.spawn(async { mini_rt::yield_now().await });
+    /// rt.block_on(blocking).unwrap();
+    ///
+    /// let slow = slow.borrow();
+    /// assert_eq!(slow.len(), 1);
+    /// assert_eq!(slow[0].0, blocking_id);
+    /// assert!(slow[0].1 >= Duration::from_millis(50));
+    /// ```
+    pub fn on_slow_poll(&mut self, threshold: Duration, hook: impl Fn(TaskId, Duration) + 'static) {
+        self.shared.borrow_mut().on_slow_poll = Some((threshold, Rc::new(hook)));
+    }
+
+    /// Schedules `future` as a new task and returns a handle that resolves
+    /// to its output once it completes.
+    pub fn spawn<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.shared.borrow_mut().spawn(future)
+    }
+
+    /// Like [`spawn`](MiniRuntime::spawn), but with a [`Priority`] other than
+    /// the default.
+    pub fn spawn_with_priority<F>(&mut self, future: F, priority: Priority) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.shared
+            .borrow_mut()
+            .spawn_with_priority(future, priority)
+    }
+
+    /// Runs the blocking closure `f` on a background thread, returning a
+    /// handle that resolves to its result without stalling other tasks.
+    pub fn spawn_blocking<F, T>(&mut self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.shared.borrow_mut().spawn_blocking(f)
+    }
+
+    /// Caps how many threads `spawn_blocking` may use at once; further
+    /// closures queue until a thread frees up.
+    pub fn set_max_blocking_threads(&mut self, max: usize) {
+        self.shared.borrow().blocking.set_max_threads(max);
+    }
+
+    /// Returns a snapshot of the scheduler's counters.
+    ///
+    /// Only woken tasks are polled, so tasks waiting on a timer cost nothing
+    /// until it fires: here each sleeping task is polled once to start it
+    /// and once more when its deadline passes.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// for _ in 0..1000 {
+    ///     rt.spawn(mini_rt::sleep(Duration::from_millis(10)));
+    /// }
+    /// rt.block_on(mini_rt::sleep(Duration::from_millis(20)));
+    /// let metrics = rt.metrics();
+    /// assert_eq!(metrics.pending_tasks, 0);
+    /// assert!(metrics.total_polls <= 2 * 1001);
+    /// ```
+    pub fn metrics(&self) -> RuntimeMetrics {
+        let shared = self.shared.borrow();
+        RuntimeMetrics {
+            total_spawned: shared.total_spawned,
+            total_polls: shared.total_polls,
+            pending_tasks: shared.tasks.len(),
+            pending_timers: shared.timers.len(),
+        }
+    }
+
+    /// Returns how many spawned tasks have not finished yet, counting those
+    /// waiting for a `max_concurrent_tasks` slot. Cheap enough to check on
+    /// every request, such as for load shedding.
+    pub fn active_tasks(&self) -> usize {
+        self.shared.borrow().tasks.len()
+    }
+
+    /// Returns how many timers are scheduled but have not fired yet; see
+    /// [`RuntimeMetrics::pending_timers`].
+    pub fn pending_timers(&self) -> usize {
+        self.shared.borrow().timers.len()
+    }
+
+    /// Makes this the current runtime on this thread until the guard is
+    /// dropped, without driving any tasks.
+    ///
+    /// Code that needs a runtime while setting up, such as
+    /// [`Handle::current`] or [`time::now`] under a custom clock, then works
+    /// outside of `block_on`. Tasks spawned meanwhile run once `block_on`
+    /// is called. Guards must be dropped in the reverse order they were
+    /// created.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// let sleep = {
+    ///     let _guard = rt.enter();
+    ///     let handle = mini_rt::Handle::current();
+    ///     handle.spawn(mini_rt::sleep(Duration::from_millis(10)))
+    /// };
+    /// rt.block_on(sleep).unwrap();
+    /// ```
+    pub fn enter(&self) -> EnterGuard<'_> {
+        let previous = RUNTIME.with(|rt| rt.borrow_mut().replace(self.shared.clone()));
+        EnterGuard {
+            previous,
+            _runtime: PhantomData,
+        }
+    }
+
+    /// Returns a [`Handle`] for spawning onto this runtime without a
+    /// `&mut MiniRuntime`.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            shared: Rc::downgrade(&self.shared),
+        }
+    }
+
+    /// Creates a runtime that polls `Send` tasks on `num_workers` threads.
+    /// Use [`MiniRuntime::new`] for futures that are not `Send`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_workers` is zero.
+    pub fn multi_thread(num_workers: usize) -> MultiThreadRuntime {
+        Builder::new().worker_threads(num_workers).build()
+    }
+}
+
+impl Default for MiniRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps a runtime current on this thread; see [`MiniRuntime::enter`].
+pub struct EnterGuard<'a> {
+    /// The runtime that was current before, restored on drop.
+    previous: Option<Rc<RefCell<Shared>>>,
+    _runtime: PhantomData<&'a MiniRuntime>,
+}
+
+impl Drop for EnterGuard<'_> {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        RUNTIME.with(|rt| *rt.borrow_mut() = previous);
+    }
+}
+
+/// A cloneable reference to a [`MiniRuntime`] that can spawn tasks onto it
+/// from anywhere on the runtime's thread, including from inside its tasks.
+///
+/// The handle does not keep the runtime alive.
+#[derive(Clone)]
+pub struct Handle {
+    shared: Weak<RefCell<Shared>>,
+}
+
+/// Polls `task`, just taken out of its slot at `key` by `Shared::start`,
+/// then puts it back or frees the slot if it finished. `running` is what
+/// the task being polled was before.
+fn poll_started(shared: &RefCell<Shared>, key: slab::Key, mut task: Task, running: Option<TaskId>) {
+    let id = task.waker.id;
+    let on_slow_poll = shared.borrow().on_slow_poll.clone();
+    let started = on_slow_poll.as_ref().map(|_| Instant::now());
+    #[cfg(feature = "tracing")]
+    let span = tracing::trace_span!("poll", task.id = id.0).entered();
+    let pending = task.poll().is_pending();
+    if !pending {
+        trace!("task completed");
+    }
+    #[cfg(feature = "tracing")]
+    drop(span);
+    let took = started.map(|started| started.elapsed());
+    let mut shared_ref = shared.borrow_mut();
+    shared_ref.running = running;
+    // The slot is gone if the task aborted itself while polled.
+    match shared_ref.tasks.get_mut(key) {
+        Some(slot) if pending => *slot = Some(task),
+        slot => {
+            if slot.is_some() {
+                shared_ref.tasks.remove(key);
+            }
+            // Dropping may run arbitrary destructors; release the borrow
+            // first.
+            drop(shared_ref);
+            drop(task);
+            shared.borrow_mut().admit_deferred();
+        }
+    }
+    if let (Some((threshold, hook)), Some(took)) = (on_slow_poll, took)
+        && took > threshold
+    {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            task.id = id.0,
+            ?took,
+            "task blocked the runtime for too long"
+        );
+        hook(id, took);
+    }
+}
+
+impl Handle {
+    /// Returns a handle to the runtime whose `block_on` is running on this
+    /// thread. Runtimes on other threads are never found from here; see
+    /// [the thread notes on `MiniRuntime`](MiniRuntime#threads).
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of `block_on`.
+    pub fn current() -> Self {
+        RUNTIME.with(|rt| Self {
+            shared: Rc::downgrade(
+                rt.borrow()
+                    .as_ref()
+                    .expect("Handle::current called outside of a runtime"),
+            ),
+        })
+    }
+
+    /// Like [`MiniRuntime::spawn`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime has been dropped.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.shared().borrow_mut().spawn(future)
+    }
+
+    /// Like [`MiniRuntime::spawn_with_priority`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime has been dropped.
+    pub fn spawn_with_priority<F>(&self, future: F, priority: Priority) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.shared()
+            .borrow_mut()
+            .spawn_with_priority(future, priority)
+    }
+
+    /// Like [`spawn`](Handle::spawn), but polls the new task once right
+    /// away, before returning, instead of waiting for the scheduler to
+    /// reach it. Work the task can do without waiting is therefore done by
+    /// the time this returns; whatever is left runs as usual.
+    ///
+    /// The task is only queued, as by `spawn`, when the runtime is not
+    /// running tasks, when [`max_concurrent_tasks`] are already live, or
+    /// when the call comes from a task that is itself being polled eagerly,
+    /// so chains of eager spawns cannot grow the stack without bound.
+    ///
+    /// [`max_concurrent_tasks`]: Builder::max_concurrent_tasks
+    ///
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    /// use mini_rt::Handle;
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// rt.block_on(async {
+    ///     let ran = Rc::new(Cell::new(false));
+    ///     let flag = ran.clone();
+    ///     let task = Handle::current().spawn_eager(async move { flag.set(true) });
+    ///     assert!(ran.get());
+    ///     task.await.unwrap();
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime has been dropped.
+    pub fn spawn_eager<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let shared = self.shared();
+        let mut shared_ref = shared.borrow_mut();
+        if !shared_ref.driving || shared_ref.polling_inline || shared_ref.is_full() {
+            return shared_ref.spawn(future);
+        }
+        let handle = shared_ref.spawn_with(future, Priority::Normal, false);
+        let Some(key) = handle.abort.key else {
+            return handle;
+        };
+        let running = shared_ref.running;
+        let task = shared_ref.start(key).unwrap();
+        shared_ref.polling_inline = true;
+        drop(shared_ref);
+        poll_started(&shared, key, task, running);
+        shared.borrow_mut().polling_inline = false;
+        handle
+    }
+
+    /// Like [`MiniRuntime::spawn_blocking`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime has been dropped.
+    pub fn spawn_blocking<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.shared().borrow_mut().spawn_blocking(f)
+    }
+
+    /// Like [`MiniRuntime::active_tasks`], but callable from inside a task.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use mini_rt::Handle;
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// rt.block_on(async {
+    ///     let handle = Handle::current();
+    ///     for _ in 0..5 {
+    ///         handle.spawn(mini_rt::sleep(Duration::from_millis(5)));
+    ///     }
+    ///     mini_rt::yield_now().await;
+    ///     // The five sleepers, plus this task.
+    ///     assert_eq!(handle.active_tasks(), 6);
+    ///     assert_eq!(handle.pending_timers(), 5);
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime has been dropped.
+    pub fn active_tasks(&self) -> usize {
+        self.shared().borrow().tasks.len()
+    }
+
+    /// Like [`MiniRuntime::pending_timers`], but callable from inside a
+    /// task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime has been dropped.
+    pub fn pending_timers(&self) -> usize {
+        self.shared().borrow().timers.len()
+    }
+
+    fn shared(&self) -> Rc<RefCell<Shared>> {
+        self.shared
+            .upgrade()
+            .expect("Handle used after its runtime was dropped")
+    }
+}
+
+impl Shared {
+    fn spawn<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with_priority(future, Priority::Normal)
+    }
+
+    fn spawn_with_priority<F>(&mut self, future: F, priority: Priority) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with(future, priority, true)
+    }
+
+    /// Spawns `future`, leaving it off the ready queue unless `schedule` is
+    /// set, for the caller to poll first.
+    fn spawn_with<F>(
+        &mut self,
+        future: F,
+        priority: Priority,
+        schedule: bool,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let state = Rc::new(RefCell::new(JoinState {
+            output: None,
+            finished: false,
+            waker: None,
+        }));
+        let id = TaskId::next();
+        let completion = Completion {
+            id,
+            state: state.clone(),
+        };
+        let key = self.spawn_task(id, priority, schedule, async move {
+            // Catch a panic here, where the output type is still known, so it
+            // reaches the JoinHandle and the other tasks keep running.
+            let mut future = pin!(future);
+            let output = future::poll_fn(|cx| {
+                match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+                    Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+                    Ok(Poll::Pending) => Poll::Pending,
+                    Err(payload) => Poll::Ready(Err(JoinError::Panic(id, payload))),
+                }
+            })
+            .await;
+            completion.finish(output);
+        });
+        JoinHandle {
+            state,
+            abort: AbortHandle {
+                id,
+                key,
+                shared: self.this.clone(),
+            },
+        }
+    }
+
+    fn spawn_blocking<F, T>(&mut self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let task = self.blocking.run(f);
+        self.spawn(task)
+    }
+
+    /// Stores task `id` for `future` and returns its slot, unless the
+    /// runtime is closed. Only a `schedule`d task is queued to be polled, or
+    /// deferred if the runtime is full.
+    fn spawn_task<F>(
+        &mut self,
+        id: TaskId,
+        priority: Priority,
+        schedule: bool,
+        future: F,
+    ) -> Option<slab::Key>
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        self.total_spawned += 1;
+        trace!(task.id = id.0, "task spawned");
+        if self.closed {
+            // Dropping the future resolves its handle as cancelled.
+            return None;
+        }
+        let full = self.is_full();
+        // The waker must know the slot, and the slot holds the waker: take
+        // the slot first and fill it in once the waker exists.
+        let key = self.tasks.insert(None);
+        let waker = Arc::new(TaskWaker {
+            id,
+            key,
+            priority,
+            queued: AtomicBool::new(true),
+            ready: self.ready.clone(),
+        });
+        *self.tasks.get_mut(key).unwrap() = Some(Task {
+            future: Box::pin(future),
+            waker,
+        });
+        if !schedule {
+            // Still marked queued, so wakes are ignored until the caller's
+            // poll clears the flag.
+            return Some(key);
+        }
+        if full {
+            // Still marked queued, so wakes are ignored until admitted.
+            self.deferred.push_back(key);
+        } else {
+            self.ready.push(key, priority);
+        }
+        Some(key)
+    }
+
+    /// Counts the tasks that have been admitted and not yet finished.
+    fn live_tasks(&self) -> usize {
+        self.tasks.len() - self.deferred.len()
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_tasks.is_some_and(|max| self.live_tasks() >= max)
+    }
+
+    /// Schedules deferred tasks for as many slots as are free.
+    fn admit_deferred(&mut self) {
+        while !self.is_full()
+            && let Some(key) = self.deferred.pop_front()
+        {
+            let priority = self
+                .tasks
+                .get_mut(key)
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .waker
+                .priority;
+            self.ready.push(key, priority);
+        }
+    }
+
+    /// Frees the slot at `key`, whether admitted or deferred, and returns
+    /// the task in it. The task is `None` if it is being polled; the run
+    /// loop then drops it once its poll returns.
+    fn remove_task(&mut self, key: slab::Key) -> Option<Option<Task>> {
+        let task = self.tasks.remove(key)?;
+        if let Some(index) = self.deferred.iter().position(|&other| other == key) {
+            self.deferred.remove(index);
+        }
+        Some(task)
+    }
+
+    /// Takes the task at `key` out to be polled, if it is still alive. Its
+    /// slot stays reserved until it is put back or removed.
+    fn start(&mut self, key: slab::Key) -> Option<Task> {
+        let task = self.tasks.get_mut(key)?.take()?;
+        self.running = Some(task.waker.id);
+        self.total_polls += 1;
+        Some(task)
+    }
+
+    fn schedule_timer(&mut self, when: Instant, waker: Waker) -> Arc<TimerEntry> {
+        self.timers.push(when, waker)
+    }
+
+    /// Returns `true` if nothing but a timer can wake a task: no socket is
+    /// being waited on and no blocking closure is in flight.
+    fn waits_only_on_timers(&self) -> bool {
+        #[cfg(unix)]
+        if !self.reactor.is_idle() {
+            return false;
+        }
+        self.blocking.is_idle()
+    }
+
+    /// Wakes every timer due at `now`, so timers sharing a deadline all
+    /// wake in the same tick.
+    fn fire_timers(&mut self, now: Instant) {
+        while let Some(waker) = self.timers.pop_due(now) {
+            trace!("timer fired");
+            waker.wake();
+        }
+    }
+}
+
+/// Registers a wake-up at `when` with whichever runtime is driving the
+/// current thread, returning its timer entry. Outside of any runtime the
+/// timer is silently dropped.
+fn schedule_timer(when: Instant, waker: &Waker) -> Option<Arc<TimerEntry>> {
+    RUNTIME
+        .with(|rt| {
+            let shared = rt.borrow().clone()?;
+            Some(shared.borrow_mut().schedule_timer(when, waker.clone()))
+        })
+        .or_else(|| multi_thread::schedule_timer(when, waker))
+}
+
+/// Returns the clock of the runtime driving the current thread.
+fn current_clock() -> Option<Arc<dyn Clock>> {
+    RUNTIME
+        .with(|rt| Some(rt.borrow().as_ref()?.borrow().clock.clone() as Arc<dyn Clock>))
+        .or_else(multi_thread::current_clock)
+}
+
+/// Returns the pausable clock of the current-thread runtime driving this
+/// thread, for `caller`.
+///
+/// # Panics
+///
+/// Panics if there is no such runtime.
+fn runtime_clock(caller: &str) -> Arc<RuntimeClock> {
+    RUNTIME.with(|rt| match &*rt.borrow() {
+        Some(shared) => shared.borrow().clock.clone(),
+        None => panic!("{caller} called outside of a current-thread runtime"),
+    })
+}
+
+/// Returns whether the clock of the current-thread runtime driving this
+/// thread is paused; `false` outside of one.
+fn time_paused() -> bool {
+    RUNTIME.with(|rt| {
+        rt.borrow()
+            .as_ref()
+            .is_some_and(|shared| shared.borrow().clock.is_paused())
+    })
+}
+
+/// Fires the timers that are due on the runtime driving the current thread,
+/// after its clock has been moved.
+fn fire_due_timers() {
+    let handled = RUNTIME.with(|rt| match &*rt.borrow() {
+        Some(shared) => {
+            let mut shared = shared.borrow_mut();
+            let now = shared.clock.now();
+            shared.fire_timers(now);
+            true
+        }
+        None => false,
+    });
+    if !handled {
+        multi_thread::fire_due_timers();
+    }
+}
+
+/// An opaque identifier for a task, unique among all tasks spawned in the
+/// process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        TaskId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// How eagerly the scheduler polls a task once it is woken.
+///
+/// Woken tasks of higher priority are polled before those of lower
+/// priority, in wake order within a priority. A task never waits more than
+/// a bounded number of polls, however, so low-priority tasks still make
+/// progress while higher-priority ones stay busy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Returns the id of the task being polled on this thread, or `None` outside
+/// of a task.
+pub fn current_task_id() -> Option<TaskId> {
+    RUNTIME.with(|rt| rt.borrow().as_ref()?.try_borrow().ok()?.running)
+}
+
+/// A handle to a spawned task, resolving to the task's output, or to a
+/// [`JoinError`] if the task panicked or was aborted.
+///
+/// Dropping the handle detaches the task; it keeps running to completion.
+/// Use [`abort_on_drop`](JoinHandle::abort_on_drop) for a handle that
+/// cancels the task instead.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+    abort: AbortHandle,
+}
+
+/// Cancels a spawned task; see [`JoinHandle::abort_handle`].
+#[derive(Clone)]
+pub struct AbortHandle {
+    id: TaskId,
+    /// `None` if the task was spawned after shutdown and never stored.
+    key: Option<slab::Key>,
+    shared: Weak<RefCell<Shared>>,
+}
+
+/// A [`JoinHandle`] that aborts its task when dropped; see
+/// [`JoinHandle::abort_on_drop`].
+pub struct AbortOnDropGuard<T> {
+    handle: JoinHandle<T>,
+}
+
+/// Why a [`JoinHandle`] did not produce its task's output, along with the
+/// task's id.
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::Handle;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     let handle = Handle::current();
+///     assert_eq!(handle.spawn(async { 7 }).await.unwrap(), 7);
+///
+///     let panicked = handle.spawn(async { panic!("boom") });
+///     let id = panicked.id();
+///     let err = panicked.await.unwrap_err();
+///     assert!(err.is_panic());
+///     assert_eq!(err.id(), id);
+///     assert_eq!(*err.into_panic().downcast::<&str>().unwrap(), "boom");
+///
+///     let aborted = handle.spawn(mini_rt::sleep(Duration::from_secs(60)));
+///     aborted.abort();
+///     let err = aborted.await.unwrap_err();
+///     assert!(err.is_cancelled());
+///     assert!(err.try_into_panic().is_err());
+/// });
+/// ```
+pub enum JoinError {
+    /// The task was aborted, or its runtime shut down, before it completed.
+    Cancelled(TaskId),
+    /// The task panicked; carries the panic payload.
+    Panic(TaskId, Box<dyn Any + Send>),
+}
+
+impl JoinError {
+    /// Returns the id of the task that failed.
+    pub fn id(&self) -> TaskId {
+        match self {
+            JoinError::Cancelled(id) | JoinError::Panic(id, _) => *id,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, JoinError::Cancelled(_))
+    }
+
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JoinError::Panic(..))
+    }
+
+    /// Returns the panic payload, to inspect or to resume unwinding with
+    /// [`std::panic::resume_unwind`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task was cancelled rather than panicking; see
+    /// [`try_into_panic`](JoinError::try_into_panic).
+    pub fn into_panic(self) -> Box<dyn Any + Send> {
+        self.try_into_panic()
+            .expect("JoinError::into_panic called on a cancelled task")
+    }
+
+    /// Returns the panic payload, or `self` back if the task was cancelled.
+    pub fn try_into_panic(self) -> Result<Box<dyn Any + Send>, JoinError> {
+        match self {
+            JoinError::Panic(_, payload) => Ok(payload),
+            cancelled => Err(cancelled),
+        }
+    }
+}
+
+/// The slot a task writes its output into, plus the waker of whoever is
+/// awaiting the `JoinHandle`.
+struct JoinState<T> {
+    output: Option<Result<T, JoinError>>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+/// Owned by a task's erased future; fills in the `JoinState` when the task
+/// completes, or with `JoinError::Cancelled` if the future is dropped before
+/// then.
+struct Completion<T> {
+    id: TaskId,
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn finish(self, output: Result<T, JoinError>) {
+        self.state.borrow_mut().finish(output);
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        if !state.finished {
+            state.finish(Err(JoinError::Cancelled(self.id)));
+        }
+    }
+}
+
+impl<T> JoinState<T> {
+    fn finish(&mut self, output: Result<T, JoinError>) {
+        self.output = Some(output);
+        self.finished = true;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// Aborts the task; see [`AbortHandle::abort`].
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// Returns a handle that can abort the task without owning the output.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    /// Returns `true` once the task has completed, panicked, or been aborted.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().finished
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.abort.id
+    }
+
+    /// Drops the handle and lets the task run to completion unobserved.
+    /// This is what dropping does anyway; calling it states the intent.
+    pub fn detach(self) {}
+
+    /// Ties the task's lifetime to the handle: the returned guard resolves
+    /// like the handle, but aborts the task if dropped before it finishes.
+    pub fn abort_on_drop(self) -> AbortOnDropGuard<T> {
+        AbortOnDropGuard { handle: self }
+    }
+}
+
+impl<T> AbortOnDropGuard<T> {
+    /// Aborts the task now; see [`AbortHandle::abort`].
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.handle.id()
+    }
+}
+
+impl<T> Drop for AbortOnDropGuard<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl AbortHandle {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Drops the task's future and removes it from the scheduler, resolving
+    /// its `JoinHandle` to `Err(JoinError::Cancelled)`. Does nothing if the task has
+    /// already finished.
+    pub fn abort(&self) {
+        let Some(shared) = self.shared.upgrade() else {
+            return;
+        };
+        let Some(key) = self.key else {
+            return;
+        };
+        let task = shared.borrow_mut().remove_task(key);
+        if task.is_some() {
+            drop(task);
+            shared.borrow_mut().admit_deferred();
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled(id) => f.debug_tuple("Cancelled").field(id).finish(),
+            JoinError::Panic(id, _) => write!(f, "Panic({id:?}, ..)"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled(id) => write!(f, "task {id} was cancelled"),
+            JoinError::Panic(id, _) => write!(f, "task {id} panicked"),
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Future for AbortOnDropGuard<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle).poll(cx)
+    }
+}
+
+/// The data behind every task's `Waker`: waking pushes `key` onto the ready
+/// queue, unless the task is already queued and not yet polled.
+struct TaskWaker {
+    id: TaskId,
+    key: slab::Key,
+    priority: Priority,
+    queued: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
+
+// `Wake` manages the `Arc` refcount behind every clone and drop of the
+// `Waker`, so no hand-written vtable is needed.
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            trace!(task.id = self.id.0, "task woken");
+            self.ready.push(self.key, self.priority);
+        }
+    }
+}
+
+/// Runs the blocking closure `f` on the current runtime's blocking pool; see
+/// [`MiniRuntime::spawn_blocking`].
+///
+/// # Panics
+///
+/// Panics if called outside of `block_on`.
+pub fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    RUNTIME.with(|rt| {
+        rt.borrow()
+            .as_ref()
+            .expect("spawn_blocking called outside of a runtime")
+            .borrow_mut()
+            .spawn_blocking(f)
+    })
+}
+
+/// Spawns a future that need not be `Send` onto the current-thread runtime
+/// or [`LocalSet`] driving this thread; see [`MiniRuntime::spawn`].
+///
+/// # Panics
+///
+/// Panics if called outside of `block_on`.
+pub fn spawn_local<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    RUNTIME.with(|rt| {
+        rt.borrow()
+            .as_ref()
+            .expect("spawn_local called outside of a runtime")
+            .borrow_mut()
+            .spawn(future)
+    })
+}
+
+/// Runs the blocking closure `f` on the thread polling the current task and
+/// returns its result, without the hand-off to another thread that
+/// [`spawn_blocking`] makes.
+///
+/// On a [`MultiThreadRuntime`] worker, the tasks queued on the worker are
+/// first handed to its siblings, so only the calling task waits for `f`.
+/// A current-thread runtime has no one to hand them to: every other task
+/// stalls until `f` returns, which the `tracing` feature reports as a
+/// warning. Keep `f` short there.
+///
+/// ```
+/// use std::{thread, time::Duration};
+/// use mini_rt::Handle;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let (sum, other) = rt.block_on(async {
+///     let other = Handle::current().spawn(async { "still runs" });
+///     let sum = mini_rt::block_in_place(|| {
+///         thread::sleep(Duration::from_millis(5));
+///         2 + 2
+///     });
+///     (sum, other.await.unwrap())
+/// });
+/// assert_eq!((sum, other), (4, "still runs"));
+/// ```
+pub fn block_in_place<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    if !multi_thread::hand_off_queued_tasks() && RUNTIME.with(|rt| rt.borrow().is_some()) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("block_in_place on a current-thread runtime stalls every other task");
+    }
+    f()
+}
+
+/// Gives the other ready tasks a turn before continuing.
+///
+/// The first poll wakes the task, which puts it at the back of the ready
+/// queue, and returns `Pending`; the next poll completes. Tasks that keep
+/// yielding therefore round-robin with everything else that is runnable,
+/// and due timers still fire between their polls.
+pub async fn yield_now() {
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    YieldNow(false).await;
+}
+
+#[macro_export]
+macro_rules! mini_rt {
+    (async fn $name:ident() $body:block) => {
+        fn main() {
+            let mut rt = $crate::MiniRuntime::new();
+            rt.block_on(async $body);
+        }
+    };
+}
+
+/// Polls several futures concurrently and resolves to a tuple of their
+/// outputs once the last one completes.
+///
+/// Each future is boxed and polled only until it finishes; its output is
+/// held until the rest are done. Polling the joined future again once it
+/// has completed is a bug, caught by a debug assertion. Up to eight futures
+/// are supported.
+#[macro_export]
+macro_rules! join_all {
+    // Give each future a binding for itself and one for its output.
+    (@munch [$fut:expr, $($rest:tt)*] [$($done:tt)*] [($f:ident $o:ident) $($names:tt)*]) => {
+        $crate::join_all!(@munch [$($rest)*] [$($done)* ($f $o $fut)] [$($names)*])
+    };
+    (@munch [$($rest:tt)+] [$($done:tt)*] []) => {
+        compile_error!("join_all! supports at most eight futures")
+    };
+    (@munch [] [$(($f:ident $o:ident $fut:expr))+] [$($unused:tt)*]) => {{
+        $(
+            let mut $f = ::std::boxed::Box::pin($fut);
+            let mut $o = ::std::option::Option::None;
+        )+
+        let mut done = false;
+        $crate::future::poll_fn(move |cx| {
+            ::std::debug_assert!(!done, "join_all! polled after completion");
+            if done {
+                return ::std::task::Poll::Pending;
+            }
+            let mut pending = false;
+            $(
+                if $o.is_none() {
+                    match ::std::future::Future::poll($f.as_mut(), cx) {
+                        ::std::task::Poll::Ready(output) => $o = Some(output),
+                        ::std::task::Poll::Pending => pending = true,
+                    }
+                }
+            )+
+            if pending {
+                return ::std::task::Poll::Pending;
+            }
+            done = true;
+            ::std::task::Poll::Ready(($($o.take().unwrap(),)+))
+        })
+    }};
+    ($($fut:expr),+ $(,)?) => {
+        $crate::join_all!(@munch
+            [$($fut,)+]
+            []
+            [(f0 o0) (f1 o1) (f2 o2) (f3 o3) (f4 o4) (f5 o5) (f6 o6) (f7 o7)])
+    };
+}
+
+/// Like [`join_all!`], but for futures yielding `Result<T, E>`: resolves to
+/// `Ok` with a tuple of every `T`, or to the first `Err` as soon as any
+/// future fails, dropping the rest. All futures must share the error type.
+/// As with `join_all!`, polling the joined future again once it has
+/// completed trips a debug assertion.
+#[macro_export]
+macro_rules! try_join {
+    (@munch [$fut:expr, $($rest:tt)*] [$($done:tt)*] [($f:ident $o:ident) $($names:tt)*]) => {
+        $crate::try_join!(@munch [$($rest)*] [$($done)* ($f $o $fut)] [$($names)*])
+    };
+    (@munch [$($rest:tt)+] [$($done:tt)*] []) => {
+        compile_error!("try_join! supports at most eight futures")
+    };
+    (@munch [] [$(($f:ident $o:ident $fut:expr))+] [$($unused:tt)*]) => {{
+        $(
+            let mut $f = ::std::option::Option::Some(::std::boxed::Box::pin($fut));
+            let mut $o = ::std::option::Option::None;
+        )+
+        let mut done = false;
+        $crate::future::poll_fn(move |cx| {
+            ::std::debug_assert!(!done, "try_join! polled after completion");
+            if done {
+                return ::std::task::Poll::Pending;
+            }
+            let mut pending = false;
+            let mut failed = ::std::option::Option::None;
+            $(
+                if failed.is_none() && let Some(future) = &mut $f {
+                    match ::std::future::Future::poll(future.as_mut(), cx) {
+                        ::std::task::Poll::Ready(Ok(output)) => {
+                            $o = Some(output);
+                            $f = None;
+                        }
+                        ::std::task::Poll::Ready(Err(error)) => failed = Some(error),
+                        ::std::task::Poll::Pending => pending = true,
+                    }
+                }
+            )+
+            if let Some(error) = failed {
+                // Drop the futures still running right away.
+                $($f = None;)+
+                done = true;
+                return ::std::task::Poll::Ready(Err(error));
+            }
+            if pending {
+                return ::std::task::Poll::Pending;
+            }
+            done = true;
+            ::std::task::Poll::Ready(Ok(($($o.take().unwrap(),)+)))
+        })
+    }};
+    ($($fut:expr),+ $(,)?) => {
+        $crate::try_join!(@munch
+            [$($fut,)+]
+            []
+            [(f0 o0) (f1 o1) (f2 o2) (f3 o3) (f4 o4) (f5 o5) (f6 o6) (f7 o7)])
+    };
+}
+
+/// Waits on several futures at once and runs the arm of whichever completes
+/// first, dropping the rest.
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::sleep;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     mini_rt::select! {
+///         _ = sleep(Duration::from_millis(10)) => println!("fast"),
+///         _ = sleep(Duration::from_millis(20)) => println!("slow"),
+///     }
+/// });
+/// ```
+///
+/// Each poll starts from a randomly chosen branch, so a branch that is
+/// always ready cannot starve the others. Starting with `biased;` polls the
+/// branches top to bottom instead, giving earlier branches priority:
+///
+/// ```
+/// use mini_rt::future::ready;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// for _ in 0..10 {
+///     let winner = rt.block_on(async {
+///         mini_rt::select! {
+///             biased;
+///             a = ready("first") => a,
+///             b = ready("second") => b,
+///         }
+///     });
+///     assert_eq!(winner, "first");
+/// }
+/// ```
+///
+/// Each branch future is pinned on the stack so it stays in place between
+/// polls. Up to eight branches are supported.
+#[macro_export]
+macro_rules! select {
+    // Pair each branch with a variant, type parameter, binding name and
+    // index.
+    (@munch $biased:literal
+        [($pat:pat, $fut:expr, $body:expr) $($rest:tt)*]
+        [$($done:tt)*]
+        [($v:ident $t:ident $f:ident $i:literal) $($names:tt)*]) => {
+        $crate::select!(@munch $biased
+            [$($rest)*]
+            [$($done)* ($v $t $f $i $pat, $fut, $body)]
+            [$($names)*])
+    };
+    (@munch $biased:literal [$($rest:tt)+] [$($done:tt)*] []) => {
+        compile_error!("select! supports at most eight branches")
+    };
+    (@munch $biased:literal
+        []
+        [$(($v:ident $t:ident $f:ident $i:literal $pat:pat, $fut:expr, $body:expr))+]
+        [$($unused:tt)*]) => {{
+        enum __SelectOutput<$($t),+> {
+            $($v($t)),+
+        }
+
+        let __output = {
+            $(let mut $f = ::std::pin::pin!($fut);)+
+            const __BRANCHES: usize = [$($i),+].len();
+            let mut __done = false;
+            $crate::future::poll_fn(|cx| {
+                ::std::debug_assert!(!__done, "select! polled after completion");
+                let __start = if $biased {
+                    0
+                } else {
+                    $crate::__private::random_below(__BRANCHES)
+                };
+                for __offset in 0..__BRANCHES {
+                    let __branch = (__start + __offset) % __BRANCHES;
+                    $(
+                        if __branch == $i
+                            && let ::std::task::Poll::Ready(output) =
+                                ::std::future::Future::poll($f.as_mut(), cx)
+                        {
+                            __done = true;
+                            return ::std::task::Poll::Ready(__SelectOutput::$v(output));
+                        }
+                    )+
+                }
+                ::std::task::Poll::Pending
+            })
+            .await
+        };
+
+        match __output {
+            $(__SelectOutput::$v($pat) => $body,)+
+        }
+    }};
+    (@start $biased:literal $($pat:pat = $fut:expr => $body:expr),+ $(,)?) => {
+        $crate::select!(@munch $biased
+            [$(($pat, $fut, $body))+]
+            []
+            [(B0 T0 f0 0) (B1 T1 f1 1) (B2 T2 f2 2) (B3 T3 f3 3)
+             (B4 T4 f4 4) (B5 T5 f5 5) (B6 T6 f6 6) (B7 T7 f7 7)])
+    };
+    (biased; $($branches:tt)+) => {
+        $crate::select!(@start true $($branches)+)
+    };
+    ($($branches:tt)+) => {
+        $crate::select!(@start false $($branches)+)
+    };
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use std::{
+        cell::Cell,
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+
+    /// Support for macro expansions; not part of the public API. Returns a
+    /// pseudo-random index below `n` from a per-thread xorshift generator.
+    pub fn random_below(n: usize) -> usize {
+        thread_local! {
+            static STATE: Cell<u64> = Cell::new(
+                RandomState::new().build_hasher().finish() | 1,
+            );
+        }
+        STATE.with(|state| {
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+            (x % n as u64) as usize
+        })
+    }
+}
@@ -0,0 +1,119 @@
+//! A set of `!Send` tasks pinned to one thread.
+
+use std::{cell::RefCell, future::Future};
+
+use crate::{Handle, JoinHandle, MiniRuntime};
+
+/// Tasks that need not be `Send`, all polled on the thread that drives the
+/// set.
+///
+/// A [`MultiThreadRuntime`](crate::MultiThreadRuntime) only accepts `Send`
+/// futures, since any worker may poll them. Tasks holding an `Rc` or a
+/// `RefCell` go in a `LocalSet` instead, and can still hand `Send` work to
+/// the pool and await its `JoinHandle`. The set is a current-thread
+/// runtime underneath, so timers, sockets and [`spawn_local`] all work
+/// inside it.
+///
+/// [`spawn_local`]: crate::spawn_local
+///
+/// ```
+/// use std::{cell::Cell, rc::Rc};
+///
+/// let pool = mini_rt::MiniRuntime::multi_thread(2);
+/// let local = mini_rt::LocalSet::new();
+/// let hits = Rc::new(Cell::new(0));
+/// let counter = hits.clone();
+/// local.spawn_local(async move { counter.set(counter.get() + 1) });
+/// let sum = local.block_on(async move {
+///     let counter = hits.clone();
+///     mini_rt::spawn_local(async move { counter.set(counter.get() + 1) })
+///         .await
+///         .unwrap();
+///     let sum = pool.spawn(async { 40 + 2 }).await.unwrap();
+///     assert_eq!(hits.get(), 2);
+///     sum
+/// });
+/// assert_eq!(sum, 42);
+/// ```
+pub struct LocalSet {
+    runtime: RefCell<MiniRuntime>,
+    /// Spawns through the shared state directly, so tasks can be added
+    /// while `block_on` holds the runtime.
+    handle: Handle,
+}
+
+impl LocalSet {
+    pub fn new() -> Self {
+        let runtime = MiniRuntime::new();
+        let handle = runtime.handle();
+        Self {
+            runtime: RefCell::new(runtime),
+            handle,
+        }
+    }
+
+    /// Adds `future` to the set. It first runs once the set is driven by
+    /// [`block_on`](LocalSet::block_on), or right away if it already is.
+    pub fn spawn_local<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.handle.spawn(future)
+    }
+
+    /// Runs `future` on this thread, along with every task in the set, until
+    /// all of them have finished, and returns its output; see
+    /// [`MiniRuntime::block_on`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set is already being driven.
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.runtime
+            .try_borrow_mut()
+            .expect("LocalSet::block_on called while the set is already being driven")
+            .block_on(future)
+    }
+}
+
+impl Default for LocalSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn rc_holding_tasks_run_and_return_their_output() {
+        let local = LocalSet::new();
+        let shared = Rc::new(vec![1, 2, 3]);
+        let early = {
+            let shared = shared.clone();
+            local.spawn_local(async move { shared.iter().sum::<i32>() })
+        };
+        let (early, late) = local.block_on(async move {
+            let held = shared.clone();
+            let late = crate::spawn_local(async move {
+                crate::yield_now().await;
+                Rc::strong_count(&held)
+            })
+            .await
+            .unwrap();
+            (early.await.unwrap(), late)
+        });
+        assert_eq!(early, 6);
+        // The early task's clone was dropped when it finished; the root's
+        // `shared` and the late task's `held` remain.
+        assert_eq!(late, 2);
+    }
+}
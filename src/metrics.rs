@@ -0,0 +1,17 @@
+//! Counters describing what a runtime's scheduler has done.
+
+/// A snapshot of a runtime's scheduler counters; see
+/// [`MiniRuntime::metrics`](crate::MiniRuntime::metrics).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuntimeMetrics {
+    /// Tasks spawned since the runtime was created, including `block_on`
+    /// roots and `spawn_blocking` tasks.
+    pub total_spawned: u64,
+    /// Times any task has been polled.
+    pub total_polls: u64,
+    /// Tasks that have been spawned and have not finished yet.
+    pub pending_tasks: usize,
+    /// Timers scheduled but not yet fired, leaving out those cancelled by
+    /// dropping their future.
+    pub pending_timers: usize,
+}
@@ -0,0 +1,450 @@
+//! A work-stealing executor that spreads `Send` tasks across worker threads.
+//!
+//! This sits beside the single-threaded [`MiniRuntime`](crate::MiniRuntime)
+//! rather than replacing it: tasks here must be `Send + 'static`, so their
+//! state lives behind `Arc`/`Mutex` instead of `Rc`/`RefCell`. Code that
+//! needs `!Send` futures keeps using the current-thread runtime.
+//!
+//! Each worker owns a deque. Tasks woken on a worker go to the back of its
+//! own deque; tasks woken from anywhere else go to a shared injector queue.
+//! A worker pops from the front of its own deque first, then the injector,
+//! and only then steals from the back of another worker's deque, so workers
+//! mostly touch their own lock. Workers with nothing to do sleep on a
+//! condvar that every push signals.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::{Pin, pin},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    Builder, JoinError, MultiThread, TaskId,
+    blocking::BlockingPool,
+    coop,
+    future::poll_fn,
+    time::{Clock, TimerEntry, Timers},
+};
+
+thread_local! {
+    /// The pool a worker thread belongs to and its index in it, so wakes and
+    /// timers from its tasks land in that worker's deque and pool's heap.
+    static WORKER: RefCell<Option<(Arc<Inner>, usize)>> = const { RefCell::new(None) };
+}
+
+/// A runtime that polls tasks on `num_workers` threads; see
+/// [`MiniRuntime::multi_thread`](crate::MiniRuntime::multi_thread).
+///
+/// Dropping the runtime stops the workers; tasks that have not finished are
+/// dropped and their handles resolve to `Err(JoinError::Cancelled)`.
+pub struct MultiThreadRuntime {
+    inner: Arc<Inner>,
+    workers: Vec<thread::JoinHandle<()>>,
+    blocking: BlockingPool,
+}
+
+struct Inner {
+    /// One run queue per worker, indexed like the worker threads.
+    locals: Vec<Mutex<VecDeque<Arc<Task>>>>,
+    /// Tasks woken from outside the pool.
+    injector: Mutex<VecDeque<Arc<Task>>>,
+    /// Held while deciding to sleep and while signalling, so a push cannot
+    /// land between a worker's last look at the queues and its wait.
+    sleep: Mutex<()>,
+    condvar: Condvar,
+    timers: Mutex<Timers>,
+    clock: Arc<dyn Clock>,
+    /// Number of spawned tasks that have not finished yet.
+    live: Mutex<usize>,
+    all_done: Condvar,
+    shutdown: AtomicBool,
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Task {
+    /// `None` once the task has completed. The lock also keeps two workers
+    /// from polling the task at once if it is woken mid-poll.
+    future: Mutex<Option<BoxFuture>>,
+    queued: AtomicBool,
+    inner: Arc<Inner>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            self.inner.push(self.clone());
+        }
+    }
+}
+
+impl MultiThreadRuntime {
+    pub(crate) fn new(builder: &Builder<MultiThread>) -> Self {
+        let num_workers = builder.worker_threads;
+        let inner = Arc::new(Inner {
+            locals: (0..num_workers)
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+            injector: Mutex::new(VecDeque::new()),
+            sleep: Mutex::new(()),
+            condvar: Condvar::new(),
+            timers: Mutex::new(Timers::new(
+                builder.timer_wheel.then(|| builder.clock.now()),
+            )),
+            clock: builder.clock.clone(),
+            live: Mutex::new(0),
+            all_done: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        });
+        let workers = (0..num_workers)
+            .map(|index| {
+                let inner = inner.clone();
+                thread::Builder::new()
+                    .name(match &builder.thread_name {
+                        Some(name) => name.clone(),
+                        None => format!("mini-rt-worker-{index}"),
+                    })
+                    .spawn(move || inner.run_worker(index))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+        Self {
+            inner,
+            workers,
+            blocking: BlockingPool::new(builder.max_blocking_threads, builder.thread_name.clone()),
+        }
+    }
+
+    pub fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Schedules `future` on the worker pool and returns a handle that
+    /// resolves to its output.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.inner.spawn(future)
+    }
+
+    /// Runs the blocking closure `f` on a pool of at most
+    /// `max_blocking_threads` background threads, so it never holds up a
+    /// worker. A panic in `f` resolves the handle to a [`JoinError`].
+    pub fn spawn_blocking<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.inner.spawn(self.blocking.run(f))
+    }
+
+    /// Runs `future` on the worker pool and blocks the calling thread until
+    /// it and every other task have finished, returning its output. A panic
+    /// in `future` is resumed here.
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        assert!(
+            WORKER.with(|worker| worker.borrow().is_none()),
+            "cannot call block_on from within a runtime: this is one of its worker threads"
+        );
+        let root = self.inner.spawn(future);
+        let mut live = self.inner.live.lock().unwrap();
+        while *live > 0 {
+            live = self.inner.all_done.wait(live).unwrap();
+        }
+        drop(live);
+        match root.state.lock().unwrap().output.take() {
+            Some(Ok(output)) => output,
+            Some(Err(JoinError::Panic(_, payload))) => panic::resume_unwind(payload),
+            _ => unreachable!("root task finished without an output"),
+        }
+    }
+}
+
+impl Drop for MultiThreadRuntime {
+    fn drop(&mut self) {
+        self.inner.shutdown.store(true, Ordering::SeqCst);
+        self.inner.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        // Drop whatever never got to finish; this resolves their handles.
+        for queue in self.inner.locals.iter().chain([&self.inner.injector]) {
+            let queued = std::mem::take(&mut *queue.lock().unwrap());
+            for task in queued {
+                task.future.lock().unwrap().take();
+            }
+        }
+        self.inner.timers.lock().unwrap().clear();
+    }
+}
+
+impl Inner {
+    fn spawn<F>(self: &Arc<Self>, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(JoinState {
+            output: None,
+            waker: None,
+        }));
+        let id = TaskId::next();
+        let completion = Completion {
+            id,
+            state: state.clone(),
+        };
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(async move {
+                let mut future = pin!(future);
+                let output = poll_fn(|cx| {
+                    match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+                        Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+                        Ok(Poll::Pending) => Poll::Pending,
+                        Err(payload) => Poll::Ready(Err(JoinError::Panic(id, payload))),
+                    }
+                })
+                .await;
+                completion.finish(output);
+            }))),
+            queued: AtomicBool::new(false),
+            inner: self.clone(),
+        });
+        *self.live.lock().unwrap() += 1;
+        task.wake_by_ref();
+        JoinHandle { id, state }
+    }
+
+    fn run_worker(self: Arc<Self>, index: usize) {
+        WORKER.with(|worker| *worker.borrow_mut() = Some((self.clone(), index)));
+        while let Some(task) = self.next_task(index) {
+            task.queued.store(false, Ordering::SeqCst);
+            let waker = Waker::from(task.clone());
+            let mut slot = task.future.lock().unwrap();
+            let Some(future) = slot.as_mut() else {
+                continue;
+            };
+            let poll = coop::budget(|| future.as_mut().poll(&mut Context::from_waker(&waker)));
+            if poll.is_ready() {
+                *slot = None;
+                drop(slot);
+                let mut live = self.live.lock().unwrap();
+                *live -= 1;
+                if *live == 0 {
+                    self.all_done.notify_all();
+                }
+            }
+        }
+        WORKER.with(|worker| *worker.borrow_mut() = None);
+    }
+
+    /// Queues a woken task: on the waking worker's own deque if the wake
+    /// comes from one of our workers, on the injector otherwise.
+    fn push(self: &Arc<Self>, task: Arc<Task>) {
+        let local = WORKER.with(|worker| match &*worker.borrow() {
+            Some((inner, index)) if Arc::ptr_eq(inner, self) => Some(*index),
+            _ => None,
+        });
+        match local {
+            Some(index) => self.locals[index].lock().unwrap().push_back(task),
+            None => self.injector.lock().unwrap().push_back(task),
+        }
+        let _sleep = self.sleep.lock().unwrap();
+        self.condvar.notify_one();
+    }
+
+    fn notify_all(&self) {
+        let _sleep = self.sleep.lock().unwrap();
+        self.condvar.notify_all();
+    }
+
+    /// Takes the next task for worker `index`: its own deque first, then the
+    /// injector, then the back of the other workers' deques.
+    fn find_task(&self, index: usize) -> Option<Arc<Task>> {
+        if let Some(task) = self.locals[index].lock().unwrap().pop_front() {
+            return Some(task);
+        }
+        if let Some(task) = self.injector.lock().unwrap().pop_front() {
+            return Some(task);
+        }
+        let workers = self.locals.len();
+        (1..workers).find_map(|offset| {
+            self.locals[(index + offset) % workers]
+                .lock()
+                .unwrap()
+                .pop_back()
+        })
+    }
+
+    /// Waits for a runnable task for worker `index`, firing due timers
+    /// meanwhile. Returns `None` once the runtime is shutting down.
+    fn next_task(&self, index: usize) -> Option<Arc<Task>> {
+        loop {
+            if self.shutdown.load(Ordering::SeqCst) {
+                return None;
+            }
+            self.fire_timers();
+            if let Some(task) = self.find_task(index) {
+                return Some(task);
+            }
+            // Look once more with the sleep lock held: pushes signal under
+            // it, so anything queued after this check will wake us.
+            let sleep = self.sleep.lock().unwrap();
+            if self.shutdown.load(Ordering::SeqCst) {
+                return None;
+            }
+            if let Some(task) = self.find_task(index) {
+                return Some(task);
+            }
+            let deadline = self.timers.lock().unwrap().next_deadline();
+            match deadline {
+                Some(when) => {
+                    let timeout = when.saturating_duration_since(self.clock.now());
+                    drop(self.condvar.wait_timeout(sleep, timeout).unwrap());
+                }
+                None => drop(self.condvar.wait(sleep).unwrap()),
+            }
+        }
+    }
+
+    fn fire_timers(&self) {
+        let now = self.clock.now();
+        let mut due = Vec::new();
+        let mut timers = self.timers.lock().unwrap();
+        while let Some(waker) = timers.pop_due(now) {
+            due.push(waker);
+        }
+        // Waking takes the queue locks, so do it after releasing the timers.
+        drop(timers);
+        for waker in due {
+            waker.wake();
+        }
+    }
+
+    fn schedule_timer(&self, when: Instant, waker: Waker) -> Arc<TimerEntry> {
+        let entry = self.timers.lock().unwrap().push(when, waker);
+        // Let idle workers recompute how long to wait.
+        self.notify_all();
+        entry
+    }
+}
+
+/// Registers a timer with the pool driving this thread, if it is a worker,
+/// returning its timer entry. Returns `None` when called from any other
+/// thread.
+pub(crate) fn schedule_timer(when: Instant, waker: &Waker) -> Option<Arc<TimerEntry>> {
+    WORKER.with(|worker| {
+        let (inner, _) = worker.borrow().clone()?;
+        Some(inner.schedule_timer(when, waker.clone()))
+    })
+}
+
+/// Returns the clock of the pool driving this thread, if it is a worker.
+pub(crate) fn current_clock() -> Option<Arc<dyn Clock>> {
+    WORKER.with(|worker| Some(worker.borrow().as_ref()?.0.clock.clone()))
+}
+
+/// Fires the due timers of the pool driving this thread, if it is a worker.
+pub(crate) fn fire_due_timers() {
+    let inner = WORKER.with(|worker| Some(worker.borrow().as_ref()?.0.clone()));
+    if let Some(inner) = inner {
+        inner.fire_timers();
+    }
+}
+
+/// Moves the tasks queued on the worker running on this thread to the
+/// injector, so that idle siblings take them while this worker is blocked.
+/// Returns `false` when called from any other thread.
+pub(crate) fn hand_off_queued_tasks() -> bool {
+    let Some((inner, index)) = WORKER.with(|worker| worker.borrow().clone()) else {
+        return false;
+    };
+    let queued = std::mem::take(&mut *inner.locals[index].lock().unwrap());
+    if !queued.is_empty() {
+        inner.injector.lock().unwrap().extend(queued);
+        inner.notify_all();
+    }
+    true
+}
+
+/// A handle to a task on a [`MultiThreadRuntime`], resolving to its output.
+/// Unlike [`crate::JoinHandle`] it is `Send`, so it can be awaited from any
+/// worker.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+struct JoinState<T> {
+    output: Option<Result<T, JoinError>>,
+    waker: Option<Waker>,
+}
+
+/// Fills in the `JoinState` when the task completes, or with
+/// `JoinError::Cancelled` if its future is dropped before then.
+struct Completion<T> {
+    id: TaskId,
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn finish(self, output: Result<T, JoinError>) {
+        self.state.lock().unwrap().output = Some(output);
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        if state.output.is_none() {
+            state.output = Some(Err(JoinError::Cancelled(self.id)));
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns `true` once the task has completed, panicked, or been dropped.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().output.is_some()
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Non-blocking sockets for tasks on a [`MiniRuntime`](crate::MiniRuntime).
+//!
+//! Each socket wraps its std counterpart in non-blocking mode. An operation
+//! that would block parks the task on the runtime's reactor until the socket
+//! is ready, so other tasks keep running. Sockets can be created anywhere,
+//! but their async operations must run inside the current-thread runtime's
+//! `block_on`.
+//!
+//! [`TcpStream`] implements [`AsyncRead`](crate::io::AsyncRead) and
+//! [`AsyncWrite`](crate::io::AsyncWrite). [`UdpSocket`] deals in whole
+//! datagrams rather than a byte stream, so it keeps to `send_to` and
+//! `recv_from`.
+
+mod tcp;
+mod udp;
+
+pub use tcp::{TcpListener, TcpStream};
+pub use udp::UdpSocket;
@@ -0,0 +1,133 @@
+use std::{
+    io::{self, Read, Write},
+    net::{self, Shutdown, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    blocking,
+    io::{AsyncRead, AsyncWrite},
+    reactor::{Interest, Source},
+};
+
+/// A TCP socket listening for incoming connections.
+pub struct TcpListener {
+    source: Source<net::TcpListener>,
+}
+
+/// A TCP connection between a local and a remote socket.
+pub struct TcpStream {
+    source: Source<net::TcpStream>,
+}
+
+impl TcpListener {
+    /// Binds a listener to `addr`. Binding never blocks, so this is not async.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            source: Source::new(listener),
+        })
+    }
+
+    /// Waits for the next incoming connection, returning it along with the
+    /// peer's address.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self
+            .source
+            .io(Interest::Read, |listener| listener.accept())
+            .await?;
+        Ok((TcpStream::from_std(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.source.get_ref().local_addr()
+    }
+}
+
+impl TcpStream {
+    /// Opens a connection to `addr`, trying each resolved address in turn.
+    ///
+    /// std offers no non-blocking connect, so the handshake runs on the
+    /// blocking pool. Resolving a host name still happens on the calling
+    /// thread.
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        let stream = blocking::run(move || net::TcpStream::connect(&addrs[..])).await?;
+        Self::from_std(stream)
+    }
+
+    fn from_std(stream: net::TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            source: Source::new(stream),
+        })
+    }
+
+    /// Reads some bytes into `buf`, returning how many were read. `Ok(0)`
+    /// means the peer closed its end.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.source
+            .io(Interest::Read, |mut stream| stream.read(buf))
+            .await
+    }
+
+    /// Writes some bytes from `buf`, returning how many were written.
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.source
+            .io(Interest::Write, |mut stream| stream.write(buf))
+            .await
+    }
+
+    /// Writes all of `buf`, waiting for room as often as needed.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.source.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.source.get_ref().peer_addr()
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.source
+            .poll_io(cx, Interest::Read, |mut stream| stream.read(buf))
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.source
+            .poll_io(cx, Interest::Write, |mut stream| stream.write(buf))
+    }
+
+    /// Writes go straight to the socket, so there is nothing to flush.
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Shuts down the writing half of the connection.
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.source.get_ref().shutdown(Shutdown::Write))
+    }
+}
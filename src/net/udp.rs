@@ -0,0 +1,79 @@
+use std::{
+    io,
+    net::{self, SocketAddr, ToSocketAddrs},
+};
+
+use crate::reactor::{Interest, Source};
+
+/// A UDP socket for sending and receiving datagrams.
+pub struct UdpSocket {
+    source: Source<net::UdpSocket>,
+}
+
+impl UdpSocket {
+    /// Binds a socket to `addr`. Binding never blocks, so this is not async.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let socket = net::UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            source: Source::new(socket),
+        })
+    }
+
+    /// Sends `buf` as one datagram to `target`, returning how many bytes
+    /// were sent.
+    pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> io::Result<usize> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        self.source
+            .io(Interest::Write, |socket| socket.send_to(buf, target))
+            .await
+    }
+
+    /// Waits for a datagram, copies it into `buf`, and returns its length
+    /// and sender. Bytes that do not fit in `buf` are discarded.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.source
+            .io(Interest::Read, |socket| socket.recv_from(buf))
+            .await
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.source.get_ref().local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Handle, MiniRuntime};
+
+    #[test]
+    fn datagram_arrives_with_its_payload_and_source() {
+        let mut rt = MiniRuntime::new();
+        let received = rt.block_on_timeout(
+            async {
+                let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let (to, from) = (receiver.local_addr().unwrap(), sender.local_addr().unwrap());
+                // The receive parks on the reactor before anything is sent.
+                let recv = Handle::current().spawn(async move {
+                    let mut buf = [0; 64];
+                    let (len, source) = receiver.recv_from(&mut buf).await.unwrap();
+                    (buf[..len].to_vec(), source)
+                });
+                crate::yield_now().await;
+                assert_eq!(sender.send_to(b"ping", to).await.unwrap(), 4);
+                let (payload, source) = recv.await.unwrap();
+                assert_eq!(source, from);
+                payload
+            },
+            Duration::from_secs(5),
+        );
+        assert_eq!(received.unwrap(), b"ping");
+    }
+}
@@ -0,0 +1,166 @@
+//! A single-use channel for sending one value between tasks.
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Creates a oneshot channel, returning the sending and receiving halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Rc::new(RefCell::new(State {
+        value: None,
+        waker: None,
+        sender_alive: true,
+        receiver_alive: true,
+    }));
+    (
+        Sender {
+            state: state.clone(),
+        },
+        Receiver { state },
+    )
+}
+
+/// The sending half of a oneshot channel.
+pub struct Sender<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// The receiving half of a oneshot channel, resolving to the sent value.
+///
+/// Resolves to `Err(Canceled)` if the `Sender` is dropped without sending.
+pub struct Receiver<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// Error returned by a `Receiver` whose `Sender` was dropped without sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled;
+
+struct State<T> {
+    value: Option<T>,
+    /// The waker of a receiver that polled before the value arrived.
+    waker: Option<Waker>,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+impl<T> Sender<T> {
+    /// Sends `value` to the receiver, waking it if it is waiting.
+    ///
+    /// Returns the value back if the receiver has already been dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = self.state.borrow_mut();
+        if !state.receiver_alive {
+            return Err(value);
+        }
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.state.borrow().receiver_alive
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.sender_alive = false;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(Ok(value))
+        } else if !state.sender_alive {
+            Poll::Ready(Err(Canceled))
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.state.borrow_mut().receiver_alive = false;
+    }
+}
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("oneshot sender dropped without sending")
+    }
+}
+
+impl Error for Canceled {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sleep};
+
+    #[test]
+    fn a_value_sent_before_the_await_is_received() {
+        let mut rt = MiniRuntime::new();
+        let (tx, rx) = channel();
+        tx.send("early").unwrap();
+        assert_eq!(rt.block_on(rx), Ok("early"));
+    }
+
+    #[test]
+    fn a_waiting_receiver_is_woken_by_the_send() {
+        let mut rt = MiniRuntime::new();
+        let received = rt.block_on(async {
+            let (tx, rx) = channel();
+            Handle::current().spawn(async move {
+                sleep(Duration::from_millis(5)).await;
+                tx.send(42).unwrap();
+            });
+            rx.await
+        });
+        assert_eq!(received, Ok(42));
+    }
+
+    #[test]
+    fn dropping_the_sender_closes_the_receiver() {
+        let mut rt = MiniRuntime::new();
+        let received = rt.block_on(async {
+            let (tx, rx) = channel::<u8>();
+            Handle::current().spawn(async move {
+                sleep(Duration::from_millis(5)).await;
+                drop(tx);
+            });
+            rx.await
+        });
+        assert_eq!(received, Err(Canceled));
+    }
+
+    #[test]
+    fn sending_to_a_dropped_receiver_returns_the_value() {
+        let (tx, rx) = channel();
+        assert!(!tx.is_closed());
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(String::from("unread")), Err(String::from("unread")));
+    }
+}
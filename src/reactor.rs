@@ -0,0 +1,481 @@
+//! Readiness-based I/O driver for the current-thread runtime.
+//!
+//! `block_on` hands the reactor control whenever no task is runnable but some
+//! task is waiting on a socket, blocking for at most the time until the next
+//! timer, and also gives it a non-blocking turn every so often while tasks
+//! are busy, so sockets are not starved by tasks that keep waking each other.
+//!
+//! Readiness is tracked per socket as edge-style flags: a turn sets a flag
+//! when the driver reports the socket ready and wakes the waiting task, and
+//! the flag is only cleared when an operation on the socket returns
+//! `WouldBlock`. The OS-facing part lives in a driver module: `poll(2)` by
+//! default, or mio with the `mio` feature.
+//!
+//! Wakers fired from other threads cannot unpark a thread stuck in the
+//! driver, so the ready queue pokes the driver through a [`Notifier`] while
+//! the reactor is polling.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::Future,
+    io,
+    os::fd::{AsRawFd, RawFd},
+    pin::Pin,
+    rc::Weak,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+#[cfg(feature = "mio")]
+mod mio_driver;
+#[cfg(not(feature = "mio"))]
+mod poll_driver;
+
+#[cfg(feature = "mio")]
+use mio_driver::Driver;
+#[cfg(feature = "mio")]
+pub(crate) use mio_driver::Notifier;
+#[cfg(not(feature = "mio"))]
+use poll_driver::Driver;
+#[cfg(not(feature = "mio"))]
+pub(crate) use poll_driver::Notifier;
+
+use crate::{RUNTIME, Shared};
+
+/// Which kind of readiness a task is waiting for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Interest {
+    Read,
+    Write,
+}
+
+pub(crate) struct Reactor {
+    sources: HashMap<usize, ScheduledIo>,
+    next_key: usize,
+    driver: Driver,
+}
+
+/// Readiness state of one registered socket.
+struct ScheduledIo {
+    fd: RawFd,
+    readable: bool,
+    writable: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+/// A readiness change reported by a driver turn.
+struct Event {
+    key: usize,
+    readable: bool,
+    writable: bool,
+}
+
+impl ScheduledIo {
+    fn is_waiting(&self) -> bool {
+        self.read_waker.is_some() || self.write_waker.is_some()
+    }
+}
+
+impl Reactor {
+    /// Creates a reactor and the notifier wakers use to interrupt it.
+    pub(crate) fn new() -> (Self, Notifier) {
+        let (driver, notifier) = Driver::new().expect("failed to create the I/O reactor");
+        let reactor = Self {
+            sources: HashMap::new(),
+            next_key: 0,
+            driver,
+        };
+        (reactor, notifier)
+    }
+
+    /// Returns `true` if no task is waiting on I/O.
+    pub(crate) fn is_idle(&self) -> bool {
+        !self.sources.values().any(ScheduledIo::is_waiting)
+    }
+
+    fn add(&mut self, fd: RawFd) -> io::Result<usize> {
+        let key = self.next_key;
+        self.driver.add(fd, key)?;
+        self.next_key += 1;
+        self.sources.insert(
+            key,
+            ScheduledIo {
+                fd,
+                readable: false,
+                writable: false,
+                read_waker: None,
+                write_waker: None,
+            },
+        );
+        Ok(key)
+    }
+
+    fn remove(&mut self, key: usize) {
+        if let Some(io) = self.sources.remove(&key) {
+            self.driver.remove(io.fd);
+        }
+    }
+
+    /// Forgets that source `key` was ready for `interest`, after an
+    /// operation on it returned `WouldBlock`.
+    fn clear_ready(&mut self, key: usize, interest: Interest) {
+        let io = self.sources.get_mut(&key).expect("unregistered I/O source");
+        match interest {
+            Interest::Read => io.readable = false,
+            Interest::Write => io.writable = false,
+        }
+    }
+
+    /// Drops the waker left for `interest` on source `key` by a wait that
+    /// was cancelled, so the source no longer counts as waited on.
+    fn clear_waker(&mut self, key: usize, interest: Interest) {
+        if let Some(io) = self.sources.get_mut(&key) {
+            match interest {
+                Interest::Read => io.read_waker = None,
+                Interest::Write => io.write_waker = None,
+            }
+        }
+    }
+
+    fn poll_ready(&mut self, key: usize, interest: Interest, cx: &mut Context<'_>) -> Poll<()> {
+        let io = self.sources.get_mut(&key).expect("unregistered I/O source");
+        let (ready, waker) = match interest {
+            Interest::Read => (io.readable, &mut io.read_waker),
+            Interest::Write => (io.writable, &mut io.write_waker),
+        };
+        if ready {
+            *waker = None;
+            return Poll::Ready(());
+        }
+        match waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+
+    /// Blocks until a source is ready, the notifier fires, or `timeout`
+    /// elapses, then wakes the tasks waiting on the sources that are ready.
+    pub(crate) fn turn(&mut self, timeout: Option<Duration>) {
+        let mut woken = Vec::new();
+        for event in self.driver.wait(&self.sources, timeout) {
+            let Some(io) = self.sources.get_mut(&event.key) else {
+                continue;
+            };
+            if event.readable {
+                io.readable = true;
+                woken.extend(io.read_waker.take());
+            }
+            if event.writable {
+                io.writable = true;
+                woken.extend(io.write_waker.take());
+            }
+        }
+        for waker in woken {
+            waker.wake();
+        }
+    }
+}
+
+/// A non-blocking std socket whose operations wait on the current runtime's
+/// reactor instead of blocking the thread.
+///
+/// The socket is registered with the reactor of whichever runtime first has
+/// an operation on it block, so it can be created outside of `block_on`.
+pub(crate) struct Source<T: AsRawFd> {
+    io: T,
+    registration: Cell<Option<Registration>>,
+}
+
+/// Removes a source from its reactor when dropped.
+struct Registration {
+    key: usize,
+    shared: Weak<RefCell<Shared>>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade()
+            && let Ok(mut shared) = shared.try_borrow_mut()
+        {
+            shared.reactor.remove(self.key);
+        }
+    }
+}
+
+impl<T: AsRawFd> Source<T> {
+    /// Wraps `io`, which must already be in non-blocking mode.
+    pub(crate) fn new(io: T) -> Self {
+        Self {
+            io,
+            registration: Cell::new(None),
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Runs `op` until it stops returning `WouldBlock`, waiting for
+    /// `interest` on the reactor in between.
+    pub(crate) async fn io<R>(
+        &self,
+        interest: Interest,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> io::Result<R> {
+        loop {
+            match op(&self.io) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    Readiness {
+                        source: self,
+                        interest,
+                        cleared: false,
+                        waiting: false,
+                    }
+                    .await?
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Polls `op` until it stops returning `WouldBlock` or the source is not
+    /// ready for `interest`, in which case the task is woken once it is.
+    /// The poll-based counterpart of [`io`](Source::io).
+    pub(crate) fn poll_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            match op(&self.io) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    match self.poll_ready(interest, true, cx) {
+                        Ok(Poll::Ready(())) => continue,
+                        Ok(Poll::Pending) => return Poll::Pending,
+                        Err(err) => return Poll::Ready(Err(err)),
+                    }
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+
+    /// Checks the current runtime's reactor for readiness, first forgetting
+    /// the readiness it had if `clear` is set.
+    fn poll_ready(
+        &self,
+        interest: Interest,
+        clear: bool,
+        cx: &mut Context<'_>,
+    ) -> io::Result<Poll<()>> {
+        RUNTIME.with(|rt| {
+            let rt = rt.borrow();
+            let mut shared = rt
+                .as_ref()
+                .expect("socket I/O used outside of a MiniRuntime")
+                .borrow_mut();
+            let key = self.key(&mut shared)?;
+            if clear {
+                shared.reactor.clear_ready(key, interest);
+            }
+            Ok(shared.reactor.poll_ready(key, interest, cx))
+        })
+    }
+
+    /// Forgets the waker a cancelled wait for `interest` left behind.
+    fn cancel_wait(&self, interest: Interest) {
+        let Some(registration) = self.registration.take() else {
+            return;
+        };
+        // The runtime may be mid-turn, or gone along with its reactor.
+        if let Some(shared) = registration.shared.upgrade()
+            && let Ok(mut shared) = shared.try_borrow_mut()
+        {
+            shared.reactor.clear_waker(registration.key, interest);
+        }
+        self.registration.set(Some(registration));
+    }
+
+    /// Returns this source's key in the current runtime's reactor,
+    /// registering it first if needed.
+    fn key(&self, shared: &mut Shared) -> io::Result<usize> {
+        let registration = match self.registration.take() {
+            Some(registration) if registration.shared.ptr_eq(&shared.this) => registration,
+            // Registered with a runtime that has since gone away.
+            stale => {
+                drop(stale);
+                Registration {
+                    key: shared.reactor.add(self.io.as_raw_fd())?,
+                    shared: shared.this.clone(),
+                }
+            }
+        };
+        let key = registration.key;
+        self.registration.set(Some(registration));
+        Ok(key)
+    }
+}
+
+/// Waits for the reactor to report a source ready for `interest`.
+struct Readiness<'a, T: AsRawFd> {
+    source: &'a Source<T>,
+    interest: Interest,
+    /// Whether the stale readiness that led to `WouldBlock` was cleared.
+    cleared: bool,
+    /// Set while our waker is registered with the reactor.
+    waiting: bool,
+}
+
+impl<T: AsRawFd> Future for Readiness<'_, T> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let clear = !std::mem::replace(&mut self.cleared, true);
+        let ready = self.source.poll_ready(self.interest, clear, cx);
+        self.waiting = matches!(ready, Ok(Poll::Pending));
+        match ready {
+            Ok(ready) => ready.map(Ok),
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl<T: AsRawFd> Drop for Readiness<'_, T> {
+    fn drop(&mut self) {
+        // A stale waker would keep the reactor from ever looking idle.
+        if self.waiting {
+            self.source.cancel_wait(self.interest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{MiniRuntime, net::TcpListener, time};
+
+    #[test]
+    fn cancelled_accept_leaves_the_reactor_idle() {
+        let mut rt = MiniRuntime::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let outcome = rt.block_on_timeout(
+            async move {
+                let accept = time::timeout(Duration::from_millis(5), listener.accept());
+                assert!(accept.await.is_err());
+                // Auto-advance only kicks in once no task waits on a socket.
+                time::pause();
+                time::sleep(Duration::from_secs(60)).await;
+                listener
+            },
+            Duration::from_secs(5),
+        );
+        assert!(outcome.is_ok(), "paused time never advanced");
+        assert!(rt.shared.borrow().reactor.is_idle());
+    }
+
+    #[test]
+    fn dropping_a_socket_deregisters_it() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let accept = time::timeout(Duration::from_millis(5), listener.accept());
+            assert!(accept.await.is_err());
+        });
+        assert!(rt.shared.borrow().reactor.sources.is_empty());
+    }
+
+    #[cfg(feature = "mio")]
+    mod edges {
+        use std::{
+            future::Future,
+            pin::Pin,
+            sync::{
+                Arc,
+                atomic::{AtomicUsize, Ordering},
+            },
+            task::{Context, Poll, Wake, Waker},
+            time::Duration,
+        };
+
+        use crate::{
+            Handle, MiniRuntime,
+            net::{TcpListener, TcpStream},
+            time,
+        };
+
+        /// Counts the wakes of whatever task polls the wrapped future.
+        struct CountWakes<F> {
+            future: Pin<Box<F>>,
+            wakes: Arc<AtomicUsize>,
+        }
+
+        struct Counting {
+            inner: Waker,
+            wakes: Arc<AtomicUsize>,
+        }
+
+        impl Wake for Counting {
+            fn wake(self: Arc<Self>) {
+                self.wake_by_ref();
+            }
+
+            fn wake_by_ref(self: &Arc<Self>) {
+                self.wakes.fetch_add(1, Ordering::SeqCst);
+                self.inner.wake_by_ref();
+            }
+        }
+
+        impl<F: Future> Future for CountWakes<F> {
+            type Output = F::Output;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+                let waker = Waker::from(Arc::new(Counting {
+                    inner: cx.waker().clone(),
+                    wakes: self.wakes.clone(),
+                }));
+                self.future.as_mut().poll(&mut Context::from_waker(&waker))
+            }
+        }
+
+        #[test]
+        fn readable_edge_wakes_a_reader_once() {
+            let mut rt = MiniRuntime::new();
+            let wakes = rt.block_on_timeout(
+                async {
+                    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                    let mut client = TcpStream::connect(listener.local_addr().unwrap())
+                        .await
+                        .unwrap();
+                    let (mut server, _) = listener.accept().await.unwrap();
+                    let reader = Handle::current().spawn(async move {
+                        let mut wakes = Vec::new();
+                        let mut buf = [0; 16];
+                        for _ in 0..3 {
+                            let counter = Arc::new(AtomicUsize::new(0));
+                            let read = CountWakes {
+                                future: Box::pin(server.read(&mut buf)),
+                                wakes: counter.clone(),
+                            };
+                            assert_eq!(read.await.unwrap(), 1);
+                            wakes.push(counter.load(Ordering::SeqCst));
+                        }
+                        wakes
+                    });
+                    for byte in [1, 2, 3] {
+                        // Give the reader time to park on the socket first.
+                        time::sleep(Duration::from_millis(20)).await;
+                        client.write_all(&[byte]).await.unwrap();
+                    }
+                    reader.await.unwrap()
+                },
+                Duration::from_secs(5),
+            );
+            assert_eq!(wakes, Ok(vec![1, 1, 1]));
+        }
+    }
+}
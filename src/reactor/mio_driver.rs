@@ -0,0 +1,77 @@
+//! The driver used with the `mio` feature: sockets are registered once with
+//! a `mio::Poll` (epoll, kqueue, ...) for edge-triggered readiness in both
+//! directions.
+
+use std::{collections::HashMap, io, os::fd::RawFd, time::Duration};
+
+use mio::{Events, Poll, Token, unix::SourceFd};
+
+use super::{Event, ScheduledIo};
+
+/// Reserved for the notifier; source keys count up from zero.
+const WAKE: Token = Token(usize::MAX);
+
+pub(super) struct Driver {
+    poll: Poll,
+    events: Events,
+}
+
+/// Interrupts a driver blocked in `mio::Poll::poll` from any thread.
+pub(crate) struct Notifier {
+    waker: mio::Waker,
+}
+
+impl Notifier {
+    pub(crate) fn notify(&self) {
+        let _ = self.waker.wake();
+    }
+}
+
+impl Driver {
+    pub(super) fn new() -> io::Result<(Self, Notifier)> {
+        let poll = Poll::new()?;
+        let waker = mio::Waker::new(poll.registry(), WAKE)?;
+        let driver = Self {
+            poll,
+            events: Events::with_capacity(256),
+        };
+        Ok((driver, Notifier { waker }))
+    }
+
+    pub(super) fn add(&mut self, fd: RawFd, key: usize) -> io::Result<()> {
+        self.poll.registry().register(
+            &mut SourceFd(&fd),
+            Token(key),
+            mio::Interest::READABLE | mio::Interest::WRITABLE,
+        )
+    }
+
+    pub(super) fn remove(&mut self, fd: RawFd) {
+        let _ = self.poll.registry().deregister(&mut SourceFd(&fd));
+    }
+
+    pub(super) fn wait(
+        &mut self,
+        _sources: &HashMap<usize, ScheduledIo>,
+        timeout: Option<Duration>,
+    ) -> Vec<Event> {
+        if let Err(err) = self.poll.poll(&mut self.events, timeout) {
+            assert!(
+                err.kind() == io::ErrorKind::Interrupted,
+                "mio poll failed: {err}"
+            );
+            return Vec::new();
+        }
+        // Errors and hang-ups count as ready: the retried operation reports
+        // them.
+        self.events
+            .iter()
+            .filter(|event| event.token() != WAKE)
+            .map(|event| Event {
+                key: event.token().0,
+                readable: event.is_readable() || event.is_read_closed() || event.is_error(),
+                writable: event.is_writable() || event.is_write_closed() || event.is_error(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,137 @@
+//! The default driver: one `poll(2)` call per turn over every socket a task
+//! is waiting on, plus a socket pair for cross-thread wakes.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        raw::{c_int, c_short},
+        unix::net::UnixStream,
+    },
+    time::Duration,
+};
+
+use super::{Event, ScheduledIo};
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type Nfds = std::os::raw::c_ulong;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+type Nfds = std::os::raw::c_uint;
+
+const POLLIN: c_short = 0x1;
+const POLLOUT: c_short = 0x4;
+const POLLERR: c_short = 0x8;
+const POLLHUP: c_short = 0x10;
+const POLLNVAL: c_short = 0x20;
+
+unsafe extern "C" {
+    fn poll(fds: *mut PollFd, nfds: Nfds, timeout: c_int) -> c_int;
+}
+
+pub(super) struct Driver {
+    /// Read end of the socket pair the [`Notifier`] writes to.
+    wake_rx: UnixStream,
+}
+
+/// Interrupts a driver blocked in `poll` from any thread.
+pub(crate) struct Notifier {
+    wake_tx: UnixStream,
+}
+
+impl Notifier {
+    pub(crate) fn notify(&self) {
+        // A full socket already guarantees a wake-up.
+        let _ = (&self.wake_tx).write(&[1]);
+    }
+}
+
+impl Driver {
+    pub(super) fn new() -> io::Result<(Self, Notifier)> {
+        let (wake_rx, wake_tx) = UnixStream::pair()?;
+        wake_rx.set_nonblocking(true)?;
+        wake_tx.set_nonblocking(true)?;
+        Ok((Self { wake_rx }, Notifier { wake_tx }))
+    }
+
+    /// `poll(2)` takes the fd list on every call, so there is nothing to
+    /// register up front.
+    pub(super) fn add(&mut self, _fd: RawFd, _key: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub(super) fn remove(&mut self, _fd: RawFd) {}
+
+    pub(super) fn wait(
+        &mut self,
+        sources: &HashMap<usize, ScheduledIo>,
+        timeout: Option<Duration>,
+    ) -> Vec<Event> {
+        let keys: Vec<usize> = sources
+            .iter()
+            .filter(|(_, io)| io.is_waiting())
+            .map(|(&key, _)| key)
+            .collect();
+        let mut fds: Vec<PollFd> = [(self.wake_rx.as_raw_fd(), POLLIN)]
+            .into_iter()
+            .chain(keys.iter().map(|key| {
+                let io = &sources[key];
+                let mut events = 0;
+                if io.read_waker.is_some() {
+                    events |= POLLIN;
+                }
+                if io.write_waker.is_some() {
+                    events |= POLLOUT;
+                }
+                (io.fd, events)
+            }))
+            .map(|(fd, events)| PollFd {
+                fd,
+                events,
+                revents: 0,
+            })
+            .collect();
+        // Round up, so we never wake just short of a timer and spin.
+        let timeout = timeout.map_or(-1, |timeout| {
+            let millis = timeout.as_millis() + u128::from(timeout.subsec_nanos() % 1_000_000 != 0);
+            c_int::try_from(millis).unwrap_or(c_int::MAX)
+        });
+        // SAFETY: `fds` is a live, correctly laid out `pollfd` array of the
+        // given length.
+        let n = unsafe { poll(fds.as_mut_ptr(), fds.len() as Nfds, timeout) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            assert!(
+                err.kind() == io::ErrorKind::Interrupted,
+                "poll failed: {err}"
+            );
+            return Vec::new();
+        }
+
+        if fds[0].revents != 0 {
+            let mut buf = [0; 64];
+            while matches!((&self.wake_rx).read(&mut buf), Ok(n) if n > 0) {}
+        }
+        // Errors and hang-ups count as ready both ways: the retried
+        // operation reports them.
+        keys.into_iter()
+            .zip(&fds[1..])
+            .filter(|(_, fd)| fd.revents != 0)
+            .map(|(key, fd)| {
+                let failed = fd.revents & (POLLERR | POLLHUP | POLLNVAL) != 0;
+                Event {
+                    key,
+                    readable: failed || fd.revents & POLLIN != 0,
+                    writable: failed || fd.revents & POLLOUT != 0,
+                }
+            })
+            .collect()
+    }
+}
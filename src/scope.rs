@@ -0,0 +1,180 @@
+//! Structured concurrency: child futures that cannot outlive their parent.
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    mem,
+    pin::{Pin, pin},
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+use crate::{FuturesUnordered, Stream, future::poll_fn};
+
+type Child<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Runs the future `f` returns, along with every child it spawns on the
+/// [`Scope`] it is given, and resolves to its output once all of them have
+/// finished.
+///
+/// The children are polled by the scope future itself rather than by the
+/// runtime, so they run concurrently with each other and with the body but
+/// on the task awaiting `scope`. That is what lets them borrow from outside
+/// the scope: dropping the scope future drops every child with it, and a
+/// panic in a child propagates out of `scope` like one in the body.
+///
+/// ```
+/// use std::{cell::Cell, time::Duration};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     let finished = Cell::new(0);
+///     let finished = &finished;
+///     let sum = mini_rt::scope(|s| async move {
+///         for delay in [3, 1, 2] {
+///             s.spawn(async move {
+///                 mini_rt::sleep(Duration::from_millis(delay)).await;
+///                 finished.set(finished.get() + 1);
+///             });
+///         }
+///         1 + 2 + 3
+///     })
+///     .await;
+///     assert_eq!(sum, 6);
+///     assert_eq!(finished.get(), 3);
+/// });
+/// ```
+pub async fn scope<'a, F, Fut>(f: F) -> Fut::Output
+where
+    F: FnOnce(Scope<'a>) -> Fut,
+    Fut: Future,
+{
+    let scope = Scope {
+        state: Rc::new(ScopeState {
+            spawned: RefCell::new(Vec::new()),
+            waker: RefCell::new(None),
+        }),
+    };
+    let mut body = pin!(f(scope.clone()));
+    let mut output = None;
+    let mut children = FuturesUnordered::new();
+    poll_fn(|cx| {
+        *scope.state.waker.borrow_mut() = Some(cx.waker().clone());
+        if output.is_none()
+            && let Poll::Ready(value) = body.as_mut().poll(cx)
+        {
+            output = Some(value);
+        }
+        // Children may spawn more children while being polled.
+        loop {
+            for child in mem::take(&mut *scope.state.spawned.borrow_mut()) {
+                children.push(child);
+            }
+            while let Poll::Ready(Some(())) = Pin::new(&mut children).poll_next(cx) {}
+            if scope.state.spawned.borrow().is_empty() {
+                break;
+            }
+        }
+        if output.is_some() && children.is_empty() {
+            Poll::Ready(output.take().unwrap())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Spawns children onto a [`scope`]. Cloning it gives another handle to
+/// the same scope.
+#[derive(Clone)]
+pub struct Scope<'a> {
+    state: Rc<ScopeState<'a>>,
+}
+
+struct ScopeState<'a> {
+    /// Children spawned since the scope last polled, not yet started.
+    spawned: RefCell<Vec<Child<'a>>>,
+    /// The task awaiting the scope, woken when a child is spawned.
+    waker: RefCell<Option<Waker>>,
+}
+
+impl<'a> Scope<'a> {
+    /// Adds `future` to the scope. It may borrow anything that outlives the
+    /// scope, and finishes before `scope` returns.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + 'a,
+    {
+        self.state.spawned.borrow_mut().push(Box::pin(future));
+        if let Some(waker) = &*self.state.waker.borrow() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::*;
+    use crate::{MiniRuntime, time};
+
+    #[test]
+    fn scope_waits_for_every_child_even_after_the_body_returns() {
+        let mut rt = MiniRuntime::new();
+        let (output, took, finished) = rt.block_on(async {
+            time::pause();
+            let finished = Cell::new(Vec::new());
+            let finished = &finished;
+            let start = time::now();
+            let record = move |name| {
+                let mut names = finished.take();
+                names.push(name);
+                finished.set(names);
+            };
+            let output = scope(|s| async move {
+                s.spawn(async move {
+                    time::sleep(Duration::from_millis(30)).await;
+                    record("slow");
+                });
+                let nested = s.clone();
+                s.spawn(async move {
+                    time::sleep(Duration::from_millis(10)).await;
+                    record("fast");
+                    nested.spawn(async move {
+                        time::sleep(Duration::from_millis(40)).await;
+                        record("nested");
+                    });
+                });
+                "body"
+            })
+            .await;
+            (output, time::now() - start, finished.take())
+        });
+        assert_eq!(output, "body");
+        assert_eq!(took, Duration::from_millis(50));
+        assert_eq!(finished, ["fast", "slow", "nested"]);
+    }
+
+    #[test]
+    fn a_panicking_child_panics_out_of_the_scope() {
+        let mut rt = MiniRuntime::new();
+        let sibling_done = Rc::new(Cell::new(false));
+        let done = sibling_done.clone();
+        let task = rt.spawn(async move {
+            scope(|s| async move {
+                s.spawn(async move {
+                    time::sleep(Duration::from_secs(60)).await;
+                    done.set(true);
+                });
+                s.spawn(async { panic!("child failed") });
+            })
+            .await
+        });
+        let payload = rt.block_on(task).unwrap_err().into_panic();
+        assert_eq!(*payload.downcast::<&str>().unwrap(), "child failed");
+        // The sibling went down with the scope instead of finishing.
+        assert!(!sibling_done.get());
+        assert_eq!(rt.pending_timers(), 0);
+    }
+}
@@ -0,0 +1,242 @@
+//! Process signals as futures.
+//!
+//! The signal handler only writes a byte to a socket pair, which is all a
+//! handler may safely do. A background thread reads that socket and wakes
+//! the waiting tasks, so a signal reaches tasks on any runtime.
+
+use std::{
+    future::Future,
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, IntoRawFd},
+        raw::{c_int, c_void},
+        unix::net::UnixStream,
+    },
+    pin::Pin,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicI32, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use crate::slab::{self, Slab};
+
+const SIGINT: c_int = 2;
+/// What `signal` returns on failure: `SIG_ERR`, which is `(void (*)(int)) -1`.
+const SIG_ERR: usize = usize::MAX;
+
+unsafe extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+    /// The calling thread's `errno`.
+    #[cfg_attr(target_os = "linux", link_name = "__errno_location")]
+    #[cfg_attr(
+        any(target_os = "android", target_os = "netbsd", target_os = "openbsd"),
+        link_name = "__errno"
+    )]
+    #[cfg_attr(
+        any(
+            target_vendor = "apple",
+            target_os = "freebsd",
+            target_os = "dragonfly"
+        ),
+        link_name = "__error"
+    )]
+    fn errno_location() -> *mut c_int;
+}
+
+/// The write end of the socket pair, for the signal handler.
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sigint(_: c_int) {
+    // SAFETY: `errno_location` has no preconditions and returns a pointer
+    // to this thread's `errno`, valid for as long as the thread runs.
+    let errno = unsafe { errno_location() };
+    // The interrupted code may be between a failing call and its check of
+    // `errno`, so a failed `write` here must not leave its own behind.
+    // SAFETY: as above.
+    let saved = unsafe { *errno };
+    let fd = WAKE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // A full socket already has a wake-up pending.
+        // SAFETY: `write` is async-signal-safe, the buffer is one valid
+        // byte, and `fd` is the socket pair's write end, never closed.
+        unsafe { write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+    // SAFETY: as above.
+    unsafe { *errno = saved };
+}
+
+/// SIGINTs seen so far, plus the tasks waiting for the next one; each
+/// `CtrlC` keeps its own slot until it completes or is dropped.
+struct Listener {
+    state: Mutex<ListenerState>,
+}
+
+struct ListenerState {
+    received: u64,
+    wakers: Slab<Waker>,
+}
+
+/// Installs the handler and starts the listener thread on first use.
+fn listener() -> io::Result<&'static Listener> {
+    static LISTENER: OnceLock<Result<Listener, (io::ErrorKind, String)>> = OnceLock::new();
+    LISTENER
+        .get_or_init(|| install().map_err(|err| (err.kind(), err.to_string())))
+        .as_ref()
+        .map_err(|(kind, message)| io::Error::new(*kind, message.clone()))
+}
+
+fn install() -> io::Result<Listener> {
+    let (mut rx, tx) = UnixStream::pair()?;
+    tx.set_nonblocking(true)?;
+    thread::Builder::new()
+        .name("mini-rt-signal".into())
+        .spawn(move || {
+            let mut buf = [0; 64];
+            while let Ok(n @ 1..) = rx.read(&mut buf) {
+                let listener = listener().expect("signal listener running without a handler");
+                let mut state = listener.state.lock().unwrap();
+                state.received += n as u64;
+                for waker in state.wakers.iter() {
+                    waker.wake_by_ref();
+                }
+            }
+        })?;
+    // The write end stays open for the rest of the process.
+    WAKE_FD.store(tx.as_raw_fd(), Ordering::Relaxed);
+    let _ = tx.into_raw_fd();
+    // SAFETY: `on_sigint` only does async-signal-safe work: an atomic
+    // load, `write`, and saving and restoring `errno`.
+    if unsafe { signal(SIGINT, on_sigint) } == SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Listener {
+        state: Mutex::new(ListenerState {
+            received: 0,
+            wakers: Slab::new(),
+        }),
+    })
+}
+
+/// Waits for the process to receive SIGINT, typically from Ctrl-C.
+///
+/// The first call replaces the default SIGINT behaviour, which is to
+/// terminate the process, for the rest of the process's life. Only signals
+/// that arrive after this call complete the future.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     mini_rt::signal::ctrl_c().await?;
+///     println!("interrupted, shutting down");
+///     Ok(())
+/// })
+/// # }
+/// ```
+pub fn ctrl_c() -> CtrlC {
+    let state = listener().map(|listener| (listener, listener.state.lock().unwrap().received));
+    CtrlC {
+        state: Some(state),
+        key: None,
+    }
+}
+
+/// Future returned by [`ctrl_c`].
+pub struct CtrlC {
+    /// The listener and how many signals it had seen when we were created,
+    /// or the error from setting it up. Taken on completion.
+    state: Option<io::Result<(&'static Listener, u64)>>,
+    /// Our waker's slot in the listener, once we have had to wait.
+    key: Option<slab::Key>,
+}
+
+impl Future for CtrlC {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let (listener, seen) = match self.state.take().expect("CtrlC polled after completion") {
+            Ok(state) => state,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        let mut state = listener.state.lock().unwrap();
+        if state.received != seen {
+            if let Some(key) = self.key.take() {
+                state.wakers.remove(key);
+            }
+            return Poll::Ready(Ok(()));
+        }
+        match self.key.and_then(|key| state.wakers.get_mut(key)) {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => self.key = Some(state.wakers.insert(cx.waker().clone())),
+        }
+        drop(state);
+        self.state = Some(Ok((listener, seen)));
+        Poll::Pending
+    }
+}
+
+impl Drop for CtrlC {
+    fn drop(&mut self) {
+        if let (Some(key), Some(Ok((listener, _)))) = (self.key, &self.state) {
+            listener.state.lock().unwrap().wakers.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{MiniRuntime, time};
+
+    unsafe extern "C" {
+        fn raise(signum: c_int) -> c_int;
+    }
+
+    /// Held by tests that raise signals or swap out `WAKE_FD`.
+    static HANDLER: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn a_raised_sigint_resolves_ctrl_c() {
+        let _handler = HANDLER.lock().unwrap();
+        let mut rt = MiniRuntime::new();
+        // Installs the handler, so raising cannot kill the test process.
+        let interrupted = ctrl_c();
+        // SAFETY: SIGINT is handled by `on_sigint` from here on.
+        assert_eq!(unsafe { raise(SIGINT) }, 0);
+        let resolved = rt.block_on(time::timeout(Duration::from_secs(5), interrupted));
+        assert!(matches!(resolved, Ok(Ok(()))));
+    }
+
+    #[test]
+    fn the_handler_leaves_errno_as_it_found_it() {
+        let _handler = HANDLER.lock().unwrap();
+        ctrl_c();
+        // A descriptor that is not open, so the handler's `write` fails
+        // and sets `errno` to `EBADF`.
+        let wake_fd = WAKE_FD.swap(c_int::MAX, Ordering::Relaxed);
+        // SAFETY: this thread's `errno`, written and read on this thread.
+        unsafe { *errno_location() = 4321 };
+        on_sigint(SIGINT);
+        // SAFETY: as above.
+        let errno = unsafe { *errno_location() };
+        WAKE_FD.store(wake_fd, Ordering::Relaxed);
+        assert_eq!(errno, 4321);
+    }
+
+    #[test]
+    fn dropped_ctrl_c_forgets_its_waker() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let waiting = time::timeout(Duration::from_millis(5), ctrl_c()).await;
+            assert!(waiting.is_err());
+        });
+        let listener = listener().unwrap();
+        assert!(listener.state.lock().unwrap().wakers.is_empty());
+    }
+}
@@ -0,0 +1,478 @@
+//! Asynchronous iteration.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use crate::coop;
+
+mod futures_unordered;
+
+pub use futures_unordered::FuturesUnordered;
+
+/// A source of values produced asynchronously, the async analogue of
+/// `Iterator`.
+pub trait Stream {
+    type Item;
+
+    /// Attempts to pull the next value, registering the task to be woken
+    /// when one may be available. `Ready(None)` means the stream is done.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+}
+
+/// Convenience methods for every [`Stream`].
+///
+/// The adapters are lazy and chain like their `Iterator` counterparts:
+///
+/// ```
+/// use mini_rt::{StreamExt, stream};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let firsts: Vec<_> = rt.block_on(
+///     stream::iter(1..)
+///         .filter(|n| n % 2 == 1)
+///         .map(|n| n * n)
+///         .take(3)
+///         .collect(),
+/// );
+/// assert_eq!(firsts, [1, 9, 25]);
+/// ```
+pub trait StreamExt: Stream {
+    /// Resolves to the next item, so streams can be driven with
+    /// `while let Some(x) = stream.next().await`.
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Next { stream: self }
+    }
+
+    /// Transforms each item with `f`.
+    fn map<U, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> U,
+    {
+        Map { stream: self, f }
+    }
+
+    /// Yields only the items for which `predicate` returns `true`.
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Filter {
+            stream: self,
+            predicate,
+        }
+    }
+
+    /// Yields at most `n` items, then ends without polling the stream again.
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take {
+            stream: self,
+            remaining: n,
+        }
+    }
+
+    /// Treats each item as a future and runs up to `n` of them at once,
+    /// yielding their outputs in completion order. A new future is pulled
+    /// from the stream whenever one finishes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    fn buffer_unordered(self, n: usize) -> BufferUnordered<Self>
+    where
+        Self: Sized,
+        Self::Item: Future,
+    {
+        assert!(n > 0, "buffer_unordered needs room for at least one future");
+        BufferUnordered {
+            stream: self,
+            done: false,
+            in_flight: FuturesUnordered::new(),
+            limit: n,
+        }
+    }
+
+    /// Drives the stream to completion, running `f` on each item and
+    /// awaiting the future it returns before pulling the next item.
+    fn for_each<F, Fut>(mut self, mut f: F) -> impl Future<Output = ()>
+    where
+        Self: Sized + Unpin,
+        F: FnMut(Self::Item) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        async move {
+            while let Some(item) = self.next().await {
+                f(item).await;
+            }
+        }
+    }
+
+    /// Drives the stream to completion and gathers its items, for example
+    /// into a `Vec`.
+    fn collect<C>(mut self) -> impl Future<Output = C>
+    where
+        Self: Sized + Unpin,
+        C: Default + Extend<Self::Item>,
+    {
+        async move {
+            let mut items = C::default();
+            while let Some(item) = self.next().await {
+                items.extend(Some(item));
+            }
+            items
+        }
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
+
+/// Future returned by [`StreamExt::next`].
+pub struct Next<'a, S: ?Sized> {
+    stream: &'a mut S,
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for &mut S {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for Box<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+}
+
+/// Stream returned by [`StreamExt::map`].
+pub struct Map<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S: Unpin, F> Unpin for Map<S, F> {}
+
+impl<S: Stream + Unpin, U, F: FnMut(S::Item) -> U> Stream for Map<S, F> {
+    type Item = U;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<U>> {
+        let item = ready!(Pin::new(&mut self.stream).poll_next(cx));
+        Poll::Ready(item.map(&mut self.f))
+    }
+}
+
+/// Stream returned by [`StreamExt::filter`].
+pub struct Filter<S, F> {
+    stream: S,
+    predicate: F,
+}
+
+impl<S: Unpin, F> Unpin for Filter<S, F> {}
+
+impl<S: Stream + Unpin, F: FnMut(&S::Item) -> bool> Stream for Filter<S, F> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        loop {
+            match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+                Some(item) if !(self.predicate)(&item) => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+/// Stream returned by [`StreamExt::take`].
+pub struct Take<S> {
+    stream: S,
+    remaining: usize,
+}
+
+impl<S: Unpin> Unpin for Take<S> {}
+
+impl<S: Stream + Unpin> Stream for Take<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let item = ready!(Pin::new(&mut self.stream).poll_next(cx));
+        match item {
+            Some(_) => self.remaining -= 1,
+            None => self.remaining = 0,
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// Stream returned by [`StreamExt::buffer_unordered`].
+pub struct BufferUnordered<S: Stream>
+where
+    S::Item: Future,
+{
+    stream: S,
+    /// Set once `stream` has ended.
+    done: bool,
+    in_flight: FuturesUnordered<S::Item>,
+    limit: usize,
+}
+
+impl<S: Stream + Unpin> Unpin for BufferUnordered<S> where S::Item: Future {}
+
+impl<S: Stream + Unpin> Stream for BufferUnordered<S>
+where
+    S::Item: Future,
+{
+    type Item = <S::Item as Future>::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        while !this.done && this.in_flight.len() < this.limit {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(future)) => this.in_flight.push(future),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+        match Pin::new(&mut this.in_flight).poll_next(cx) {
+            // An empty set only means the stream has nothing for us yet.
+            Poll::Ready(None) if !this.done => Poll::Pending,
+            output => output,
+        }
+    }
+}
+
+/// Turns an iterator into a stream that yields its items without waiting.
+pub fn iter<I: IntoIterator>(items: I) -> Iter<I::IntoIter> {
+    Iter {
+        items: items.into_iter(),
+    }
+}
+
+/// Stream returned by [`iter`].
+pub struct Iter<I> {
+    items: I,
+}
+
+impl<I> Unpin for Iter<I> {}
+
+impl<I: Iterator> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        ready!(coop::poll_proceed(cx));
+        Poll::Ready(self.items.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{MiniRuntime, yield_now};
+
+    #[test]
+    fn next_drives_an_iter_stream_to_completion() {
+        let mut rt = MiniRuntime::new();
+        let items = rt.block_on(async {
+            let mut stream = iter(["a", "b", "c"]);
+            let mut items = Vec::new();
+            while let Some(item) = stream.next().await {
+                items.push(item);
+            }
+            // A finished stream stays finished.
+            assert_eq!(stream.next().await, None);
+            items
+        });
+        assert_eq!(items, ["a", "b", "c"]);
+    }
+
+    /// Yields `0..3`, going pending once before each item.
+    struct Countdown {
+        next: u32,
+        primed: bool,
+    }
+
+    impl Stream for Countdown {
+        type Item = u32;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u32>> {
+            if self.next == 3 {
+                return Poll::Ready(None);
+            }
+            if !self.primed {
+                self.primed = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.primed = false;
+            self.next += 1;
+            Poll::Ready(Some(self.next - 1))
+        }
+    }
+
+    #[test]
+    fn next_waits_out_a_pending_stream() {
+        let mut rt = MiniRuntime::new();
+        let items = rt.block_on(async {
+            let mut stream = Countdown {
+                next: 0,
+                primed: false,
+            };
+            let mut items = Vec::new();
+            while let Some(item) = stream.next().await {
+                items.push(item);
+                yield_now().await;
+            }
+            items
+        });
+        assert_eq!(items, [0, 1, 2]);
+    }
+
+    #[test]
+    fn collect_gathers_an_iter_stream_into_a_vec() {
+        let mut rt = MiniRuntime::new();
+        let items: Vec<u32> = rt.block_on(iter(1..=4).collect());
+        assert_eq!(items, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn for_each_runs_each_item_to_completion_in_turn() {
+        let mut rt = MiniRuntime::new();
+        let sum = Rc::new(Cell::new(0));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (total, steps) = (sum.clone(), log.clone());
+        rt.block_on(iter(1..=4).for_each(move |n| {
+            let (total, steps) = (total.clone(), steps.clone());
+            async move {
+                steps.borrow_mut().push(n);
+                yield_now().await;
+                total.set(total.get() + n);
+                steps.borrow_mut().push(-n);
+            }
+        }));
+        assert_eq!(sum.get(), 10);
+        assert_eq!(*log.borrow(), [1, -1, 2, -2, 3, -3, 4, -4]);
+    }
+
+    #[test]
+    fn buffer_unordered_keeps_at_most_n_futures_in_flight() {
+        let mut rt = MiniRuntime::new();
+        let in_flight = Rc::new(Cell::new(0));
+        let peak = Rc::new(Cell::new(0));
+        let (current, most) = (in_flight.clone(), peak.clone());
+        let done: Vec<u64> = rt.block_on(async move {
+            crate::time::pause();
+            let sleeps = iter(0..8u64).map(move |n| {
+                let (current, most) = (current.clone(), most.clone());
+                async move {
+                    current.set(current.get() + 1);
+                    most.set(most.get().max(current.get()));
+                    crate::sleep(Duration::from_millis(10 + (n % 3) * 5)).await;
+                    current.set(current.get() - 1);
+                    n
+                }
+            });
+            sleeps.buffer_unordered(3).collect().await
+        });
+        assert_eq!(peak.get(), 3);
+        assert_eq!(in_flight.get(), 0);
+        let mut sorted = done.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+    }
+
+    /// Counts how often the stream underneath is polled.
+    struct Counted<S> {
+        stream: S,
+        polls: Rc<Cell<usize>>,
+    }
+
+    impl<S: Stream + Unpin> Stream for Counted<S> {
+        type Item = S::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+            self.polls.set(self.polls.get() + 1);
+            Pin::new(&mut self.stream).poll_next(cx)
+        }
+    }
+
+    fn counted<S>(stream: S) -> (Counted<S>, Rc<Cell<usize>>) {
+        let polls = Rc::new(Cell::new(0));
+        let counted = Counted {
+            stream,
+            polls: polls.clone(),
+        };
+        (counted, polls)
+    }
+
+    #[test]
+    fn map_filter_and_take_chain() {
+        let mut rt = MiniRuntime::new();
+        let (numbers, polls) = counted(iter(0..100));
+        let items: Vec<_> = rt.block_on(
+            numbers
+                .map(|n| n * 3)
+                .filter(|n| n % 2 == 0)
+                .take(4)
+                .collect(),
+        );
+        assert_eq!(items, [0, 6, 12, 18]);
+        // 0 through 6 were pulled to find four even multiples; nothing more.
+        assert_eq!(polls.get(), 7);
+    }
+
+    #[test]
+    fn take_zero_ends_without_polling_the_stream() {
+        let mut rt = MiniRuntime::new();
+        let (numbers, polls) = counted(iter(0..10));
+        let items: Vec<i32> = rt.block_on(numbers.take(0).collect());
+        assert!(items.is_empty());
+        assert_eq!(polls.get(), 0);
+    }
+
+    #[test]
+    fn the_end_of_the_stream_passes_through_every_adapter() {
+        let mut rt = MiniRuntime::new();
+        let (numbers, polls) = counted(iter(0..5));
+        rt.block_on(async move {
+            let mut stream = numbers.map(|n| n + 1).filter(|n| n % 2 == 1).take(10);
+            let mut items = Vec::new();
+            while let Some(item) = stream.next().await {
+                items.push(item);
+            }
+            assert_eq!(items, [1, 3, 5]);
+            // Take remembers the end and stops polling.
+            assert_eq!(stream.next().await, None);
+            assert_eq!(stream.next().await, None);
+        });
+        assert_eq!(polls.get(), 6);
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use super::Stream;
+
+/// A growable set of futures, itself a [`Stream`] yielding each output as
+/// its future completes (completion order, not insertion order).
+///
+/// Every future gets its own waker, so a poll of the set only polls the
+/// futures that were actually woken rather than all of them.
+pub struct FuturesUnordered<F> {
+    slots: Vec<Option<Slot<F>>>,
+    free: Vec<usize>,
+    len: usize,
+    woken: Arc<Woken>,
+}
+
+struct Slot<F> {
+    future: Pin<Box<F>>,
+    waker: Arc<SlotWaker>,
+}
+
+/// Indices of woken slots, plus the waker of the task polling the set.
+struct Woken {
+    indices: Mutex<VecDeque<usize>>,
+    parent: Mutex<Option<Waker>>,
+}
+
+struct SlotWaker {
+    index: usize,
+    queued: AtomicBool,
+    woken: Arc<Woken>,
+}
+
+impl Wake for SlotWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            self.woken.indices.lock().unwrap().push_back(self.index);
+            if let Some(parent) = &*self.woken.parent.lock().unwrap() {
+                parent.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl<F> FuturesUnordered<F> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            woken: Arc::new(Woken {
+                indices: Mutex::new(VecDeque::new()),
+                parent: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Adds a future to the set. It is first polled on the next poll of the
+    /// set.
+    pub fn push(&mut self, future: F) {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(None);
+            self.slots.len() - 1
+        });
+        let waker = Arc::new(SlotWaker {
+            index,
+            queued: AtomicBool::new(false),
+            woken: self.woken.clone(),
+        });
+        waker.wake_by_ref();
+        self.slots[index] = Some(Slot {
+            future: Box::pin(future),
+            waker,
+        });
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<F> Default for FuturesUnordered<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> Unpin for FuturesUnordered<F> {}
+
+impl<F: Future> Stream for FuturesUnordered<F> {
+    type Item = F::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<F::Output>> {
+        if self.len == 0 {
+            return Poll::Ready(None);
+        }
+        *self.woken.parent.lock().unwrap() = Some(cx.waker().clone());
+
+        // Futures that wake themselves straight away would keep us here
+        // forever; give each at most one poll before yielding.
+        let mut budget = self.len;
+        while budget > 0 {
+            let Some(index) = self.woken.indices.lock().unwrap().pop_front() else {
+                return Poll::Pending;
+            };
+            // A stale wake for a slot that has since been emptied.
+            let Some(slot) = &mut self.slots[index] else {
+                continue;
+            };
+            budget -= 1;
+            slot.waker.queued.store(false, Ordering::SeqCst);
+            let waker = Waker::from(slot.waker.clone());
+            if let Poll::Ready(output) = slot.future.as_mut().poll(&mut Context::from_waker(&waker))
+            {
+                self.slots[index] = None;
+                self.free.push(index);
+                self.len -= 1;
+                return Poll::Ready(Some(output));
+            }
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{MiniRuntime, StreamExt, future::poll_fn, sleep, time};
+
+    #[test]
+    fn outputs_arrive_in_completion_order() {
+        let mut rt = MiniRuntime::new();
+        let outputs: Vec<u64> = rt.block_on(async {
+            time::pause();
+            let mut set = FuturesUnordered::new();
+            for millis in [70, 20, 100, 10, 50, 90, 30, 60, 40, 80] {
+                set.push(async move {
+                    sleep(Duration::from_millis(millis)).await;
+                    millis
+                });
+            }
+            set.collect().await
+        });
+        assert_eq!(outputs, [10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+    }
+
+    /// Counts its polls and parks until its stored waker is used.
+    fn counted(
+        polls: Rc<Cell<u32>>,
+        waker: Rc<RefCell<Option<Waker>>>,
+    ) -> impl Future<Output = ()> {
+        poll_fn(move |cx| {
+            polls.set(polls.get() + 1);
+            *waker.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+
+    #[test]
+    fn only_woken_futures_are_polled() {
+        let polls: Vec<_> = (0..5).map(|_| Rc::new(Cell::new(0))).collect();
+        let wakers: Vec<_> = (0..5).map(|_| Rc::new(RefCell::new(None))).collect();
+        let mut set = FuturesUnordered::new();
+        for (polls, waker) in polls.iter().zip(&wakers) {
+            set.push(counted(polls.clone(), waker.clone()));
+        }
+        let mut cx = Context::from_waker(Waker::noop());
+        let count = |polls: &[Rc<Cell<u32>>]| polls.iter().map(|p| p.get()).collect::<Vec<_>>();
+
+        assert!(Pin::new(&mut set).poll_next(&mut cx).is_pending());
+        assert_eq!(count(&polls), [1, 1, 1, 1, 1]);
+
+        let woken = wakers[3].borrow_mut().take().unwrap();
+        woken.wake();
+        assert!(Pin::new(&mut set).poll_next(&mut cx).is_pending());
+        assert_eq!(count(&polls), [1, 1, 1, 2, 1]);
+
+        // Nothing woken, nothing polled.
+        assert!(Pin::new(&mut set).poll_next(&mut cx).is_pending());
+        assert_eq!(count(&polls), [1, 1, 1, 2, 1]);
+    }
+}
@@ -0,0 +1,25 @@
+//! Synchronization primitives for tasks on the same runtime.
+//!
+//! These are built on `Cell`/`RefCell` rather than atomics: every task of a
+//! `MiniRuntime` runs on one thread, so contention only ever happens across
+//! `.await` points, and waiting means parking a waker rather than blocking.
+
+mod barrier;
+mod cancellation_token;
+mod condvar;
+mod mutex;
+mod notify;
+mod once_cell;
+mod rwlock;
+mod semaphore;
+mod wait_group;
+
+pub use barrier::{Barrier, BarrierWait, BarrierWaitResult};
+pub use cancellation_token::{CancellationToken, WaitForCancellation};
+pub use condvar::Condvar;
+pub use mutex::{Lock, Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use once_cell::OnceCell;
+pub use rwlock::{ReadLock, RwLock, RwLockReadGuard, RwLockWriteGuard, WriteLock};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
+pub use wait_group::{WaitGroup, WaitGroupWait, WaitGuard};
@@ -0,0 +1,175 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// Lets a fixed number of tasks wait for each other: each call to
+/// [`wait`](Barrier::wait) is held until `n` tasks have made one, then all
+/// of them are released together and the barrier resets for the next round.
+pub struct Barrier {
+    n: usize,
+    arrived: Cell<usize>,
+    /// Bumped each time a full round is released.
+    generation: Cell<u64>,
+    /// Parked arrivals of the current round as `(ticket, waker)`.
+    waiters: RefCell<Vec<(u64, Waker)>>,
+    next_ticket: Cell<u64>,
+}
+
+/// Future returned by [`Barrier::wait`].
+///
+/// Dropping it after it has been polled does not take back its arrival.
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    /// The round we arrived in and our wait-queue ticket, once parked.
+    parked: Option<(u64, u64)>,
+}
+
+/// What [`Barrier::wait`] resolves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl Barrier {
+    /// Creates a barrier that releases tasks in groups of `n`. A barrier for
+    /// zero tasks behaves like one for a single task.
+    pub fn new(n: usize) -> Self {
+        Self {
+            n: n.max(1),
+            arrived: Cell::new(0),
+            generation: Cell::new(0),
+            waiters: RefCell::new(Vec::new()),
+            next_ticket: Cell::new(0),
+        }
+    }
+
+    /// Waits until `n` tasks are waiting.
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            parked: None,
+        }
+    }
+}
+
+impl BarrierWaitResult {
+    /// Returns `true` for exactly one task per round: the last to arrive.
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<BarrierWaitResult> {
+        let this = self.get_mut();
+        let barrier = this.barrier;
+        if let Some((generation, ticket)) = this.parked {
+            if barrier.generation.get() != generation {
+                this.parked = None;
+                return Poll::Ready(BarrierWaitResult { leader: false });
+            }
+            let mut waiters = barrier.waiters.borrow_mut();
+            if let Some((_, waker)) = waiters.iter_mut().find(|(t, _)| *t == ticket) {
+                waker.clone_from(cx.waker());
+            }
+            return Poll::Pending;
+        }
+
+        let arrived = barrier.arrived.get() + 1;
+        if arrived == barrier.n {
+            barrier.arrived.set(0);
+            barrier.generation.set(barrier.generation.get() + 1);
+            let waiters = std::mem::take(&mut *barrier.waiters.borrow_mut());
+            for (_, waker) in waiters {
+                waker.wake();
+            }
+            return Poll::Ready(BarrierWaitResult { leader: true });
+        }
+        barrier.arrived.set(arrived);
+        let ticket = barrier.next_ticket.get();
+        barrier.next_ticket.set(ticket + 1);
+        barrier
+            .waiters
+            .borrow_mut()
+            .push((ticket, cx.waker().clone()));
+        this.parked = Some((barrier.generation.get(), ticket));
+        Poll::Pending
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        if let Some((_, ticket)) = self.parked {
+            self.barrier
+                .waiters
+                .borrow_mut()
+                .retain(|(t, _)| *t != ticket);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sleep};
+
+    #[test]
+    fn no_task_passes_until_all_three_arrive() {
+        let mut rt = MiniRuntime::new();
+        let leaders = rt.block_on(async {
+            let barrier = Rc::new(Barrier::new(3));
+            let arrived = Rc::new(Cell::new(0));
+            let tasks: Vec<_> = [20, 5, 10]
+                .into_iter()
+                .map(|millis| {
+                    let (barrier, arrived) = (barrier.clone(), arrived.clone());
+                    Handle::current().spawn(async move {
+                        sleep(Duration::from_millis(millis)).await;
+                        arrived.set(arrived.get() + 1);
+                        let result = barrier.wait().await;
+                        assert_eq!(arrived.get(), 3, "passed the barrier early");
+                        result.is_leader()
+                    })
+                })
+                .collect();
+            let mut leaders = 0;
+            for task in tasks {
+                leaders += usize::from(task.await.unwrap());
+            }
+            leaders
+        });
+        assert_eq!(leaders, 1);
+    }
+
+    #[test]
+    fn the_barrier_resets_for_the_next_round() {
+        let mut rt = MiniRuntime::new();
+        let (ours, theirs) = rt.block_on(async {
+            let barrier = Rc::new(Barrier::new(2));
+            let other = barrier.clone();
+            let peer = Handle::current().spawn(async move {
+                let mut leads = Vec::new();
+                for _ in 0..3 {
+                    leads.push(other.wait().await.is_leader());
+                }
+                leads
+            });
+            let mut leads = Vec::new();
+            for _ in 0..3 {
+                leads.push(barrier.wait().await.is_leader());
+            }
+            (leads, peer.await.unwrap())
+        });
+        // Exactly one leader in every round.
+        for (ours, theirs) in ours.into_iter().zip(theirs) {
+            assert_ne!(ours, theirs);
+        }
+    }
+}
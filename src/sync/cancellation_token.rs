@@ -0,0 +1,219 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    rc::{Rc, Weak},
+    task::{Context, Poll, Waker},
+};
+
+/// A flag tasks can watch to learn they should stop, for cooperative
+/// cancellation.
+///
+/// Clones share the same flag. [`child_token`](CancellationToken::child_token)
+/// derives a token that is cancelled along with its parent, but can also be
+/// cancelled on its own without affecting the parent, so a whole tree of
+/// tasks can be stopped from its root.
+///
+/// A task typically races its work against [`cancelled`]:
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::{CancellationToken, Handle, sleep};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     let token = CancellationToken::new();
+///     let child = token.child_token();
+///     let worker = Handle::current().spawn(async move {
+///         let mut rounds = 0;
+///         loop {
+///             mini_rt::select! {
+///                 _ = child.cancelled() => return rounds,
+///                 _ = sleep(Duration::from_millis(1)) => rounds += 1,
+///             }
+///         }
+///     });
+///     sleep(Duration::from_millis(10)).await;
+///     token.cancel();
+///     assert!(worker.await.unwrap() > 0);
+/// });
+/// ```
+///
+/// [`cancelled`]: CancellationToken::cancelled
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    node: Rc<Node>,
+}
+
+#[derive(Default)]
+struct Node {
+    cancelled: Cell<bool>,
+    /// One slot per pending `cancelled()` future.
+    waiters: RefCell<Vec<Rc<RefCell<Option<Waker>>>>>,
+    children: RefCell<Vec<Weak<Node>>>,
+    /// Keeps the parent's node alive, and with it the path from the root,
+    /// so cancellation still reaches this node after the tokens in between
+    /// are dropped.
+    _parent: Option<Rc<Node>>,
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+pub struct WaitForCancellation<'a> {
+    token: &'a CancellationToken,
+    /// Our entry in the token's waiter list, once we have had to wait.
+    waiter: Option<Rc<RefCell<Option<Waker>>>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token that is cancelled whenever this one is. Cancelling
+    /// the child leaves this token alone.
+    pub fn child_token(&self) -> Self {
+        let child = Self {
+            node: Rc::new(Node {
+                _parent: Some(self.node.clone()),
+                ..Node::default()
+            }),
+        };
+        if self.is_cancelled() {
+            child.node.cancelled.set(true);
+        } else {
+            let mut children = self.node.children.borrow_mut();
+            // Forget children that have been dropped, so long-lived parents
+            // do not accumulate them.
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Rc::downgrade(&child.node));
+        }
+        child
+    }
+
+    /// Cancels this token and every token derived from it, waking all tasks
+    /// waiting in [`cancelled`](CancellationToken::cancelled).
+    pub fn cancel(&self) {
+        self.node.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node.cancelled.get()
+    }
+
+    /// Waits until the token is cancelled. Completes immediately if it
+    /// already is.
+    pub fn cancelled(&self) -> WaitForCancellation<'_> {
+        WaitForCancellation {
+            token: self,
+            waiter: None,
+        }
+    }
+}
+
+impl Node {
+    fn cancel(&self) {
+        if self.cancelled.replace(true) {
+            return;
+        }
+        let waiters = std::mem::take(&mut *self.waiters.borrow_mut());
+        for waiter in waiters {
+            if let Some(waker) = waiter.borrow_mut().take() {
+                waker.wake();
+            }
+        }
+        let children = std::mem::take(&mut *self.children.borrow_mut());
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+impl Future for WaitForCancellation<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.token.is_cancelled() {
+            this.waiter = None;
+            return Poll::Ready(());
+        }
+        match &this.waiter {
+            Some(waiter) => *waiter.borrow_mut() = Some(cx.waker().clone()),
+            None => {
+                let waiter = Rc::new(RefCell::new(Some(cx.waker().clone())));
+                this.token.node.waiters.borrow_mut().push(waiter.clone());
+                this.waiter = Some(waiter);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for WaitForCancellation<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            self.token
+                .node
+                .waiters
+                .borrow_mut()
+                .retain(|other| !Rc::ptr_eq(other, &waiter));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sleep};
+
+    #[test]
+    fn cancelling_a_parent_reaches_children_and_grandchildren() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        let sibling = parent.child_token();
+
+        grandchild.cancel();
+        assert!(grandchild.is_cancelled());
+        assert!(!child.is_cancelled() && !parent.is_cancelled());
+
+        parent.cancel();
+        assert!(child.is_cancelled() && sibling.is_cancelled());
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn dropping_an_intermediate_token_keeps_the_tree_connected() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        drop(child);
+        parent.cancel();
+        assert!(grandchild.is_cancelled());
+    }
+
+    #[test]
+    fn cancellation_wakes_a_grandchild_waiting_mid_sleep() {
+        let mut rt = MiniRuntime::new();
+        let finished_sleep = Rc::new(Cell::new(false));
+        let flag = finished_sleep.clone();
+        rt.block_on(async move {
+            let parent = CancellationToken::new();
+            let grandchild = parent.child_token().child_token();
+            let worker = Handle::current().spawn(async move {
+                crate::select! {
+                    _ = grandchild.cancelled() => {},
+                    _ = sleep(Duration::from_secs(60)) => flag.set(true),
+                }
+            });
+            sleep(Duration::from_millis(5)).await;
+            let start = crate::time::now();
+            parent.cancel();
+            worker.await.unwrap();
+            assert!(crate::time::now() - start < Duration::from_secs(1));
+        });
+        assert!(!finished_sleep.get());
+    }
+}
@@ -0,0 +1,171 @@
+use super::{MutexGuard, notify::Notify};
+
+/// A condition variable for tasks holding a [`Mutex`](super::Mutex) guard.
+///
+/// [`wait`](Condvar::wait) releases the guard and parks until notified,
+/// then locks the mutex again. As with any condition variable, a wait may
+/// also end without the condition having changed, so check it in a loop.
+/// Notifications are not stored: one sent while no task is waiting is
+/// lost.
+///
+/// ```
+/// use std::{collections::VecDeque, rc::Rc};
+/// use mini_rt::{Condvar, Handle, Mutex};
+///
+/// const CAPACITY: usize = 2;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let received = rt.block_on(async {
+///     let buffer = Rc::new((Mutex::new(VecDeque::new()), Condvar::new()));
+///     let producer = {
+///         let buffer = buffer.clone();
+///         Handle::current().spawn(async move {
+///             let (queue, changed) = &*buffer;
+///             for item in 0..6 {
+///                 let mut guard = queue.lock().await;
+///                 while guard.len() == CAPACITY {
+///                     guard = changed.wait(guard).await;
+///                 }
+///                 guard.push_back(item);
+///                 changed.notify_all();
+///             }
+///         })
+///     };
+///     let (queue, changed) = &*buffer;
+///     let mut received = Vec::new();
+///     while received.len() < 6 {
+///         let mut guard = queue.lock().await;
+///         while guard.is_empty() {
+///             guard = changed.wait(guard).await;
+///         }
+///         received.extend(guard.drain(..));
+///         changed.notify_all();
+///     }
+///     producer.await.unwrap();
+///     received
+/// });
+/// assert_eq!(received, [0, 1, 2, 3, 4, 5]);
+/// ```
+pub struct Condvar {
+    notify: Notify,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Self {
+            notify: Notify::without_permits(),
+        }
+    }
+
+    /// Releases `guard`, waits for a notification, and locks the mutex
+    /// again before returning the new guard.
+    pub async fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        let notified = self.notify.notified();
+        // Nothing else runs before `notified` is first polled and parks,
+        // so no notification can slip in between.
+        drop(guard);
+        notified.await;
+        mutex.lock().await
+    }
+
+    /// Wakes one waiting task, if there is one.
+    pub fn notify_one(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wakes every waiting task.
+    pub fn notify_all(&self) {
+        self.notify.notify_waiters();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sync::Mutex, time, yield_now};
+
+    /// Spawns `count` tasks that each wait on the condvar once and then
+    /// count themselves in `woken`.
+    fn spawn_waiters(
+        shared: &Rc<(Mutex<()>, Condvar)>,
+        woken: &Rc<Cell<usize>>,
+        count: usize,
+    ) -> Vec<crate::JoinHandle<()>> {
+        (0..count)
+            .map(|_| {
+                let (shared, woken) = (shared.clone(), woken.clone());
+                Handle::current().spawn(async move {
+                    let (mutex, condvar) = &*shared;
+                    let guard = mutex.lock().await;
+                    drop(condvar.wait(guard).await);
+                    woken.set(woken.get() + 1);
+                })
+            })
+            .collect()
+    }
+
+    /// Gives every runnable task a few turns.
+    async fn settle() {
+        for _ in 0..4 {
+            yield_now().await;
+        }
+    }
+
+    #[test]
+    fn notify_one_wakes_exactly_one_of_two_waiters() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let shared = Rc::new((Mutex::new(()), Condvar::new()));
+            let woken = Rc::new(Cell::new(0));
+            let waiters = spawn_waiters(&shared, &woken, 2);
+            settle().await;
+            shared.1.notify_one();
+            settle().await;
+            assert_eq!(woken.get(), 1);
+            shared.1.notify_one();
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+            assert_eq!(woken.get(), 2);
+        });
+    }
+
+    #[test]
+    fn notify_all_wakes_every_waiter() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let shared = Rc::new((Mutex::new(()), Condvar::new()));
+            let woken = Rc::new(Cell::new(0));
+            let waiters = spawn_waiters(&shared, &woken, 3);
+            settle().await;
+            shared.1.notify_all();
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+            assert_eq!(woken.get(), 3);
+        });
+    }
+
+    #[test]
+    fn notify_one_with_nobody_waiting_is_lost() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            time::pause();
+            let mutex = Mutex::new(());
+            let condvar = Condvar::new();
+            condvar.notify_one();
+            let guard = mutex.lock().await;
+            let waited = time::timeout(Duration::from_millis(50), condvar.wait(guard)).await;
+            assert!(waited.is_err());
+        });
+    }
+}
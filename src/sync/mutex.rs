@@ -0,0 +1,163 @@
+use std::{
+    cell::{RefCell, RefMut},
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::semaphore::{Acquire, Semaphore, SemaphorePermit};
+
+/// An async mutex whose guard may be held across `.await` points.
+///
+/// This is a single-permit [`Semaphore`] guarding a `RefCell`, so contended
+/// lockers wait in FIFO order: when the guard is dropped, the longest-waiting
+/// `lock()` is woken and no newcomer can jump ahead of it.
+pub struct Mutex<T> {
+    semaphore: Semaphore,
+    value: RefCell<T>,
+}
+
+/// Exclusive access to the value behind a [`Mutex`], released on drop.
+pub struct MutexGuard<'a, T> {
+    // Declared first so the borrow is released before the permit wakes the
+    // next locker.
+    value: RefMut<'a, T>,
+    _permit: SemaphorePermit<'a>,
+    /// The mutex the guard came from, for [`Condvar::wait`] to lock again.
+    ///
+    /// [`Condvar::wait`]: super::Condvar::wait
+    pub(super) mutex: &'a Mutex<T>,
+}
+
+/// Future returned by [`Mutex::lock`].
+pub struct Lock<'a, T> {
+    mutex: &'a Mutex<T>,
+    acquire: Acquire<'a>,
+}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(1),
+            value: RefCell::new(value),
+        }
+    }
+
+    /// Waits for the lock and returns a guard for the value.
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            acquire: self.semaphore.acquire(),
+        }
+    }
+
+    /// Takes the lock if it is free and nobody is waiting for it.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire()?;
+        Some(self.guard(permit))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn guard<'a>(&'a self, permit: SemaphorePermit<'a>) -> MutexGuard<'a, T> {
+        MutexGuard {
+            value: self.value.borrow_mut(),
+            _permit: permit,
+            mutex: self,
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        Pin::new(&mut self.acquire)
+            .poll(cx)
+            .map(|permit| mutex.guard(permit))
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, yield_now};
+
+    #[test]
+    fn increments_held_across_an_await_do_not_race() {
+        let mut rt = MiniRuntime::new();
+        let counter = Rc::new(Mutex::new(0));
+        let shared = counter.clone();
+        rt.block_on(async move {
+            let tasks: Vec<_> = (0..2)
+                .map(|_| {
+                    let counter = shared.clone();
+                    Handle::current().spawn(async move {
+                        let mut value = counter.lock().await;
+                        let read = *value;
+                        // The other task runs here, but must wait for the lock.
+                        yield_now().await;
+                        *value = read + 1;
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        });
+        assert_eq!(Rc::into_inner(counter).unwrap().into_inner(), 2);
+    }
+
+    #[test]
+    fn contended_lockers_get_the_lock_in_arrival_order() {
+        let mut rt = MiniRuntime::new();
+        let order = Rc::new(Mutex::new(Vec::new()));
+        let held = Rc::new(Mutex::new(()));
+        let (log, lock) = (order.clone(), held.clone());
+        rt.block_on(async move {
+            let guard = lock.lock().await;
+            let waiters: Vec<_> = (0..3)
+                .map(|n| {
+                    let (log, lock) = (log.clone(), lock.clone());
+                    Handle::current().spawn(async move {
+                        let _guard = lock.lock().await;
+                        log.lock().await.push(n);
+                    })
+                })
+                .collect();
+            yield_now().await;
+            assert!(lock.try_lock().is_none());
+            drop(guard);
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+        });
+        assert_eq!(Rc::into_inner(order).unwrap().into_inner(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Wakes tasks waiting on [`notified()`](Notify::notified), for building
+/// condition-variable-style coordination.
+///
+/// `notify_one` with nobody waiting stores a single permit, so the next
+/// `notified()` completes immediately; `notify_waiters` only wakes tasks
+/// that are already waiting.
+#[derive(Default)]
+pub struct Notify {
+    permit: Cell<bool>,
+    /// Set for condition variables, where a `notify_one` with nobody
+    /// waiting is lost instead of stored.
+    drop_permits: bool,
+    waiters: RefCell<VecDeque<Rc<Waiter>>>,
+}
+
+/// Future returned by [`Notify::notified`].
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    /// Our entry in the wait queue, once we have had to park.
+    waiter: Option<Rc<Waiter>>,
+}
+
+#[derive(Default)]
+struct Waiter {
+    waker: RefCell<Option<Waker>>,
+    notified: Cell<Option<Notification>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Notification {
+    One,
+    All,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a `Notify` whose `notify_one` does nothing when nobody is
+    /// waiting.
+    pub(crate) fn without_permits() -> Self {
+        Self {
+            drop_permits: true,
+            ..Self::default()
+        }
+    }
+
+    /// Waits for a notification.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            waiter: None,
+        }
+    }
+
+    /// Wakes the longest-waiting task, or stores a permit for the next
+    /// `notified()` if nobody is waiting.
+    pub fn notify_one(&self) {
+        let waiter = self.waiters.borrow_mut().pop_front();
+        match waiter {
+            Some(waiter) => waiter.notify(Notification::One),
+            None => self.permit.set(!self.drop_permits),
+        }
+    }
+
+    /// Wakes every task currently waiting, without storing a permit.
+    pub fn notify_waiters(&self) {
+        let waiters = std::mem::take(&mut *self.waiters.borrow_mut());
+        for waiter in waiters {
+            waiter.notify(Notification::All);
+        }
+    }
+}
+
+impl Waiter {
+    fn notify(&self, notification: Notification) {
+        self.notified.set(Some(notification));
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        match &this.waiter {
+            Some(waiter) => {
+                if waiter.notified.get().is_some() {
+                    this.waiter = None;
+                    return Poll::Ready(());
+                }
+                *waiter.waker.borrow_mut() = Some(cx.waker().clone());
+            }
+            None => {
+                if this.notify.permit.replace(false) {
+                    return Poll::Ready(());
+                }
+                let waiter = Rc::new(Waiter::default());
+                *waiter.waker.borrow_mut() = Some(cx.waker().clone());
+                this.notify.waiters.borrow_mut().push_back(waiter.clone());
+                this.waiter = Some(waiter);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let Some(waiter) = self.waiter.take() else {
+            return;
+        };
+        match waiter.notified.get() {
+            // We were picked by notify_one but never saw it: hand it on.
+            Some(Notification::One) => self.notify.notify_one(),
+            Some(Notification::All) => {}
+            None => self
+                .notify
+                .waiters
+                .borrow_mut()
+                .retain(|other| !Rc::ptr_eq(other, &waiter)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Handle, MiniRuntime, yield_now};
+
+    /// Polls `future` once with a waker that does nothing.
+    fn poll_once(future: &mut Notified<'_>) -> Poll<()> {
+        Pin::new(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn notify_before_wait_leaves_a_single_permit() {
+        let notify = Notify::new();
+        notify.notify_one();
+        notify.notify_one();
+        assert!(poll_once(&mut notify.notified()).is_ready());
+        // Permits do not stack up.
+        assert!(poll_once(&mut notify.notified()).is_pending());
+    }
+
+    #[test]
+    fn wait_before_notify_parks_until_notified() {
+        let mut rt = MiniRuntime::new();
+        let notify = Rc::new(Notify::new());
+        let woken = Rc::new(Cell::new(false));
+        let (waiting, flag) = (notify.clone(), woken.clone());
+        rt.spawn(async move {
+            waiting.notified().await;
+            flag.set(true);
+        });
+        rt.run_until_stalled();
+        assert!(!woken.get());
+        notify.notify_one();
+        rt.run_until_stalled();
+        assert!(woken.get());
+    }
+
+    #[test]
+    fn notify_waiters_wakes_every_parked_task() {
+        let mut rt = MiniRuntime::new();
+        let notify = Rc::new(Notify::new());
+        let woken = Rc::new(Cell::new(0));
+        let (shared, counter) = (notify.clone(), woken.clone());
+        rt.block_on(async move {
+            let waiters: Vec<_> = (0..3)
+                .map(|_| {
+                    let (notify, woken) = (shared.clone(), counter.clone());
+                    Handle::current().spawn(async move {
+                        notify.notified().await;
+                        woken.set(woken.get() + 1);
+                    })
+                })
+                .collect();
+            yield_now().await;
+            shared.notify_waiters();
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+        });
+        assert_eq!(woken.get(), 3);
+        // Nobody was waiting any more, so no permit was stored.
+        assert!(poll_once(&mut notify.notified()).is_pending());
+    }
+}
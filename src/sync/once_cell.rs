@@ -0,0 +1,166 @@
+use std::{cell, future::Future};
+
+use super::semaphore::Semaphore;
+
+/// A value initialized at most once, by an async initializer.
+///
+/// Tasks calling [`get_or_init`](OnceCell::get_or_init) together run only
+/// one initializer; the rest wait for it and then see its value. If the
+/// running initializer is dropped before it finishes, the next waiter runs
+/// its own instead.
+///
+/// ```
+/// use std::{cell::Cell, rc::Rc};
+/// use mini_rt::{Handle, OnceCell};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let runs = Rc::new(Cell::new(0));
+/// let cell = Rc::new(OnceCell::new());
+/// let values = rt.block_on({
+///     let runs = runs.clone();
+///     async move {
+///         let callers: Vec<_> = (0..3)
+///             .map(|_| {
+///                 let (cell, runs) = (cell.clone(), runs.clone());
+///                 Handle::current().spawn(async move {
+///                     *cell
+///                         .get_or_init(|| async {
+///                             runs.set(runs.get() + 1);
+///                             mini_rt::yield_now().await;
+///                             42
+///                         })
+///                         .await
+///                 })
+///             })
+///             .collect();
+///         let mut values = Vec::new();
+///         for caller in callers {
+///             values.push(caller.await.unwrap());
+///         }
+///         values
+///     }
+/// });
+/// assert_eq!(values, [42, 42, 42]);
+/// assert_eq!(runs.get(), 1);
+/// ```
+pub struct OnceCell<T> {
+    value: cell::OnceCell<T>,
+    /// Held by the running initializer, so the others queue behind it.
+    init: Semaphore,
+}
+
+impl<T> OnceCell<T> {
+    pub fn new() -> Self {
+        Self {
+            value: cell::OnceCell::new(),
+            init: Semaphore::new(1),
+        }
+    }
+
+    /// Returns the value, if it has been initialized.
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// Returns the value, first running `init` to produce it unless it has
+    /// been initialized already or another caller's initializer finishes
+    /// first.
+    pub async fn get_or_init<F, Fut>(&self, init: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.value.get() {
+            return value;
+        }
+        let _permit = self.init.acquire().await;
+        if let Some(value) = self.value.get() {
+            return value;
+        }
+        let value = init().await;
+        self.value.get_or_init(|| value)
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, time};
+
+    #[test]
+    fn concurrent_callers_share_one_initializer() {
+        let mut rt = MiniRuntime::new();
+        let runs = Rc::new(Cell::new(0));
+        let values = rt.block_on({
+            let runs = runs.clone();
+            async move {
+                time::pause();
+                let cell = Rc::new(OnceCell::new());
+                let callers: Vec<_> = (0..5)
+                    .map(|n| {
+                        let (cell, runs) = (cell.clone(), runs.clone());
+                        Handle::current().spawn(async move {
+                            *cell
+                                .get_or_init(|| async move {
+                                    runs.set(runs.get() + 1);
+                                    time::sleep(Duration::from_millis(10)).await;
+                                    n
+                                })
+                                .await
+                        })
+                    })
+                    .collect();
+                let mut values = Vec::new();
+                for caller in callers {
+                    values.push(caller.await.unwrap());
+                }
+                values
+            }
+        });
+        // The first caller's initializer won, and no other ran at all.
+        assert_eq!(values, [0; 5]);
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn a_cancelled_initializer_lets_the_next_caller_initialize() {
+        let mut rt = MiniRuntime::new();
+        let value = rt.block_on(async {
+            time::pause();
+            let cell = Rc::new(OnceCell::new());
+            let slow = {
+                let cell = cell.clone();
+                Handle::current().spawn(async move {
+                    *cell
+                        .get_or_init(|| async {
+                            time::sleep(Duration::from_secs(60)).await;
+                            "slow"
+                        })
+                        .await
+                })
+            };
+            let next = {
+                let cell = cell.clone();
+                Handle::current().spawn(async move { *cell.get_or_init(|| async { "next" }).await })
+            };
+            crate::yield_now().await;
+            assert!(cell.get().is_none());
+            slow.abort();
+            assert!(slow.await.unwrap_err().is_cancelled());
+            next.await.unwrap()
+        });
+        assert_eq!(value, "next");
+    }
+}
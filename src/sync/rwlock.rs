@@ -0,0 +1,258 @@
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::semaphore::{Acquire, Semaphore, SemaphorePermit};
+
+/// How many readers may hold the lock at once; this many permits make a
+/// writer.
+const MAX_READERS: usize = u32::MAX as usize;
+
+/// An async reader-writer lock whose guards may be held across `.await`
+/// points.
+///
+/// Any number of readers may hold the lock together, or one writer alone.
+/// Lockers queue in FIFO order on a [`Semaphore`]: a reader takes one
+/// permit and a writer takes them all, so a waiting writer holds up readers
+/// that arrive after it instead of being starved by them.
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use mini_rt::{Handle, sync::RwLock};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let order = rt.block_on(async {
+///     let lock = Rc::new(RwLock::new(0));
+///     let order = Rc::new(RefCell::new(Vec::new()));
+///     let first = lock.read().await;
+///     let second = lock.read().await;
+///
+///     let (writer_lock, log) = (lock.clone(), order.clone());
+///     let writer = Handle::current().spawn(async move {
+///         *writer_lock.write().await += 1;
+///         log.borrow_mut().push("writer");
+///     });
+///     mini_rt::yield_now().await;
+///     let (reader_lock, log) = (lock.clone(), order.clone());
+///     let reader = Handle::current().spawn(async move {
+///         assert_eq!(*reader_lock.read().await, 1);
+///         log.borrow_mut().push("late reader");
+///     });
+///     mini_rt::yield_now().await;
+///     // The writer still waits for both readers, and the late reader for it.
+///     assert!(order.borrow().is_empty());
+///     assert_eq!(*first + *second, 0);
+///     drop((first, second));
+///     writer.await.unwrap();
+///     reader.await.unwrap();
+///     order.take()
+/// });
+/// assert_eq!(order, ["writer", "late reader"]);
+/// ```
+pub struct RwLock<T> {
+    semaphore: Semaphore,
+    value: RefCell<T>,
+}
+
+/// Shared access to the value behind a [`RwLock`], released on drop.
+pub struct RwLockReadGuard<'a, T> {
+    // Declared first so the borrow is released before the permit wakes the
+    // next locker.
+    value: Ref<'a, T>,
+    _permit: SemaphorePermit<'a>,
+}
+
+/// Exclusive access to the value behind a [`RwLock`], released on drop.
+pub struct RwLockWriteGuard<'a, T> {
+    value: RefMut<'a, T>,
+    _permit: SemaphorePermit<'a>,
+}
+
+/// Future returned by [`RwLock::read`].
+pub struct ReadLock<'a, T> {
+    lock: &'a RwLock<T>,
+    acquire: Acquire<'a>,
+}
+
+/// Future returned by [`RwLock::write`].
+pub struct WriteLock<'a, T> {
+    lock: &'a RwLock<T>,
+    acquire: Acquire<'a>,
+}
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(MAX_READERS),
+            value: RefCell::new(value),
+        }
+    }
+
+    /// Waits until no writer holds or is queued ahead for the lock, and
+    /// returns a guard for reading the value.
+    pub fn read(&self) -> ReadLock<'_, T> {
+        ReadLock {
+            lock: self,
+            acquire: self.semaphore.acquire(),
+        }
+    }
+
+    /// Waits until every earlier reader and writer is done, and returns a
+    /// guard for changing the value.
+    pub fn write(&self) -> WriteLock<'_, T> {
+        WriteLock {
+            lock: self,
+            acquire: self.semaphore.acquire_many(MAX_READERS),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<'a, T> Future for ReadLock<'a, T> {
+    type Output = RwLockReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.lock;
+        Pin::new(&mut self.acquire)
+            .poll(cx)
+            .map(|permit| RwLockReadGuard {
+                value: lock.value.borrow(),
+                _permit: permit,
+            })
+    }
+}
+
+impl<'a, T> Future for WriteLock<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.lock;
+        Pin::new(&mut self.acquire)
+            .poll(cx)
+            .map(|permit| RwLockWriteGuard {
+                value: lock.value.borrow_mut(),
+                _permit: permit,
+            })
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, time};
+
+    #[test]
+    fn readers_hold_the_lock_together() {
+        let mut rt = MiniRuntime::new();
+        let (most, took) = rt.block_on(async {
+            time::pause();
+            let lock = Rc::new(RwLock::new(()));
+            let (holding, most) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+            let start = time::now();
+            let readers: Vec<_> = (0..3)
+                .map(|_| {
+                    let (lock, holding, most) = (lock.clone(), holding.clone(), most.clone());
+                    Handle::current().spawn(async move {
+                        let _guard = lock.read().await;
+                        holding.set(holding.get() + 1);
+                        most.set(most.get().max(holding.get()));
+                        time::sleep(Duration::from_millis(10)).await;
+                        holding.set(holding.get() - 1);
+                    })
+                })
+                .collect();
+            for reader in readers {
+                reader.await.unwrap();
+            }
+            (most.get(), time::now() - start)
+        });
+        assert_eq!(most, 3);
+        // Had they taken turns this would be 30ms.
+        assert_eq!(took, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn a_waiting_writer_holds_up_readers_that_come_after_it() {
+        let mut rt = MiniRuntime::new();
+        let log = rt.block_on(async {
+            time::pause();
+            let lock = Rc::new(RwLock::new(0));
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let start = time::now();
+            let stamp = move |log: &RefCell<Vec<_>>, what| {
+                log.borrow_mut()
+                    .push((what, (time::now() - start).as_millis()))
+            };
+            let spawn = |what, write: bool, after: u64| {
+                let (lock, log) = (lock.clone(), log.clone());
+                Handle::current().spawn(async move {
+                    time::sleep(Duration::from_millis(after)).await;
+                    if write {
+                        let mut guard = lock.write().await;
+                        stamp(&log, what);
+                        *guard += 1;
+                        time::sleep(Duration::from_millis(10)).await;
+                    } else {
+                        let guard = lock.read().await;
+                        stamp(&log, what);
+                        assert_eq!(*guard, if what == "early" { 0 } else { 1 });
+                        time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+            };
+            let tasks = [
+                spawn("early", false, 0),
+                spawn("writer", true, 1),
+                spawn("late", false, 2),
+            ];
+            for task in tasks {
+                task.await.unwrap();
+            }
+            log.take()
+        });
+        // The late reader could have shared the lock with the early one at
+        // 2ms, but waits behind the writer instead.
+        assert_eq!(log, [("early", 0), ("writer", 10), ("late", 20)]);
+    }
+}
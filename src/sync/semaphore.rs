@@ -0,0 +1,222 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// A counting semaphore for limiting how many tasks do something at once.
+///
+/// Waiters are served in FIFO order: a released permit always goes to the
+/// longest-waiting `acquire()`, never to a newcomer.
+pub struct Semaphore {
+    permits: Cell<usize>,
+    /// Parked acquirers as `(ticket, waker)`, oldest first.
+    waiters: RefCell<VecDeque<(u64, Waker)>>,
+    next_ticket: Cell<u64>,
+}
+
+/// Permits from a [`Semaphore`], returned to it on drop.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+/// Future returned by [`Semaphore::acquire`] and
+/// [`acquire_many`](Semaphore::acquire_many).
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    /// Our place in the wait queue, once we have had to park.
+    ticket: Option<u64>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: Cell::new(permits),
+            waiters: RefCell::new(VecDeque::new()),
+            next_ticket: Cell::new(0),
+        }
+    }
+
+    /// Waits for a permit.
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Waits for `n` permits at once. While it waits at the front of the
+    /// queue it holds up everyone behind it, even those needing fewer
+    /// permits than are free.
+    pub fn acquire_many(&self, n: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            permits: n,
+            ticket: None,
+        }
+    }
+
+    /// Takes a permit if one is free and nobody is waiting for it.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if self.permits.get() == 0 || !self.waiters.borrow().is_empty() {
+            return None;
+        }
+        Some(self.take_permits(1))
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.permits.get()
+    }
+
+    /// Adds `n` new permits, waking waiters to claim them.
+    pub fn add_permits(&self, n: usize) {
+        self.permits.set(self.permits.get() + n);
+        self.wake_first();
+    }
+
+    fn take_permits(&self, n: usize) -> SemaphorePermit<'_> {
+        self.permits.set(self.permits.get() - n);
+        SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        }
+    }
+
+    fn wake_first(&self) {
+        if self.permits.get() > 0
+            && let Some((_, waker)) = self.waiters.borrow().front()
+        {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let semaphore = this.semaphore;
+        let mut waiters = semaphore.waiters.borrow_mut();
+
+        if semaphore.permits.get() >= this.permits {
+            let first_in_line = match this.ticket {
+                None => waiters.is_empty(),
+                Some(ticket) => waiters.front().is_some_and(|(first, _)| *first == ticket),
+            };
+            if first_in_line {
+                if this.ticket.take().is_some() {
+                    waiters.pop_front();
+                }
+                drop(waiters);
+                let permit = semaphore.take_permits(this.permits);
+                // More permits may be left over for whoever is next.
+                semaphore.wake_first();
+                return Poll::Ready(permit);
+            }
+        }
+
+        match this.ticket {
+            Some(ticket) => {
+                if let Some((_, waker)) = waiters.iter_mut().find(|(t, _)| *t == ticket) {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let ticket = semaphore.next_ticket.get();
+                semaphore.next_ticket.set(ticket + 1);
+                waiters.push_back((ticket, cx.waker().clone()));
+                this.ticket = Some(ticket);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else { return };
+        let mut waiters = self.semaphore.waiters.borrow_mut();
+        let Some(index) = waiters.iter().position(|(t, _)| *t == ticket) else {
+            return;
+        };
+        waiters.remove(index);
+        drop(waiters);
+        // If we were next in line, pass our turn on.
+        if index == 0 {
+            self.semaphore.wake_first();
+        }
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sleep, time};
+
+    #[test]
+    fn no_more_than_the_permits_run_at_once() {
+        let mut rt = MiniRuntime::new();
+        let semaphore = Rc::new(Semaphore::new(2));
+        let (running, peak) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+        let (current, highest) = (running.clone(), peak.clone());
+        rt.block_on(async move {
+            time::pause();
+            let tasks: Vec<_> = (0..5)
+                .map(|_| {
+                    let (semaphore, running, peak) =
+                        (semaphore.clone(), current.clone(), highest.clone());
+                    Handle::current().spawn(async move {
+                        let _permit = semaphore.acquire().await;
+                        running.set(running.get() + 1);
+                        peak.set(peak.get().max(running.get()));
+                        sleep(Duration::from_millis(10)).await;
+                        running.set(running.get() - 1);
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(semaphore.available_permits(), 2);
+        });
+        assert_eq!(peak.get(), 2);
+        assert_eq!(running.get(), 0);
+    }
+
+    #[test]
+    fn released_permits_go_to_the_longest_waiter() {
+        let mut rt = MiniRuntime::new();
+        let semaphore = Rc::new(Semaphore::new(0));
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let log = order.clone();
+        rt.block_on(async move {
+            let waiters: Vec<_> = (0..3)
+                .map(|n| {
+                    let (semaphore, log) = (semaphore.clone(), log.clone());
+                    Handle::current().spawn(async move {
+                        let _permit = semaphore.acquire().await;
+                        log.borrow_mut().push(n);
+                    })
+                })
+                .collect();
+            crate::yield_now().await;
+            // A newcomer cannot take the permit from those already waiting.
+            semaphore.add_permits(1);
+            assert!(semaphore.try_acquire().is_none());
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+        });
+        assert_eq!(*order.borrow(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Waits for a changing number of pieces of work to finish, without holding
+/// on to their `JoinHandle`s.
+///
+/// Each piece of work holds a [`WaitGuard`] from [`add`](WaitGroup::add);
+/// [`wait`](WaitGroup::wait) completes once every guard has been dropped.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mini_rt::{Handle, MiniRuntime, WaitGroup, sleep};
+///
+/// MiniRuntime::new().block_on(async {
+///     let group = WaitGroup::new();
+///     for millis in [30, 10, 20] {
+///         let guard = group.add();
+///         Handle::current().spawn(async move {
+///             sleep(Duration::from_millis(millis)).await;
+///             guard.done();
+///         });
+///     }
+///     group.wait().await;
+///     assert_eq!(group.outstanding(), 0);
+/// });
+/// ```
+#[derive(Default)]
+pub struct WaitGroup {
+    inner: Rc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    outstanding: Cell<usize>,
+    /// Parked `wait()` calls as `(ticket, waker)`.
+    waiters: RefCell<Vec<(u64, Waker)>>,
+    next_ticket: Cell<u64>,
+}
+
+/// Marks one outstanding piece of work in a [`WaitGroup`] until dropped.
+pub struct WaitGuard {
+    inner: Rc<Inner>,
+}
+
+/// Future returned by [`WaitGroup::wait`].
+pub struct WaitGroupWait<'a> {
+    group: &'a WaitGroup,
+    /// Our wait-queue ticket, once we have had to park.
+    ticket: Option<u64>,
+}
+
+impl WaitGroup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers one more piece of work, finished when the returned guard is
+    /// dropped.
+    pub fn add(&self) -> WaitGuard {
+        let outstanding = &self.inner.outstanding;
+        outstanding.set(outstanding.get() + 1);
+        WaitGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Returns how many guards are still alive.
+    pub fn outstanding(&self) -> usize {
+        self.inner.outstanding.get()
+    }
+
+    /// Waits until no guards are left. Completes immediately if there are
+    /// none now.
+    pub fn wait(&self) -> WaitGroupWait<'_> {
+        WaitGroupWait {
+            group: self,
+            ticket: None,
+        }
+    }
+}
+
+impl WaitGuard {
+    /// Marks the work as finished; the same as dropping the guard.
+    pub fn done(self) {}
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        let outstanding = self.inner.outstanding.get() - 1;
+        self.inner.outstanding.set(outstanding);
+        if outstanding == 0 {
+            let waiters = std::mem::take(&mut *self.inner.waiters.borrow_mut());
+            for (_, waker) in waiters {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Future for WaitGroupWait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let inner = &this.group.inner;
+        if inner.outstanding.get() == 0 {
+            this.ticket = None;
+            return Poll::Ready(());
+        }
+        let mut waiters = inner.waiters.borrow_mut();
+        let parked = this
+            .ticket
+            .and_then(|ticket| waiters.iter_mut().find(|(t, _)| *t == ticket));
+        match parked {
+            Some((_, waker)) => waker.clone_from(cx.waker()),
+            // Not parked yet, or woken by work that finished before more
+            // was added.
+            None => {
+                let ticket = inner.next_ticket.get();
+                inner.next_ticket.set(ticket + 1);
+                waiters.push((ticket, cx.waker().clone()));
+                this.ticket = Some(ticket);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for WaitGroupWait<'_> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket {
+            self.group
+                .inner
+                .waiters
+                .borrow_mut()
+                .retain(|(t, _)| *t != ticket);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        task::Wake,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sleep};
+
+    /// Records whether it has been woken.
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wait_after_all_work_is_done_completes_at_once() {
+        let group = WaitGroup::new();
+        let guards = [group.add(), group.add()];
+        drop(guards);
+        let mut wait = group.wait();
+        let poll = Pin::new(&mut wait).poll(&mut Context::from_waker(Waker::noop()));
+        assert!(poll.is_ready());
+    }
+
+    #[test]
+    fn every_waiter_is_woken() {
+        let mut rt = MiniRuntime::new();
+        let group = Rc::new(WaitGroup::new());
+        let woken = Rc::new(Cell::new(0));
+        let guard = group.add();
+        let (waiting, counted) = (group.clone(), woken.clone());
+        rt.block_on(async move {
+            for _ in 0..3 {
+                let (group, woken) = (waiting.clone(), counted.clone());
+                Handle::current().spawn(async move {
+                    group.wait().await;
+                    woken.set(woken.get() + 1);
+                });
+            }
+            sleep(Duration::from_millis(5)).await;
+            assert_eq!(counted.get(), 0);
+            guard.done();
+        });
+        assert_eq!(woken.get(), 3);
+        assert!(group.inner.waiters.borrow().is_empty());
+    }
+
+    #[test]
+    fn waiter_parks_again_if_work_is_added_before_it_runs() {
+        let group = WaitGroup::new();
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut wait = group.wait();
+
+        let first = group.add();
+        assert!(Pin::new(&mut wait).poll(&mut cx).is_pending());
+        drop(first);
+        assert!(flag.0.swap(false, Ordering::SeqCst));
+
+        let second = group.add();
+        assert!(Pin::new(&mut wait).poll(&mut cx).is_pending());
+        drop(second);
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(Pin::new(&mut wait).poll(&mut cx).is_ready());
+    }
+}
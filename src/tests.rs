@@ -0,0 +1,1146 @@
+//! Tests of the scheduler, the task handles, and the crate-level macros.
+
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Builder, Handle, MiniRuntime, future, time::MockClock};
+
+/// Polls `future` once with a waker that does nothing.
+fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    Pin::new(future).poll(&mut Context::from_waker(Waker::noop()))
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    should_panic(expected = "join_all! polled after completion")
+)]
+fn join_all_catches_a_poll_after_completion() {
+    let mut joined = crate::join_all!(future::ready(1), future::ready("two"));
+    assert_eq!(poll_once(&mut joined), Poll::Ready((1, "two")));
+    // Without debug assertions this is merely pending forever.
+    assert!(poll_once(&mut joined).is_pending());
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    should_panic(expected = "try_join! polled after completion")
+)]
+fn try_join_catches_a_poll_after_completion() {
+    let mut joined = crate::try_join!(
+        future::ready(Ok::<_, ()>(1)),
+        future::ready(Err::<u8, _>(()))
+    );
+    assert_eq!(poll_once(&mut joined), Poll::Ready(Err(())));
+    assert!(poll_once(&mut joined).is_pending());
+}
+
+/// Sleeps for `millis`, then returns `value`.
+async fn after<T>(millis: u64, value: T) -> T {
+    crate::sleep(Duration::from_millis(millis)).await;
+    value
+}
+
+#[test]
+fn join_all_runs_futures_concurrently_and_returns_every_output() {
+    let mut rt = MiniRuntime::new();
+    let (outputs, took) = rt.block_on(async {
+        crate::time::pause();
+        let start = crate::time::now();
+        let outputs = crate::join_all!(after(30, 1u8), after(10, "two"), after(20, 3.0f32)).await;
+        (outputs, crate::time::now() - start)
+    });
+    assert_eq!(outputs, (1, "two", 3.0));
+    // The longest sleep, not the sum of all three.
+    assert_eq!(took, Duration::from_millis(30));
+}
+
+#[test]
+fn join_all_stops_polling_a_finished_future() {
+    let polls = Rc::new(Cell::new(0));
+    let counter = polls.clone();
+    let quick = future::poll_fn(move |_| {
+        counter.set(counter.get() + 1);
+        Poll::Ready(())
+    });
+    let mut rt = MiniRuntime::new();
+    rt.block_on(async move {
+        crate::join_all!(quick, after(5, ())).await;
+    });
+    assert_eq!(polls.get(), 1);
+}
+
+#[test]
+fn try_join_returns_every_output_when_all_succeed() {
+    let mut rt = MiniRuntime::new();
+    let joined = rt.block_on(async {
+        crate::time::pause();
+        crate::try_join!(after(10, Ok::<_, &str>(1)), after(5, Ok('x'))).await
+    });
+    assert_eq!(joined, Ok((1, 'x')));
+}
+
+/// Sets its flag when dropped.
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+fn try_join_returns_the_first_error_and_drops_the_rest() {
+    let mut rt = MiniRuntime::new();
+    let seen = Rc::new(Cell::new(false));
+    let flag = DropFlag(seen.clone());
+    let (joined, took) = rt.block_on(async move {
+        crate::time::pause();
+        let start = crate::time::now();
+        let slow = async move {
+            let _flag = flag;
+            after(50, Ok::<u8, _>(1)).await
+        };
+        let joined = crate::try_join!(slow, after(5, Err::<(), _>("boom"))).await;
+        // Dropped on the spot, not merely once `block_on` is done.
+        assert!(seen.get());
+        (joined, crate::time::now() - start)
+    });
+    assert_eq!(joined, Err("boom"));
+    assert_eq!(took, Duration::from_millis(5));
+}
+
+#[test]
+fn aborting_a_sleeping_task_stops_it_for_good() {
+    let mut rt = MiniRuntime::new();
+    let completed = Rc::new(Cell::new(false));
+    let flag = completed.clone();
+    let (outcome, timers) = rt.block_on(async move {
+        crate::time::pause();
+        let sleeper = Handle::current().spawn(async move {
+            crate::sleep(Duration::from_secs(1)).await;
+            flag.set(true);
+        });
+        crate::yield_now().await;
+        sleeper.abort();
+        let outcome = sleeper.await;
+        // Waiting past the deadline shows the task is really gone.
+        crate::sleep(Duration::from_secs(2)).await;
+        (outcome, Handle::current().pending_timers())
+    });
+    assert!(outcome.unwrap_err().is_cancelled());
+    assert!(!completed.get());
+    assert_eq!(timers, 0);
+    assert_eq!(rt.active_tasks(), 0);
+}
+
+#[test]
+fn aborting_a_finished_task_changes_nothing() {
+    let mut rt = MiniRuntime::new();
+    let task = rt.spawn(async { 5 });
+    let abort = task.abort_handle();
+    rt.run_until_stalled();
+    abort.abort();
+    assert_eq!(rt.block_on(task).unwrap(), 5);
+}
+
+#[test]
+fn a_panicking_task_does_not_take_the_others_down() {
+    let mut rt = MiniRuntime::new();
+    let (bad, good) = rt.block_on(async {
+        let bad = Handle::current().spawn(async {
+            crate::yield_now().await;
+            panic!("task went wrong");
+        });
+        let good = Handle::current().spawn(async {
+            for _ in 0..3 {
+                crate::yield_now().await;
+            }
+            "fine"
+        });
+        (bad.await, good.await)
+    });
+    assert_eq!(good.unwrap(), "fine");
+    let payload = bad.unwrap_err().into_panic();
+    assert_eq!(*payload.downcast::<&str>().unwrap(), "task went wrong");
+    assert_eq!(rt.active_tasks(), 0);
+}
+
+#[test]
+fn a_spawned_task_can_spawn_through_the_current_handle() {
+    let mut rt = MiniRuntime::new();
+    let ran = Rc::new(Cell::new(false));
+    let flag = ran.clone();
+    rt.spawn(async move {
+        // Not awaited: block_on still runs the child to completion.
+        Handle::current().spawn(async move { flag.set(true) });
+    });
+    rt.block_on(async {});
+    assert!(ran.get());
+    assert_eq!(rt.metrics().total_spawned, 3);
+}
+
+#[test]
+#[should_panic(expected = "Handle::current called outside of a runtime")]
+fn current_handle_outside_a_runtime_panics() {
+    Handle::current();
+}
+
+#[test]
+fn metrics_count_spawns_polls_and_pending_work() {
+    let mut rt = MiniRuntime::new();
+    for _ in 0..4 {
+        rt.spawn(async {});
+    }
+    // The four tasks are queued ahead of the root; each finishes in one poll.
+    rt.block_on(async {});
+    let metrics = rt.metrics();
+    assert_eq!(metrics.total_spawned, 5);
+    assert_eq!(metrics.total_polls, 5);
+    assert_eq!(metrics.pending_tasks, 0);
+    assert_eq!(metrics.pending_timers, 0);
+
+    for _ in 0..3 {
+        rt.spawn(crate::sleep(Duration::from_secs(60)));
+    }
+    rt.run_until_stalled();
+    let metrics = rt.metrics();
+    assert_eq!(metrics.total_spawned, 8);
+    assert_eq!(metrics.total_polls, 8);
+    assert_eq!(metrics.pending_tasks, 3);
+    assert_eq!(metrics.pending_timers, 3);
+}
+
+#[test]
+fn task_ids_are_unique_and_match_inside_the_task() {
+    let mut rt = MiniRuntime::new();
+    let handles: Vec<_> = (0..8)
+        .map(|_| rt.spawn(async { crate::current_task_id().unwrap() }))
+        .collect();
+    let ids: Vec<_> = handles.iter().map(|handle| handle.id()).collect();
+    let seen = rt.block_on(async move {
+        let mut seen = Vec::new();
+        for handle in handles {
+            seen.push(handle.await.unwrap());
+        }
+        seen
+    });
+    assert_eq!(seen, ids);
+    let unique: std::collections::HashSet<_> = ids.iter().collect();
+    assert_eq!(unique.len(), ids.len());
+    assert_eq!(crate::current_task_id(), None);
+}
+
+#[test]
+fn shutdown_runs_spawned_tasks_to_completion() {
+    let mut rt = MiniRuntime::new();
+    let finished = Rc::new(Cell::new(0));
+    let late = Rc::new(RefCell::new(None));
+    for millis in [5, 10, 15] {
+        let (finished, late) = (finished.clone(), late.clone());
+        rt.spawn(async move {
+            crate::sleep(Duration::from_millis(millis)).await;
+            finished.set(finished.get() + 1);
+            // Spawned after shutdown began, so never run.
+            *late.borrow_mut() = Some(Handle::current().spawn(async {}));
+        });
+    }
+    rt.shutdown();
+    assert_eq!(finished.get(), 3);
+    let late = late.take().unwrap();
+    assert!(late.is_finished());
+    let err = MiniRuntime::new().block_on(late).unwrap_err();
+    assert!(err.is_cancelled());
+}
+
+#[test]
+fn shutdown_timeout_drops_tasks_still_running_at_the_deadline() {
+    let mut rt = MiniRuntime::new();
+    let (quick, dropped) = (Rc::new(Cell::new(false)), Rc::new(Cell::new(false)));
+    let done = quick.clone();
+    rt.spawn(async move {
+        crate::sleep(Duration::from_millis(5)).await;
+        done.set(true);
+    });
+    let flag = DropFlag(dropped.clone());
+    let stuck = rt.spawn(async move {
+        let _flag = flag;
+        crate::sleep(Duration::from_secs(60)).await;
+    });
+    let start = Instant::now();
+    rt.shutdown_timeout(Duration::from_millis(30));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(quick.get());
+    assert!(dropped.get());
+    let err = MiniRuntime::new().block_on(stuck).unwrap_err();
+    assert!(err.is_cancelled());
+}
+
+#[test]
+#[should_panic(expected = "cannot call block_on from within a runtime")]
+fn nested_block_on_panics() {
+    let mut rt = MiniRuntime::new();
+    rt.block_on(async {
+        MiniRuntime::new().block_on(async {});
+    });
+}
+
+#[test]
+fn yielding_tasks_take_turns() {
+    let mut rt = MiniRuntime::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for name in ['a', 'b'] {
+        let log = log.clone();
+        rt.spawn(async move {
+            for round in 0..3 {
+                log.borrow_mut().push((name, round));
+                crate::yield_now().await;
+            }
+        });
+    }
+    rt.run_until_stalled();
+    assert_eq!(
+        *log.borrow(),
+        [('a', 0), ('b', 0), ('a', 1), ('b', 1), ('a', 2), ('b', 2)]
+    );
+    // One poll per yield plus the last: the re-queue is a single wake, not
+    // a spin.
+    assert_eq!(rt.metrics().total_polls, 8);
+}
+
+#[test]
+fn a_detached_task_keeps_running_and_a_guarded_one_stops() {
+    let mut rt = MiniRuntime::new();
+    let (detached, guarded) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+    let ticker = |count: Rc<Cell<u32>>| async move {
+        for _ in 0..5 {
+            crate::yield_now().await;
+            count.set(count.get() + 1);
+        }
+    };
+    rt.spawn(ticker(detached.clone())).detach();
+    let guard = rt.spawn(ticker(guarded.clone())).abort_on_drop();
+    assert!(rt.tick());
+    assert!(rt.tick());
+    drop(guard);
+    rt.run_until_stalled();
+    assert_eq!(detached.get(), 5);
+    assert_eq!(guarded.get(), 0);
+    assert_eq!(rt.active_tasks(), 0);
+}
+
+#[test]
+fn an_awaited_guard_resolves_like_its_handle() {
+    let mut rt = MiniRuntime::new();
+    let guard = rt.spawn(async { 7 }).abort_on_drop();
+    assert_eq!(rt.block_on(guard).unwrap(), 7);
+}
+
+#[test]
+fn a_spawn_storm_stays_within_max_concurrent_tasks() {
+    let mut rt = Builder::new().max_concurrent_tasks(10).build();
+    let (in_flight, peak, finished) = (
+        Rc::new(Cell::new(0)),
+        Rc::new(Cell::new(0)),
+        Rc::new(Cell::new(0)),
+    );
+    for _ in 0..100 {
+        let (in_flight, peak, finished) = (in_flight.clone(), peak.clone(), finished.clone());
+        rt.spawn(async move {
+            in_flight.set(in_flight.get() + 1);
+            peak.set(peak.get().max(in_flight.get()));
+            for _ in 0..3 {
+                crate::yield_now().await;
+            }
+            in_flight.set(in_flight.get() - 1);
+            finished.set(finished.get() + 1);
+        });
+    }
+    assert_eq!(rt.active_tasks(), 100);
+    rt.run_until_stalled();
+    assert_eq!(peak.get(), 10);
+    assert_eq!(finished.get(), 100);
+    assert_eq!(rt.active_tasks(), 0);
+}
+
+/// Yields `rounds` times, then records `name` as finished.
+async fn yield_then_log(rounds: usize, name: char, log: Rc<RefCell<Vec<char>>>) {
+    for _ in 0..rounds {
+        crate::yield_now().await;
+    }
+    log.borrow_mut().push(name);
+}
+
+#[test]
+fn high_priority_tasks_finish_ahead_of_low_ones() {
+    let mut rt = MiniRuntime::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for _ in 0..3 {
+        rt.spawn_with_priority(yield_then_log(10, 'l', log.clone()), crate::Priority::Low);
+        rt.spawn_with_priority(yield_then_log(10, 'h', log.clone()), crate::Priority::High);
+    }
+    rt.run_until_stalled();
+    let log = log.borrow();
+    let mean_position = |name| {
+        let positions: Vec<_> = (0..log.len()).filter(|&i| log[i] == name).collect();
+        positions.iter().sum::<usize>() as f64 / positions.len() as f64
+    };
+    assert!(
+        mean_position('h') < mean_position('l'),
+        "finished as {log:?}"
+    );
+}
+
+#[test]
+fn a_busy_high_priority_task_does_not_starve_a_low_one() {
+    let mut rt = MiniRuntime::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    rt.spawn_with_priority(
+        yield_then_log(1000, 'h', log.clone()),
+        crate::Priority::High,
+    );
+    rt.spawn_with_priority(yield_then_log(1, 'l', log.clone()), crate::Priority::Low);
+    rt.run_until_stalled();
+    assert_eq!(*log.borrow(), ['l', 'h']);
+}
+
+#[test]
+fn every_task_keeps_one_slot_for_the_whole_run() {
+    let mut rt = MiniRuntime::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let handles: Vec<_> = (0..10)
+        .map(|_| {
+            let log = log.clone();
+            rt.spawn(async move {
+                for _ in 0..3 {
+                    log.borrow_mut().push(crate::current_task_id().unwrap());
+                    crate::yield_now().await;
+                }
+                log.borrow_mut().push(crate::current_task_id().unwrap());
+            })
+        })
+        .collect();
+    let ids: Vec<_> = handles.iter().map(|handle| handle.id()).collect();
+    let live = rt.block_on(async {
+        crate::yield_now().await;
+        // The ten tasks plus this root, each stored exactly once.
+        crate::RUNTIME.with(|rt| rt.borrow().as_ref().unwrap().borrow().tasks.len())
+    });
+    rt.run_until_stalled();
+    assert_eq!(live, 11);
+    // Four polls each, round by round, with no task ever polled twice in a
+    // round.
+    let log = log.borrow();
+    assert_eq!(log.len(), 40);
+    for round in log.chunks(10) {
+        assert_eq!(round, ids);
+    }
+    assert!(rt.shared.borrow().tasks.is_empty());
+    assert_eq!(rt.metrics().total_polls, 40 + 2);
+}
+
+#[test]
+fn block_on_timeout_returns_once_the_root_finishes_in_time() {
+    let mut rt = MiniRuntime::new();
+    let start = Instant::now();
+    let output = rt.block_on_timeout(
+        async {
+            // Left running: it must not hold the call open.
+            Handle::current().spawn(crate::sleep(Duration::from_secs(2)));
+            crate::sleep(Duration::from_millis(5)).await;
+            1
+        },
+        Duration::from_millis(500),
+    );
+    assert!(matches!(output, Ok(1)));
+    assert!(
+        start.elapsed() < Duration::from_millis(400),
+        "took {:?}",
+        start.elapsed()
+    );
+    assert_eq!(rt.active_tasks(), 1);
+}
+
+#[test]
+fn block_on_timeout_gives_up_on_a_root_past_the_limit() {
+    let mut rt = MiniRuntime::new();
+    let dropped = Rc::new(Cell::new(false));
+    let flag = DropFlag(dropped.clone());
+    let start = Instant::now();
+    let output = rt.block_on_timeout(
+        async move {
+            let _flag = flag;
+            crate::sleep(Duration::from_secs(60)).await;
+        },
+        Duration::from_millis(30),
+    );
+    assert!(matches!(output, Err(crate::time::Elapsed)));
+    let took = start.elapsed();
+    assert!(took >= Duration::from_millis(30) && took < Duration::from_secs(5));
+    assert!(dropped.get());
+    assert_eq!(rt.active_tasks(), 0);
+}
+
+#[test]
+fn select_runs_the_branch_that_completes_first() {
+    let mut rt = MiniRuntime::new();
+    let (winner, took) = rt.block_on(async {
+        crate::time::pause();
+        let start = crate::time::now();
+        let winner = crate::select! {
+            slow = after(30, "slow") => slow,
+            fast = after(10, "fast") => fast,
+            never = future::pending::<&str>() => never,
+        };
+        (winner, crate::time::now() - start)
+    });
+    assert_eq!(winner, "fast");
+    assert_eq!(took, Duration::from_millis(10));
+}
+
+#[test]
+fn select_drops_the_losing_branches() {
+    let mut rt = MiniRuntime::new();
+    let dropped = Rc::new(Cell::new(false));
+    let flag = DropFlag(dropped.clone());
+    let pending_timers = rt.block_on(async move {
+        crate::time::pause();
+        let loser = async move {
+            let _flag = flag;
+            crate::sleep(Duration::from_secs(60)).await;
+        };
+        crate::select! {
+            _ = loser => unreachable!("the long sleep won"),
+            _ = crate::sleep(Duration::from_millis(5)) => {}
+        }
+        // Dropped as soon as the select finished, not at the end of the run.
+        assert!(dropped.get());
+        Handle::current().pending_timers()
+    });
+    assert_eq!(pending_timers, 0);
+}
+
+#[test]
+fn biased_select_always_takes_the_first_of_two_ready_branches() {
+    let mut rt = MiniRuntime::new();
+    for _ in 0..200 {
+        let winner = rt.block_on(async {
+            crate::select! {
+                biased;
+                first = future::ready("first") => first,
+                second = future::ready("second") => second,
+            }
+        });
+        assert_eq!(winner, "first");
+    }
+}
+
+#[test]
+fn unbiased_select_lets_either_ready_branch_win() {
+    let mut rt = MiniRuntime::new();
+    let winners: std::collections::HashSet<_> = (0..200)
+        .map(|_| {
+            rt.block_on(async {
+                crate::select! {
+                    first = future::ready("first") => first,
+                    second = future::ready("second") => second,
+                }
+            })
+        })
+        .collect();
+    assert_eq!(winners.len(), 2);
+}
+
+#[test]
+fn futures_made_and_tasks_spawned_under_enter_run_in_block_on() {
+    let mut rt = MiniRuntime::new();
+    let ran = Rc::new(Cell::new(false));
+    let (sleep, task) = {
+        let _guard = rt.enter();
+        let flag = ran.clone();
+        let task = Handle::current().spawn(async move { flag.set(true) });
+        (crate::sleep(Duration::from_millis(5)), task)
+    };
+    // Entering drives nothing.
+    assert!(!ran.get());
+    assert_eq!(rt.active_tasks(), 1);
+    let start = Instant::now();
+    rt.block_on(async move {
+        sleep.await;
+        task.await.unwrap();
+    });
+    assert!(start.elapsed() >= Duration::from_millis(5));
+    assert!(ran.get());
+}
+
+#[test]
+fn idle_tasks_are_not_polled_again_when_another_task_wakes() {
+    let mut rt = MiniRuntime::new();
+    for _ in 0..100 {
+        rt.spawn(future::pending::<()>());
+    }
+    rt.spawn(async {
+        for _ in 0..50 {
+            crate::yield_now().await;
+        }
+    });
+    rt.run_until_stalled();
+    // One poll for each idle task, then only the busy one: 50 wakes plus
+    // its final poll.
+    let metrics = rt.metrics();
+    assert_eq!(metrics.total_polls, 100 + 51);
+    assert_eq!(metrics.pending_tasks, 100);
+}
+
+#[test]
+fn handle_counts_follow_tasks_and_sleeps_through_a_run() {
+    let mut rt = MiniRuntime::new();
+    let counts = rt.block_on(async {
+        crate::time::pause();
+        let handle = Handle::current();
+        let sleepers: Vec<_> = [10, 20, 30]
+            .into_iter()
+            .map(|millis| handle.spawn(crate::sleep(Duration::from_millis(millis))))
+            .collect();
+        let count = || (handle.active_tasks(), handle.pending_timers());
+        let mut counts = vec![count()];
+        crate::yield_now().await;
+        counts.push(count());
+        // The root's own sleep is a pending timer while it waits, but no
+        // longer once it has woken.
+        crate::sleep(Duration::from_millis(15)).await;
+        counts.push(count());
+        crate::sleep(Duration::from_millis(10)).await;
+        counts.push(count());
+        for sleeper in sleepers {
+            sleeper.await.unwrap();
+        }
+        counts.push(count());
+        counts
+    });
+    assert_eq!(counts, [(4, 0), (4, 3), (3, 2), (2, 1), (1, 0)]);
+    assert_eq!((rt.active_tasks(), rt.pending_timers()), (0, 0));
+}
+
+#[test]
+fn block_on_returns_promptly_once_a_long_sleep_is_dropped() {
+    let mut rt = MiniRuntime::new();
+    let start = Instant::now();
+    let holder = rt.spawn(crate::sleep(Duration::from_secs(60)));
+    rt.block_on(async move {
+        crate::yield_now().await;
+        let mut sleep = crate::sleep(Duration::from_secs(60));
+        assert!(poll_once(&mut sleep).is_pending());
+        assert_eq!(Handle::current().pending_timers(), 2);
+        drop(sleep);
+        holder.abort();
+        crate::sleep(Duration::from_millis(5)).await;
+    });
+    // Neither dropped sleep left a timer behind to keep the loop alive.
+    rt.run_until_stalled();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!((rt.active_tasks(), rt.pending_timers()), (0, 0));
+}
+
+#[test]
+fn spawn_eager_runs_up_to_the_first_await_before_returning() {
+    let mut rt = MiniRuntime::new();
+    let log = rt.block_on(async {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let task_log = log.clone();
+        let task = Handle::current().spawn_eager(async move {
+            task_log.borrow_mut().push("before yield");
+            crate::yield_now().await;
+            task_log.borrow_mut().push("after yield");
+        });
+        log.borrow_mut().push("spawned");
+        assert!(!task.is_finished());
+        let done = Handle::current().spawn_eager(async {});
+        assert!(done.is_finished());
+        task.await.unwrap();
+        log.take()
+    });
+    assert_eq!(log, ["before yield", "spawned", "after yield"]);
+}
+
+#[test]
+fn eager_spawns_from_an_eager_poll_are_queued_instead() {
+    let mut rt = MiniRuntime::new();
+    let log = rt.block_on(async {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let outer_log = log.clone();
+        let outer = Handle::current().spawn_eager(async move {
+            outer_log.borrow_mut().push("outer");
+            let inner_log = outer_log.clone();
+            let inner = Handle::current().spawn_eager(async move {
+                inner_log.borrow_mut().push("inner");
+            });
+            outer_log.borrow_mut().push("outer spawned inner");
+            inner.await.unwrap();
+        });
+        log.borrow_mut().push("root spawned outer");
+        outer.await.unwrap();
+        log.take()
+    });
+    assert_eq!(
+        log,
+        [
+            "outer",
+            "outer spawned inner",
+            "root spawned outer",
+            "inner"
+        ]
+    );
+}
+
+#[test]
+fn slow_poll_hook_reports_the_blocking_task_and_how_long_it_took() {
+    let mut rt = MiniRuntime::new();
+    let slow = Rc::new(RefCell::new(Vec::new()));
+    let reported = slow.clone();
+    rt.on_slow_poll(Duration::from_millis(10), move |id, took| {
+        reported.borrow_mut().push((id, took));
+    });
+    for _ in 0..3 {
+        rt.spawn(yield_then_log(2, 'f', Rc::default()));
+    }
+    let blocker = rt.spawn(async {
+        // Only the second poll blocks.
+        crate::yield_now().await;
+        std::thread::sleep(Duration::from_millis(30));
+        crate::current_task_id().unwrap()
+    });
+    let blocker_id = blocker.id();
+    assert_eq!(rt.block_on(blocker).unwrap(), blocker_id);
+    let slow = slow.borrow();
+    assert_eq!(slow.len(), 1, "{slow:?}");
+    assert_eq!(slow[0].0, blocker_id);
+    assert!(slow[0].1 >= Duration::from_millis(30));
+}
+
+#[test]
+fn block_on_a_ready_future_returns_its_value() {
+    let mut rt = MiniRuntime::new();
+    assert_eq!(rt.block_on(future::ready(5)), 5);
+    assert_eq!(rt.metrics().total_polls, 1);
+}
+
+#[test]
+fn a_pending_future_times_out_on_a_paused_clock() {
+    let mut rt = MiniRuntime::new();
+    let (output, waited) = rt.block_on(async {
+        crate::time::pause();
+        let start = crate::time::now();
+        let output = crate::time::timeout(Duration::from_secs(30), future::pending::<()>()).await;
+        (output, crate::time::now() - start)
+    });
+    assert_eq!(output, Err(crate::time::Elapsed));
+    assert_eq!(waited, Duration::from_secs(30));
+}
+
+#[test]
+fn wakers_cloned_and_dropped_across_threads_neither_leak_nor_double_free() {
+    // Miri is slow; fewer rounds still exercise every refcount path.
+    const ROUNDS: usize = if cfg!(miri) { 20 } else { 2_000 };
+    let mut rt = MiniRuntime::new();
+    let ready = rt.shared.borrow().ready.clone();
+    let baseline = Arc::strong_count(&ready);
+    rt.block_on(async {
+        let waker = future::poll_fn(|cx| Poll::Ready(cx.waker().clone())).await;
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for round in 0..ROUNDS {
+                        let clone = waker.clone();
+                        match round % 3 {
+                            0 => clone.wake(),
+                            1 => {
+                                clone.wake_by_ref();
+                                drop(clone.clone());
+                            }
+                            _ => drop(clone),
+                        }
+                    }
+                });
+            }
+        });
+        drop(waker);
+        crate::yield_now().await;
+    });
+    // Every task has finished, so nothing should hold a waker any more.
+    assert_eq!(Arc::strong_count(&ready), baseline);
+}
+
+#[test]
+fn spawned_sleep_fires_and_consumes_its_timer() {
+    let mut rt = MiniRuntime::new();
+    let start = Instant::now();
+    let slept = rt.block_on(async {
+        let sleeper = Handle::current().spawn(async {
+            crate::sleep(Duration::from_millis(50)).await;
+            true
+        });
+        sleeper.await.unwrap()
+    });
+    assert!(slept);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    // The timer went through the runtime's own storage, which is now empty.
+    let mut shared = rt.shared.borrow_mut();
+    assert_eq!(shared.timers.len(), 0);
+    assert_eq!(shared.timers.next_deadline(), None);
+}
+
+#[test]
+fn a_woken_task_is_polled_exactly_once() {
+    let mut rt = MiniRuntime::new();
+    let polls = Rc::new(Cell::new(0));
+    let parked: Rc<RefCell<Option<Waker>>> = Rc::default();
+    let (counter, slot) = (polls.clone(), parked.clone());
+    let task = rt.spawn(future::poll_fn(move |cx| {
+        counter.set(counter.get() + 1);
+        if counter.get() == 3 {
+            return Poll::Ready(());
+        }
+        *slot.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }));
+    rt.run_until_stalled();
+    assert_eq!(polls.get(), 1);
+    // Nothing woke it, so the scheduler leaves it alone.
+    rt.run_until_stalled();
+    assert_eq!(polls.get(), 1);
+
+    // Waking twice before it runs still queues it once.
+    let waker = parked.borrow_mut().take().unwrap();
+    waker.wake_by_ref();
+    waker.wake();
+    rt.run_until_stalled();
+    assert_eq!(polls.get(), 2);
+
+    parked.borrow_mut().take().unwrap().wake();
+    rt.run_until_stalled();
+    assert_eq!(polls.get(), 3);
+    assert!(task.is_finished());
+}
+
+/// CPU time used so far by the calling thread.
+#[cfg(target_os = "linux")]
+fn thread_cpu_time() -> Duration {
+    use std::ffi::{c_int, c_long};
+
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: i64,
+        tv_nsec: c_long,
+    }
+
+    const CLOCK_THREAD_CPUTIME_ID: c_int = 3;
+
+    unsafe extern "C" {
+        fn clock_gettime(clock: c_int, time: *mut Timespec) -> c_int;
+    }
+
+    let mut time = Timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is a valid, writable `timespec`.
+    assert_eq!(
+        unsafe { clock_gettime(CLOCK_THREAD_CPUTIME_ID, &mut time) },
+        0
+    );
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sleeping_parks_the_thread_instead_of_spinning() {
+    let mut rt = MiniRuntime::new();
+    let (start, cpu) = (Instant::now(), thread_cpu_time());
+    rt.block_on(crate::sleep(Duration::from_millis(200)));
+    let (elapsed, cpu) = (start.elapsed(), thread_cpu_time() - cpu);
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(cpu < Duration::from_millis(20), "used {cpu:?} of CPU");
+}
+
+#[test]
+fn ready_tasks_with_no_timers_run_without_parking() {
+    let mut rt = MiniRuntime::new();
+    let parks = Rc::new(Cell::new(0));
+    let counter = parks.clone();
+    rt.on_park(move || counter.set(counter.get() + 1));
+    rt.block_on(async {
+        for _ in 0..100 {
+            crate::yield_now().await;
+        }
+    });
+    assert_eq!(parks.get(), 0);
+    // With nothing at all left to do, block_on returns straight away.
+    rt.block_on(async {});
+    assert_eq!(parks.get(), 0);
+}
+
+#[test]
+fn park_hook_runs_on_a_sleep_only_once_no_task_is_ready() {
+    let mut rt = MiniRuntime::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let parks = log.clone();
+    rt.on_park(move || parks.borrow_mut().push("park"));
+    let busy = log.clone();
+    rt.spawn(async move {
+        for _ in 0..20 {
+            busy.borrow_mut().push("poll");
+            crate::yield_now().await;
+        }
+    });
+    rt.block_on(crate::sleep(Duration::from_millis(10)));
+    let log = log.borrow();
+    let first_park = log.iter().position(|&event| event == "park").unwrap();
+    // Every park comes after the busy task stopped being ready.
+    assert_eq!(first_park, 20);
+    assert!(log[first_park..].iter().all(|&event| event == "park"));
+}
+
+#[test]
+fn timers_sharing_a_deadline_all_fire_in_one_tick() {
+    let clock = MockClock::new();
+    let mut rt = Builder::new().clock(clock.clone()).build();
+    let woke = Rc::new(RefCell::new(Vec::new()));
+    for n in 0..3 {
+        let woke = woke.clone();
+        rt.spawn(async move {
+            crate::sleep(Duration::from_millis(10)).await;
+            woke.borrow_mut().push(n);
+        });
+    }
+    rt.run_until_stalled();
+    assert_eq!(rt.pending_timers(), 3);
+
+    // Outside the runtime the clock moves without firing anything; the
+    // next tick fires every timer that came due.
+    clock.advance(Duration::from_millis(10));
+    assert!(!rt.tick());
+    assert_eq!(rt.pending_timers(), 0);
+    for _ in 0..3 {
+        assert!(rt.tick());
+    }
+    assert_eq!(*woke.borrow(), [0, 1, 2]);
+    assert!(!rt.tick());
+}
+
+#[test]
+fn awaiting_a_join_handle_yields_the_task_output() {
+    let mut rt = MiniRuntime::new();
+    let answer = rt.block_on(async {
+        let task = Handle::current().spawn(async {
+            crate::yield_now().await;
+            42
+        });
+        // The handle parks until the task finishes, then gets woken.
+        task.await.unwrap()
+    });
+    assert_eq!(answer, 42);
+}
+
+#[test]
+fn join_handle_of_a_finished_task_resolves_at_once() {
+    let mut rt = MiniRuntime::new();
+    let mut task = rt.spawn(async { 42 });
+    rt.run_until_stalled();
+    assert!(task.is_finished());
+    assert!(matches!(poll_once(&mut task), Poll::Ready(Ok(42))));
+}
+
+#[test]
+fn block_on_returns_the_root_output() {
+    let mut rt = MiniRuntime::new();
+    assert_eq!(rt.block_on(async { 7 }), 7);
+    let greeting = rt.block_on(async {
+        crate::yield_now().await;
+        String::from("hello")
+    });
+    assert_eq!(greeting, "hello");
+}
+
+#[test]
+fn block_on_still_runs_unit_futures() {
+    let mut rt = MiniRuntime::new();
+    let ran = Rc::new(Cell::new(false));
+    let flag = ran.clone();
+    let () = rt.block_on(async move { flag.set(true) });
+    assert!(ran.get());
+}
+
+#[test]
+fn tasks_of_different_output_types_each_return_their_own() {
+    let mut rt = MiniRuntime::new();
+    let number = rt.spawn(async { 1u8 });
+    let text = rt.spawn(async { "two".to_owned() });
+    let list = rt.spawn(async {
+        crate::yield_now().await;
+        vec![3.0, 3.5]
+    });
+    let nothing = rt.spawn(async {});
+    let (number, text, list, nothing) = rt.block_on(async move {
+        (
+            number.await.unwrap(),
+            text.await.unwrap(),
+            list.await.unwrap(),
+            nothing.await.unwrap(),
+        )
+    });
+    assert_eq!(number, 1);
+    assert_eq!(text, "two");
+    assert_eq!(list, [3.0, 3.5]);
+    assert_eq!(nothing, ());
+}
+
+#[cfg(feature = "tracing")]
+mod trace_events {
+    use std::{
+        fmt,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+
+    use crate::{Handle, MiniRuntime, current_task_id, time};
+
+    /// An event as `Recorder` saw it: its message, its `task.id` field, and
+    /// the `task.id` of the span it happened in.
+    #[derive(Debug, PartialEq)]
+    struct Seen {
+        message: String,
+        task: Option<u64>,
+        span: Option<u64>,
+    }
+
+    #[derive(Default)]
+    struct Log {
+        /// The `task.id` of each span, indexed by span id minus one.
+        spans: Vec<Option<u64>>,
+        entered: Vec<u64>,
+        events: Vec<Seen>,
+    }
+
+    /// A subscriber that writes everything down.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Log>>);
+
+    #[derive(Default)]
+    struct Fields {
+        message: String,
+        task: Option<u64>,
+    }
+
+    impl Visit for Fields {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "task.id" {
+                self.task = Some(value);
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                self.message = format!("{value:?}");
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields::default();
+            span.record(&mut fields);
+            let mut log = self.0.lock().unwrap();
+            log.spans.push(fields.task);
+            Id::from_u64(log.spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            let mut log = self.0.lock().unwrap();
+            let span = log
+                .entered
+                .last()
+                .and_then(|&id| log.spans[id as usize - 1]);
+            log.events.push(Seen {
+                message: fields.message,
+                task: fields.task,
+                span,
+            });
+        }
+
+        fn enter(&self, span: &Id) {
+            self.0.lock().unwrap().entered.push(span.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.0.lock().unwrap().entered.pop();
+        }
+    }
+
+    #[test]
+    fn events_trace_a_task_from_spawn_to_completion() {
+        let recorder = Recorder::default();
+        let (root, task) = tracing::subscriber::with_default(recorder.clone(), || {
+            MiniRuntime::new().block_on(async {
+                time::pause();
+                let sleeper = Handle::current().spawn(async {
+                    crate::sleep(Duration::from_millis(10)).await;
+                    current_task_id().unwrap()
+                });
+                (current_task_id().unwrap(), sleeper.await.unwrap())
+            })
+        });
+
+        let log = recorder.0.lock().unwrap();
+        let seen = |message: &str, task: Option<u64>, span: Option<u64>| Seen {
+            message: message.to_owned(),
+            task,
+            span,
+        };
+        let (root, id) = (Some(root.0), Some(task.0));
+        let about_task: Vec<_> = log
+            .events
+            .iter()
+            .filter(|event| event.task == id || event.span == id || event.message == "timer fired")
+            .collect();
+        assert_eq!(
+            about_task,
+            [
+                &seen("task spawned", id, root),
+                &seen("timer fired", None, None),
+                &seen("task woken", id, None),
+                // Finishing wakes the root task awaiting its handle.
+                &seen("task woken", root, id),
+                &seen("task completed", None, id),
+            ]
+        );
+        // One poll to start the sleep, one to finish after the timer.
+        let polls = log.spans.iter().filter(|&&span| span == id).count();
+        assert_eq!(polls, 2);
+    }
+}
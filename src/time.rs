@@ -0,0 +1,903 @@
+//! Timers: sleeping, periodic ticks, and bounding how long a future may take.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::{current_clock, fire_due_timers, runtime_clock, schedule_timer};
+
+mod clock;
+pub mod delay_queue;
+mod rate_limiter;
+mod retry;
+mod wheel;
+
+pub(crate) use clock::RuntimeClock;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use delay_queue::DelayQueue;
+pub use rate_limiter::RateLimiter;
+pub use retry::{RetryPolicy, retry};
+
+use wheel::TimerWheel;
+
+/// Returns the current time according to the clock of the runtime driving
+/// this thread, or the real time outside of any runtime.
+pub fn now() -> Instant {
+    current_clock().map_or_else(Instant::now, |clock| clock.now())
+}
+
+/// Freezes the clock of the current-thread runtime driving this thread.
+///
+/// While time is paused it only moves through [`advance`], or on its own
+/// when every task is waiting on a timer: instead of sleeping, the runtime
+/// then jumps the clock straight to the next deadline. Timer-heavy code thus
+/// runs as fast as it can be polled, with timers still firing in deadline
+/// order. Pausing paused time does nothing.
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use mini_rt::time;
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let start = Instant::now();
+/// let order = rt.block_on(async {
+///     time::pause();
+///     let begin = time::now();
+///     let mut order = Vec::new();
+///     for step in 1..=3 {
+///         time::sleep(Duration::from_secs(1)).await;
+///         assert_eq!(time::now() - begin, Duration::from_secs(step));
+///         order.push(step);
+///     }
+///     order
+/// });
+/// assert_eq!(order, [1, 2, 3]);
+/// assert!(start.elapsed() < Duration::from_secs(1));
+/// ```
+///
+/// # Panics
+///
+/// Panics if not called from within a current-thread runtime.
+pub fn pause() {
+    runtime_clock("time::pause").pause();
+}
+
+/// Unfreezes time paused by [`pause`]. The clock carries on from where it
+/// stood rather than jumping back to real time. Resuming running time does
+/// nothing.
+///
+/// # Panics
+///
+/// Panics if not called from within a current-thread runtime.
+pub fn resume() {
+    runtime_clock("time::resume").resume();
+}
+
+/// Moves paused time forward by `duration`, firing every timer that becomes
+/// due.
+///
+/// # Panics
+///
+/// Panics if not called from within a current-thread runtime, or if time is
+/// not paused.
+pub fn advance(duration: Duration) {
+    let clock = runtime_clock("time::advance");
+    assert!(
+        clock.advance_to(clock.now() + duration),
+        "time::advance requires time to be paused"
+    );
+    fire_due_timers();
+}
+
+/// Waits until `duration` has elapsed; see [`sleep_until`].
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(now() + duration)
+}
+
+/// Waits until `deadline`. A deadline that has already passed completes on
+/// the first poll without touching the runtime's timers.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        registered: None,
+    }
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`], backed by the runtime's
+/// timers.
+///
+/// Dropping a sleep, such as the losing branch of a `select!`, cancels its
+/// timer, so the runtime neither wakes for it nor, with time paused, jumps
+/// the clock to it:
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::{Handle, time};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     time::pause();
+///     let start = time::now();
+///     mini_rt::select! {
+///         _ = time::sleep(Duration::from_secs(1)) => {},
+///         _ = time::sleep(Duration::from_secs(100)) => unreachable!(),
+///     }
+///     assert_eq!(Handle::current().pending_timers(), 0);
+///     time::sleep(Duration::from_secs(1)).await;
+///     assert_eq!(time::now() - start, Duration::from_secs(2));
+/// });
+/// ```
+pub struct Sleep {
+    deadline: Instant,
+    /// The pending timer and the waker it will wake, once one is
+    /// scheduled. Dropping the sleep cancels the timer.
+    registered: Option<(Waker, TimerKey)>,
+}
+
+impl Sleep {
+    /// Returns the instant the sleep completes at.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Returns `true` once the deadline has passed, whether or not the
+    /// sleep has been polled since.
+    pub fn is_elapsed(&self) -> bool {
+        now() >= self.deadline
+    }
+
+    /// Re-arms the sleep to complete at `deadline` instead, as for an idle
+    /// timeout pushed back on every message. The timer for the old deadline
+    /// is cancelled, and the next poll schedules one for the new deadline.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use mini_rt::time;
+    ///
+    /// let mut rt = mini_rt::MiniRuntime::new();
+    /// rt.block_on(async {
+    ///     time::pause();
+    ///     let start = time::now();
+    ///     let mut idle = time::sleep(Duration::from_secs(1));
+    ///     idle.reset(start + Duration::from_secs(5));
+    ///     assert_eq!(idle.deadline(), start + Duration::from_secs(5));
+    ///     time::advance(Duration::from_secs(2));
+    ///     assert!(!idle.is_elapsed());
+    ///     (&mut idle).await;
+    ///     assert!(idle.is_elapsed());
+    ///     assert_eq!(time::now() - start, Duration::from_secs(5));
+    /// });
+    /// ```
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        self.registered = None;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // One timer per sleep: re-polls before the deadline only schedule
+        // another if the future has moved to a task with a different waker.
+        if !self
+            .registered
+            .as_ref()
+            .is_some_and(|(waker, _)| waker.will_wake(cx.waker()))
+        {
+            let timer = register_timer(self.deadline, cx.waker());
+            self.registered = Some((cx.waker().clone(), timer));
+        }
+        Poll::Pending
+    }
+}
+
+/// Wakes `waker` once `when` has passed on the clock of the runtime driving
+/// this thread, the way [`Sleep`] does. This is for futures of your own that
+/// need to wait for a deadline; they should still check [`now`] when polled,
+/// since a wake may come early or for another reason.
+///
+/// Outside of any runtime the timer never fires.
+///
+/// ```
+/// use std::{
+///     future::Future,
+///     pin::Pin,
+///     task::{Context, Poll},
+///     time::{Duration, Instant},
+/// };
+/// use mini_rt::time::{self, TimerKey};
+///
+/// /// Resolves to how late it was polled after its deadline.
+/// struct Deadline {
+///     when: Instant,
+///     timer: Option<TimerKey>,
+/// }
+///
+/// impl Future for Deadline {
+///     type Output = Duration;
+///
+///     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Duration> {
+///         let now = time::now();
+///         if now >= self.when {
+///             return Poll::Ready(now - self.when);
+///         }
+///         if self.timer.is_none() {
+///             // Dropping the future drops the key, which disarms the timer.
+///             self.timer = Some(time::register_timer(self.when, cx.waker()));
+///         }
+///         Poll::Pending
+///     }
+/// }
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let late = rt.block_on(async {
+///     time::pause();
+///     let when = time::now() + Duration::from_secs(3);
+///     Deadline { when, timer: None }.await
+/// });
+/// assert_eq!(late, Duration::ZERO);
+/// ```
+pub fn register_timer(when: Instant, waker: &Waker) -> TimerKey {
+    TimerKey {
+        entry: schedule_timer(when, waker),
+    }
+}
+
+/// A timer set by [`register_timer`], disarmed when the key is dropped.
+pub struct TimerKey {
+    /// `None` if no runtime took the timer.
+    entry: Option<Arc<TimerEntry>>,
+}
+
+impl TimerKey {
+    /// Disarms the timer, the same as dropping the key: its waker is not
+    /// woken, and the runtime forgets the deadline rather than waiting for
+    /// it.
+    pub fn cancel(self) {}
+}
+
+impl Drop for TimerKey {
+    fn drop(&mut self) {
+        if let Some(entry) = &self.entry {
+            entry.retire();
+        }
+    }
+}
+
+/// Creates an [`Interval`] whose first tick completes immediately and which
+/// then ticks every `period`.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    interval_at(now(), period)
+}
+
+/// Creates an [`Interval`] whose first tick completes at `start` and which
+/// then ticks every `period`. Pass `time::now() + period` to skip the
+/// immediate first tick.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+    Interval {
+        next: start,
+        period,
+    }
+}
+
+/// Ticks at a fixed period; see [`interval`].
+///
+/// Each deadline is computed from the previous deadline rather than from
+/// when the tick was observed, so time spent between ticks does not
+/// accumulate as drift. A tick that is late is followed by the missed ones
+/// immediately.
+pub struct Interval {
+    next: Instant,
+    period: Duration,
+}
+
+impl Interval {
+    /// Waits for the next tick and returns its scheduled deadline.
+    pub async fn tick(&mut self) -> Instant {
+        let when = self.next;
+        sleep_until(when).await;
+        self.next = when + self.period;
+        when
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+/// Runs `future`, giving up with `Err(Elapsed)` if it has not completed
+/// within `duration`. The future is dropped on timeout; a future that is
+/// ready by the time the deadline passes still wins.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    crate::select! {
+        biased;
+        output = future => Ok(output),
+        _ = sleep(duration) => Err(Elapsed),
+    }
+}
+
+/// Error returned by [`timeout`] when the deadline passes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+/// A runtime's pending timers. With [`Builder::timer_wheel`] they go in a
+/// [`TimerWheel`], unless too far ahead for it; otherwise, and for those,
+/// in a [`TimerHeap`].
+///
+/// [`Builder::timer_wheel`]: crate::Builder::timer_wheel
+pub(crate) struct Timers {
+    wheel: Option<TimerWheel>,
+    heap: TimerHeap,
+    /// How many timers are neither fired nor cancelled, shared with every
+    /// entry so that cancelling one can count it off.
+    pending: Arc<AtomicUsize>,
+}
+
+impl Timers {
+    /// Creates an empty set of timers, using a wheel that counts from
+    /// `wheel_origin` if one is given. The origin must not be later than
+    /// the runtime's clock.
+    pub(crate) fn new(wheel_origin: Option<Instant>) -> Self {
+        Self {
+            wheel: wheel_origin.map(TimerWheel::new),
+            heap: TimerHeap::new(),
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn push(&mut self, when: Instant, waker: Waker) -> Arc<TimerEntry> {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let entry = Arc::new(TimerEntry {
+            waker,
+            cancelled: AtomicBool::new(false),
+            pending: self.pending.clone(),
+        });
+        let unplaced = match &mut self.wheel {
+            Some(wheel) => wheel.insert(when, entry.clone()).err(),
+            None => Some(entry.clone()),
+        };
+        if let Some(unplaced) = unplaced {
+            self.heap.push(when, unplaced);
+        }
+        entry
+    }
+
+    /// Returns the soonest deadline of a timer that is still wanted, or
+    /// with the wheel possibly an earlier time at which it has to turn.
+    pub(crate) fn next_deadline(&mut self) -> Option<Instant> {
+        let wheel = self.wheel.as_mut().and_then(TimerWheel::next_deadline);
+        let heap = self.heap.next_deadline();
+        wheel.into_iter().chain(heap).min()
+    }
+
+    /// Removes a timer due at `now`, returning its waker.
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Option<Waker> {
+        self.wheel
+            .as_mut()
+            .and_then(|wheel| wheel.pop_due(now))
+            .or_else(|| self.heap.pop_due(now))
+    }
+
+    /// Counts the timers that have neither fired nor been cancelled.
+    pub(crate) fn len(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        if let Some(wheel) = &mut self.wheel {
+            wheel.clear();
+        }
+        self.heap.clear();
+    }
+}
+
+/// Pending timers, soonest deadline first.
+struct TimerHeap {
+    heap: BinaryHeap<Timer>,
+    /// Incremented per push, so timers with the same deadline fire in the
+    /// order they were scheduled.
+    next_seq: u64,
+}
+
+/// A heap entry. `BinaryHeap` is a max-heap, so the key is wrapped in
+/// `Reverse` to make the earliest deadline, then the earliest push, the
+/// greatest.
+struct Timer {
+    key: Reverse<(Instant, u64)>,
+    entry: Arc<TimerEntry>,
+}
+
+/// What a timer wakes, shared with the [`TimerKey`] that can cancel it.
+pub(crate) struct TimerEntry {
+    waker: Waker,
+    /// Set once the timer is no longer wanted, or has fired; the runtime
+    /// then discards the entry instead of waking it.
+    cancelled: AtomicBool,
+    /// The owning [`Timers`]' count of pending timers.
+    pending: Arc<AtomicUsize>,
+}
+
+impl TimerEntry {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Marks the timer as done with, counting it off the pending timers the
+    /// first time only, whether it fired or was cancelled.
+    fn retire(&self) {
+        if !self.cancelled.swap(true, Ordering::SeqCst) {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Retires the timer and returns its waker, for firing it.
+    fn fire(&self) -> Waker {
+        self.retire();
+        self.waker.clone()
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl TimerHeap {
+    fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+
+    fn push(&mut self, when: Instant, entry: Arc<TimerEntry>) {
+        self.heap.push(Timer {
+            key: Reverse((when, self.next_seq)),
+            entry,
+        });
+        self.next_seq += 1;
+    }
+
+    /// Returns the soonest deadline of a timer that is still wanted,
+    /// discarding cancelled ones on the way.
+    fn next_deadline(&mut self) -> Option<Instant> {
+        while self.heap.peek()?.entry.is_cancelled() {
+            self.heap.pop();
+        }
+        self.heap.peek().map(|timer| timer.key.0.0)
+    }
+
+    /// Removes the soonest timer if it is due at `now`, returning its waker.
+    fn pop_due(&mut self, now: Instant) -> Option<Waker> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+        self.heap.pop().map(|timer| timer.entry.fire())
+    }
+
+    fn clear(&mut self) {
+        for timer in self.heap.drain() {
+            timer.entry.retire();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Handle, MiniRuntime, future};
+
+    #[test]
+    fn fast_future_beats_its_timeout() {
+        let mut rt = MiniRuntime::new();
+        let output = rt.block_on(async {
+            pause();
+            timeout(Duration::from_millis(50), async {
+                sleep(Duration::from_millis(10)).await;
+                "done"
+            })
+            .await
+        });
+        assert_eq!(output, Ok("done"));
+        assert_eq!(rt.pending_timers(), 0);
+    }
+
+    #[test]
+    fn slow_sleep_is_cut_off_by_a_short_timeout() {
+        let mut rt = MiniRuntime::new();
+        let start = Instant::now();
+        let (output, took) = rt.block_on(async {
+            let begin = now();
+            let output = timeout(Duration::from_millis(10), sleep(Duration::from_secs(60))).await;
+            (output, now() - begin)
+        });
+        assert_eq!(output, Err(Elapsed));
+        assert!(took >= Duration::from_millis(10));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // The abandoned sleep's timer went with it.
+        assert_eq!(rt.pending_timers(), 0);
+    }
+
+    #[test]
+    fn three_ticks_of_a_20ms_interval_take_about_40ms() {
+        let mut rt = MiniRuntime::new();
+        let took = rt.block_on(async {
+            let mut ticks = interval(Duration::from_millis(20));
+            let start = now();
+            for _ in 0..3 {
+                ticks.tick().await;
+            }
+            now() - start
+        });
+        // The first tick is immediate, so this is two periods; waiting a
+        // period before the first one would make it 60ms.
+        assert!(took >= Duration::from_millis(40), "took {took:?}");
+        assert!(took < Duration::from_millis(60), "took {took:?}");
+    }
+
+    #[test]
+    fn interval_ticks_stay_on_their_schedule() {
+        let mut rt = MiniRuntime::new();
+        let offsets = rt.block_on(async {
+            pause();
+            let start = now();
+            let mut ticks = interval(Duration::from_millis(20));
+            let mut offsets = Vec::new();
+            for _ in 0..4 {
+                offsets.push(ticks.tick().await - start);
+                sleep(Duration::from_millis(7)).await;
+            }
+            // A tick that is overdue completes at once, at its own deadline.
+            sleep(Duration::from_millis(50)).await;
+            offsets.push(ticks.tick().await - start);
+            offsets.push(ticks.tick().await - start);
+            offsets
+        });
+        let millis: Vec<_> = offsets.iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, [0, 20, 40, 60, 80, 100]);
+    }
+
+    #[test]
+    fn sleep_until_a_past_deadline_is_ready_at_once() {
+        let mut rt = MiniRuntime::new();
+        let timers = rt.block_on(async {
+            let past = now().checked_sub(Duration::from_millis(1)).unwrap();
+            let mut sleep = sleep_until(past);
+            let first = future::poll_fn(|cx| Poll::Ready(Pin::new(&mut sleep).poll(cx))).await;
+            assert!(first.is_ready());
+            Handle::current().pending_timers()
+        });
+        assert_eq!(timers, 0);
+    }
+
+    #[test]
+    fn sleep_until_a_future_deadline_wakes_right_on_it() {
+        let mut rt = MiniRuntime::new();
+        let (start, woke) = rt.block_on(async {
+            pause();
+            let start = now();
+            sleep_until(start + Duration::from_secs(3)).await;
+            (start, now())
+        });
+        assert_eq!(woke - start, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn repolling_a_sleep_keeps_its_one_timer() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let mut sleep = sleep(Duration::from_secs(60));
+            let entry = |sleep: &Sleep| {
+                let (_, key) = sleep.registered.as_ref().unwrap();
+                Arc::as_ptr(key.entry.as_ref().unwrap())
+            };
+            let first = future::poll_fn(|cx| {
+                assert!(Pin::new(&mut sleep).poll(cx).is_pending());
+                Poll::Ready(entry(&sleep))
+            })
+            .await;
+            future::poll_fn(|cx| {
+                for _ in 0..5 {
+                    assert!(Pin::new(&mut sleep).poll(cx).is_pending());
+                }
+                Poll::Ready(())
+            })
+            .await;
+            assert_eq!(entry(&sleep), first);
+            assert_eq!(Handle::current().pending_timers(), 1);
+        });
+    }
+
+    #[test]
+    fn timeout_prefers_a_ready_future_over_an_expired_deadline() {
+        let mut rt = MiniRuntime::new();
+        for _ in 0..200 {
+            let output = rt.block_on(timeout(Duration::ZERO, future::ready(1)));
+            assert_eq!(output, Ok(1));
+        }
+    }
+
+    #[test]
+    fn a_sleep_reports_its_deadline_and_elapses_without_a_poll() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            pause();
+            let start = now();
+            let mut sleep = sleep(Duration::from_secs(5));
+            assert_eq!(sleep.deadline(), start + Duration::from_secs(5));
+            assert!(!sleep.is_elapsed());
+            advance(Duration::from_secs(5));
+            assert!(sleep.is_elapsed());
+            (&mut sleep).await;
+            assert_eq!(now() - start, Duration::from_secs(5));
+        });
+    }
+
+    #[test]
+    fn resetting_a_polled_sleep_cancels_its_timer_and_moves_the_wake() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            pause();
+            let start = now();
+            let mut sleep = sleep(Duration::from_secs(60));
+            let first = future::poll_fn(|cx| Poll::Ready(Pin::new(&mut sleep).poll(cx))).await;
+            assert!(first.is_pending());
+            assert_eq!(Handle::current().pending_timers(), 1);
+            sleep.reset(start + Duration::from_secs(1));
+            assert_eq!(Handle::current().pending_timers(), 0);
+            (&mut sleep).await;
+            assert_eq!(now() - start, Duration::from_secs(1));
+            // An elapsed sleep can be re-armed for a later deadline.
+            sleep.reset(now() + Duration::from_secs(2));
+            assert!(!sleep.is_elapsed());
+            (&mut sleep).await;
+            assert_eq!(now() - start, Duration::from_secs(3));
+        });
+        assert_eq!(rt.pending_timers(), 0);
+    }
+
+    /// Resolves once it has passed `left` deadlines `step` apart, arming
+    /// one timer per deadline the way a future outside the crate would.
+    struct Steps {
+        next: Instant,
+        step: Duration,
+        left: u32,
+        polls: u32,
+        timer: Option<TimerKey>,
+    }
+
+    impl Future for Steps {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            let this = &mut *self;
+            this.polls += 1;
+            while now() >= this.next {
+                this.left -= 1;
+                if this.left == 0 {
+                    return Poll::Ready(this.polls);
+                }
+                this.next += this.step;
+                this.timer = None;
+            }
+            if this.timer.is_none() {
+                this.timer = Some(register_timer(this.next, cx.waker()));
+            }
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn a_hand_written_future_is_woken_at_each_timer_it_registers() {
+        let mut rt = MiniRuntime::new();
+        let (polls, took) = rt.block_on(async {
+            pause();
+            let start = now();
+            let step = Duration::from_millis(5);
+            let steps = Steps {
+                next: start + step,
+                step,
+                left: 4,
+                polls: 0,
+                timer: None,
+            };
+            (steps.await, now() - start)
+        });
+        assert_eq!(took, Duration::from_millis(20));
+        // The first poll, then one per deadline and no spurious ones.
+        assert_eq!(polls, 5);
+        assert_eq!(rt.pending_timers(), 0);
+    }
+
+    /// A waker counting how often it is woken.
+    #[derive(Default)]
+    struct Counting(std::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for Counting {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn cancelled_timers_leave_the_count_and_never_wake() {
+        let mut rt = MiniRuntime::new();
+        let wakes = Arc::new(Counting::default());
+        let fired = wakes.clone();
+        rt.block_on(async move {
+            pause();
+            let waker = Waker::from(fired);
+            let deadline = now() + Duration::from_millis(10);
+            let cancelled = register_timer(deadline, &waker);
+            let dropped = register_timer(deadline, &waker);
+            let kept = register_timer(deadline, &waker);
+            assert_eq!(Handle::current().pending_timers(), 3);
+            cancelled.cancel();
+            drop(dropped);
+            assert_eq!(Handle::current().pending_timers(), 1);
+            advance(Duration::from_millis(20));
+            drop(kept);
+        });
+        // Only the timer left armed fired.
+        assert_eq!(wakes.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(rt.pending_timers(), 0);
+    }
+
+    /// A waker that does nothing but can be told apart from the others.
+    struct Tagged;
+
+    impl std::task::Wake for Tagged {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Pushes a timer per offset from `origin`, then pops every one and
+    /// returns the index of each in the order they came out.
+    fn pop_order(timers: &mut Timers, origin: Instant, offsets: &[u64]) -> Vec<usize> {
+        let wakers: Vec<_> = offsets
+            .iter()
+            .map(|_| Waker::from(Arc::new(Tagged)))
+            .collect();
+        let _keys: Vec<_> = offsets
+            .iter()
+            .zip(&wakers)
+            .map(|(&millis, waker)| {
+                timers.push(origin + Duration::from_millis(millis), waker.clone())
+            })
+            .collect();
+        let end = origin + Duration::from_secs(1);
+        std::iter::from_fn(|| timers.pop_due(end))
+            .map(|fired| wakers.iter().position(|w| w.will_wake(&fired)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn timers_pop_in_ascending_deadline_order() {
+        let origin = Instant::now();
+        for wheel_origin in [None, Some(origin)] {
+            let mut timers = Timers::new(wheel_origin);
+            let order = pop_order(&mut timers, origin, &[30, 10, 50, 20, 40]);
+            assert_eq!(order, [1, 3, 0, 4, 2]);
+        }
+    }
+
+    #[test]
+    fn equal_deadlines_pop_in_insertion_order() {
+        let origin = Instant::now();
+        for wheel_origin in [None, Some(origin)] {
+            let mut timers = Timers::new(wheel_origin);
+            let order = pop_order(&mut timers, origin, &[20, 10, 20, 10, 20]);
+            assert_eq!(order, [1, 3, 0, 2, 4]);
+        }
+    }
+
+    #[test]
+    fn tasks_sleeping_until_one_instant_wake_in_scheduling_order() {
+        let mut rt = MiniRuntime::new();
+        let order = rt.block_on(async {
+            pause();
+            let deadline = now() + Duration::from_millis(10);
+            let order = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+            let tasks: Vec<_> = (0..3)
+                .map(|n| {
+                    let order = order.clone();
+                    Handle::current().spawn(async move {
+                        // Schedule in the reverse of spawn order.
+                        for _ in n..3 {
+                            crate::yield_now().await;
+                        }
+                        sleep_until(deadline).await;
+                        order.borrow_mut().push(n);
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            order.take()
+        });
+        assert_eq!(order, [2, 1, 0]);
+    }
+
+    #[test]
+    fn pending_count_follows_inserts_fires_and_cancels() {
+        let origin = Instant::now();
+        for wheel_origin in [None, Some(origin)] {
+            let mut timers = Timers::new(wheel_origin);
+            let mut keys: Vec<_> = [1, 2, 3, 4]
+                .map(|secs| TimerKey {
+                    entry: Some(
+                        timers.push(origin + Duration::from_secs(secs), Waker::noop().clone()),
+                    ),
+                })
+                .into();
+            assert_eq!(timers.len(), 4);
+
+            keys.remove(1).cancel();
+            assert_eq!(timers.len(), 3);
+
+            let now = origin + Duration::from_secs(2);
+            while timers.pop_due(now).is_some() {}
+            assert_eq!(timers.len(), 2);
+
+            // Dropping the key of a timer that already fired counts nothing.
+            keys.remove(0);
+            assert_eq!(timers.len(), 2);
+
+            timers.clear();
+            assert_eq!(timers.len(), 0);
+            drop(keys);
+            assert_eq!(timers.len(), 0);
+        }
+    }
+}
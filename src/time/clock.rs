@@ -0,0 +1,147 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A source of the current time for a runtime's timers; see
+/// [`Builder::clock`](crate::Builder::clock).
+///
+/// Timers, [`sleep`](super::sleep) deadlines and [`now`](super::now) all
+/// read the clock of the runtime driving them, so swapping in a
+/// [`MockClock`] makes time-dependent code deterministic.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+}
+
+/// The real clock: [`Instant::now`]. Runtimes use it unless told otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep one clone and install
+/// another in the runtime. Advancing it from inside a task fires every
+/// timer that becomes due:
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use mini_rt::time::{self, MockClock};
+///
+/// let clock = MockClock::new();
+/// let mut rt = mini_rt::MiniRuntime::builder().clock(clock.clone()).build();
+/// let start = Instant::now();
+/// rt.block_on(async move {
+///     let sleeper = mini_rt::Handle::current().spawn(time::sleep(Duration::from_secs(10)));
+///     mini_rt::yield_now().await;
+///     clock.advance(Duration::from_secs(10));
+///     sleeper.await.unwrap();
+/// });
+/// assert!(start.elapsed() < Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    /// Creates a clock frozen at the current real time.
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`. When called on a runtime
+    /// thread, timers that are now due fire straight away; other runtimes
+    /// using the clock notice on their next scheduler tick.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        crate::fire_due_timers();
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// The clock a current-thread runtime reads: its configured clock, which
+/// [`pause`](super::pause) can freeze and [`advance`](super::advance) can
+/// move while frozen.
+pub(crate) struct RuntimeClock {
+    base: Arc<dyn Clock>,
+    state: Mutex<PauseState>,
+}
+
+struct PauseState {
+    /// The frozen time, while paused.
+    paused: Option<Instant>,
+    /// How far ahead of `base` the clock runs, from time spent advancing
+    /// while paused.
+    offset: Duration,
+}
+
+impl RuntimeClock {
+    pub(crate) fn new(base: Arc<dyn Clock>) -> Self {
+        Self {
+            base,
+            state: Mutex::new(PauseState {
+                paused: None,
+                offset: Duration::ZERO,
+            }),
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused.is_some()
+    }
+
+    pub(crate) fn pause(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.paused.is_none() {
+            state.paused = Some(self.base.now() + state.offset);
+        }
+    }
+
+    /// Unfreezes the clock where it stands, so it never runs backwards.
+    pub(crate) fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(paused) = state.paused.take() {
+            state.offset = paused.saturating_duration_since(self.base.now());
+        }
+    }
+
+    /// Moves the frozen clock forward to `when`, if that is later. Returns
+    /// `false` if the clock is not paused.
+    pub(crate) fn advance_to(&self, when: Instant) -> bool {
+        match &mut self.state.lock().unwrap().paused {
+            Some(paused) => {
+                *paused = (*paused).max(when);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Clock for RuntimeClock {
+    fn now(&self) -> Instant {
+        let state = self.state.lock().unwrap();
+        state
+            .paused
+            .unwrap_or_else(|| self.base.now() + state.offset)
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use super::{Sleep, now, sleep_until};
+use crate::Stream;
+
+/// A queue of values that each become available after their own delay.
+///
+/// As a [`Stream`] it yields every value once its deadline passes, soonest
+/// first, and ends while the queue is empty; inserting more values makes it
+/// yield again. Pending values can be removed or rescheduled through the
+/// [`Key`] returned by [`insert`](DelayQueue::insert).
+pub struct DelayQueue<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    len: usize,
+    /// Deadlines in firing order. An entry whose stamp no longer matches its
+    /// slot was removed or reset and is skipped when it surfaces.
+    deadlines: BinaryHeap<Reverse<(Instant, u64, usize)>>,
+    next_stamp: u64,
+    /// A timer for the soonest deadline, recreated when that changes.
+    sleep: Option<Sleep>,
+}
+
+/// Identifies a value in a [`DelayQueue`] until it is yielded or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    index: usize,
+    generation: u64,
+}
+
+struct Slot<T> {
+    /// Bumped whenever the slot is vacated, so stale keys stop matching.
+    generation: u64,
+    entry: Option<Entry<T>>,
+}
+
+struct Entry<T> {
+    value: T,
+    deadline: Instant,
+    stamp: u64,
+}
+
+impl<T> DelayQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            deadlines: BinaryHeap::new(),
+            next_stamp: 0,
+            sleep: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Schedules `value` to be yielded once `delay` has elapsed.
+    pub fn insert(&mut self, value: T, delay: Duration) -> Key {
+        self.insert_at(value, now() + delay)
+    }
+
+    /// Schedules `value` to be yielded once `deadline` has passed.
+    pub fn insert_at(&mut self, value: T, deadline: Instant) -> Key {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot {
+                generation: 0,
+                entry: None,
+            });
+            self.slots.len() - 1
+        });
+        let stamp = self.schedule(index, deadline);
+        let slot = &mut self.slots[index];
+        slot.entry = Some(Entry {
+            value,
+            deadline,
+            stamp,
+        });
+        self.len += 1;
+        Key {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    /// Takes the value for `key` out of the queue before it is due. Returns
+    /// `None` if it was already yielded or removed.
+    pub fn remove(&mut self, key: &Key) -> Option<T> {
+        let slot = self.slot_mut(key)?;
+        let entry = slot.entry.take()?;
+        slot.generation += 1;
+        self.free.push(key.index);
+        self.len -= 1;
+        Some(entry.value)
+    }
+
+    /// Moves the deadline for `key` to `delay` from now. Returns `false` if
+    /// the value was already yielded or removed.
+    pub fn reset(&mut self, key: &Key, delay: Duration) -> bool {
+        if self.slot_mut(key).is_none() {
+            return false;
+        }
+        let deadline = now() + delay;
+        let stamp = self.schedule(key.index, deadline);
+        let entry = self.slots[key.index].entry.as_mut().unwrap();
+        entry.deadline = deadline;
+        entry.stamp = stamp;
+        true
+    }
+
+    /// Returns the deadline for `key`, if it is still queued.
+    pub fn deadline(&self, key: &Key) -> Option<Instant> {
+        let slot = self.slots.get(key.index)?;
+        if slot.generation != key.generation {
+            return None;
+        }
+        slot.entry.as_ref().map(|entry| entry.deadline)
+    }
+
+    fn slot_mut(&mut self, key: &Key) -> Option<&mut Slot<T>> {
+        self.slots
+            .get_mut(key.index)
+            .filter(|slot| slot.generation == key.generation && slot.entry.is_some())
+    }
+
+    fn schedule(&mut self, index: usize, deadline: Instant) -> u64 {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.deadlines.push(Reverse((deadline, stamp, index)));
+        stamp
+    }
+
+    /// Drops heap entries for values that were removed or reset.
+    fn skip_stale(&mut self) {
+        while let Some(&Reverse((_, stamp, index))) = self.deadlines.peek() {
+            match &self.slots[index].entry {
+                Some(entry) if entry.stamp == stamp => return,
+                _ => {
+                    self.deadlines.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Unpin for DelayQueue<T> {}
+
+impl<T> Stream for DelayQueue<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.skip_stale();
+        let Some(&Reverse((deadline, _, index))) = self.deadlines.peek() else {
+            self.sleep = None;
+            return Poll::Ready(None);
+        };
+        if deadline <= now() {
+            self.deadlines.pop();
+            self.sleep = None;
+            let slot = &mut self.slots[index];
+            let entry = slot.entry.take().unwrap();
+            slot.generation += 1;
+            self.free.push(index);
+            self.len -= 1;
+            return Poll::Ready(Some(entry.value));
+        }
+        if self
+            .sleep
+            .as_ref()
+            .is_none_or(|sleep| sleep.deadline() != deadline)
+        {
+            self.sleep = Some(sleep_until(deadline));
+        }
+        let sleep = self.sleep.as_mut().unwrap();
+        // The deadline is still ahead, so this only registers the timer.
+        let _ = Pin::new(sleep).poll(cx);
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MiniRuntime, StreamExt, time::pause};
+
+    #[test]
+    fn values_emerge_in_delay_order_at_their_deadlines() {
+        let mut rt = MiniRuntime::new();
+        let emerged = rt.block_on(async {
+            pause();
+            let start = now();
+            let mut queue = DelayQueue::new();
+            queue.insert("slow", Duration::from_millis(30));
+            queue.insert("fast", Duration::from_millis(10));
+            queue.insert("middle", Duration::from_millis(20));
+            let mut emerged = Vec::new();
+            while let Some(value) = queue.next().await {
+                emerged.push((value, now() - start));
+            }
+            emerged
+        });
+        let ms = Duration::from_millis;
+        assert_eq!(
+            emerged,
+            [("fast", ms(10)), ("middle", ms(20)), ("slow", ms(30))]
+        );
+    }
+
+    #[test]
+    fn removed_values_never_emerge_and_reset_ones_move() {
+        let mut rt = MiniRuntime::new();
+        let emerged = rt.block_on(async {
+            pause();
+            let start = now();
+            let mut queue = DelayQueue::new();
+            let removed = queue.insert("removed", Duration::from_millis(10));
+            let moved = queue.insert("moved", Duration::from_millis(20));
+            queue.insert("kept", Duration::from_millis(30));
+            assert_eq!(queue.remove(&removed), Some("removed"));
+            assert_eq!(queue.remove(&removed), None);
+            assert!(queue.reset(&moved, Duration::from_millis(40)));
+            assert!(!queue.reset(&removed, Duration::from_millis(5)));
+            assert_eq!(queue.len(), 2);
+            let mut emerged = Vec::new();
+            while let Some(value) = queue.next().await {
+                emerged.push((value, now() - start));
+            }
+            assert!(queue.deadline(&moved).is_none());
+            emerged
+        });
+        let ms = Duration::from_millis;
+        assert_eq!(emerged, [("kept", ms(30)), ("moved", ms(40))]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use super::{now, sleep_until};
+
+/// Paces callers so that at most `rate` of them proceed per second.
+///
+/// Callers are let through one at a time, `1 / rate` seconds apart, in the
+/// order they called [`acquire`](RateLimiter::acquire). By default time the
+/// limiter spends unused is not saved up, so there are no bursts; see
+/// [`burst`](RateLimiter::burst) to let a few callers through at once.
+///
+/// ```
+/// use std::time::Duration;
+/// use mini_rt::time::{self, RateLimiter};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// let elapsed = rt.block_on(async {
+///     time::pause();
+///     let limiter = RateLimiter::new(5);
+///     let start = time::now();
+///     for _ in 0..10 {
+///         limiter.acquire().await;
+///     }
+///     time::now() - start
+/// });
+/// // The first call proceeds at once, then one every 200ms.
+/// assert_eq!(elapsed, Duration::from_millis(1800));
+/// ```
+pub struct RateLimiter {
+    interval: Duration,
+    /// How far ahead of its even spacing a caller may be let through, which
+    /// is `interval` times one less than the burst capacity.
+    slack: Duration,
+    /// When the next caller would proceed if callers were spaced evenly.
+    next: Cell<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter letting `rate_per_sec` callers through per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is zero.
+    pub fn new(rate_per_sec: u32) -> Self {
+        assert!(rate_per_sec > 0, "rate limiter rate must be positive");
+        Self::with_interval(Duration::from_secs(1) / rate_per_sec)
+    }
+
+    /// Creates a limiter letting one caller through per `interval`.
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            slack: Duration::ZERO,
+            next: Cell::new(None),
+        }
+    }
+
+    /// Lets up to `capacity` callers through at once after the limiter has
+    /// been idle, refilling one slot per interval.
+    ///
+    /// The long-run rate is unchanged. A capacity of one, the default, means
+    /// no bursts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn burst(mut self, capacity: u32) -> Self {
+        assert!(capacity > 0, "rate limiter burst must be positive");
+        self.slack = self.interval * (capacity - 1);
+        self
+    }
+
+    /// Waits for this caller's turn.
+    ///
+    /// The turn is reserved on the first poll; dropping the future after
+    /// that gives it up without letting anyone else through sooner.
+    pub async fn acquire(&self) {
+        let now = now();
+        let even = self.next.get().map_or(now, |next| next.max(now));
+        self.next.set(Some(even + self.interval));
+        let slot = even
+            .checked_sub(self.slack)
+            .map_or(now, |slot| slot.max(now));
+        sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MiniRuntime, time::pause};
+
+    #[test]
+    fn a_full_burst_passes_at_once_and_the_next_caller_waits_for_a_refill() {
+        let mut rt = MiniRuntime::new();
+        let waits = rt.block_on(async {
+            pause();
+            let limiter = RateLimiter::new(5).burst(3);
+            let start = now();
+            let mut waits = Vec::new();
+            for _ in 0..5 {
+                limiter.acquire().await;
+                waits.push(now() - start);
+            }
+            waits
+        });
+        let ms = Duration::from_millis;
+        assert_eq!(waits, [ms(0), ms(0), ms(0), ms(200), ms(400)]);
+    }
+
+    #[test]
+    fn idle_time_refills_the_burst_but_not_beyond_its_capacity() {
+        let mut rt = MiniRuntime::new();
+        let waits = rt.block_on(async {
+            pause();
+            let limiter = RateLimiter::new(10).burst(2);
+            for _ in 0..2 {
+                limiter.acquire().await;
+            }
+            crate::sleep(Duration::from_secs(5)).await;
+            let start = now();
+            let mut waits = Vec::new();
+            for _ in 0..3 {
+                limiter.acquire().await;
+                waits.push(now() - start);
+            }
+            waits
+        });
+        let ms = Duration::from_millis;
+        assert_eq!(waits, [ms(0), ms(0), ms(100)]);
+    }
+
+    #[test]
+    fn without_a_burst_each_caller_waits_one_interval() {
+        let mut rt = MiniRuntime::new();
+        let waits = rt.block_on(async {
+            pause();
+            let limiter = RateLimiter::with_interval(Duration::from_millis(50));
+            let start = now();
+            let mut waits = Vec::new();
+            for _ in 0..3 {
+                limiter.acquire().await;
+                waits.push(now() - start);
+            }
+            waits
+        });
+        let ms = Duration::from_millis;
+        assert_eq!(waits, [ms(0), ms(50), ms(100)]);
+    }
+}
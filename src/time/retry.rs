@@ -0,0 +1,217 @@
+use std::{future::Future, time::Duration};
+
+use super::sleep;
+
+/// How [`retry`] spaces out its attempts: the first retry waits `base`, and
+/// each one after waits `multiplier` times longer than the last, up to
+/// `max_delay`.
+///
+/// Starts from a multiplier of two, no cap on the delay, five attempts in
+/// all and no jitter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    base: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    max_attempts: u32,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    pub fn new(base: Duration) -> Self {
+        Self {
+            base,
+            multiplier: 2.0,
+            max_delay: Duration::MAX,
+            max_attempts: 5,
+            jitter: false,
+        }
+    }
+
+    /// Sets how much longer each delay is than the one before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `multiplier` is below one, or not a number.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        assert!(multiplier >= 1.0, "retry multiplier must be at least 1");
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Caps how long any one delay may grow.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets how many times the operation runs at most, counting the first
+    /// attempt.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is zero.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "retry needs at least one attempt");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Shortens each delay by a random amount of up to half, so that many
+    /// callers failing together do not all retry in lockstep.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay after `delay`: longer by `multiplier`, within the cap.
+    fn grow(&self, delay: Duration) -> Duration {
+        Duration::try_from_secs_f64(delay.as_secs_f64() * self.multiplier)
+            .unwrap_or(Duration::MAX)
+            .min(self.max_delay)
+    }
+}
+
+/// Runs the future `operation` returns until it succeeds or `policy` runs
+/// out of attempts, sleeping between attempts as the policy says. Resolves
+/// to the first `Ok`, or to the last `Err`.
+///
+/// ```
+/// use std::{cell::Cell, time::Duration};
+/// use mini_rt::time::{self, RetryPolicy};
+///
+/// let mut rt = mini_rt::MiniRuntime::new();
+/// rt.block_on(async {
+///     time::pause();
+///     let policy = RetryPolicy::new(Duration::from_secs(1)).max_attempts(4);
+///
+///     let attempts = Cell::new(0);
+///     let start = time::now();
+///     let result = time::retry(policy, || async {
+///         attempts.set(attempts.get() + 1);
+///         if attempts.get() < 3 { Err("busy") } else { Ok(attempts.get()) }
+///     })
+///     .await;
+///     assert_eq!(result, Ok(3));
+///     // Waited one second, then two.
+///     assert_eq!(time::now() - start, Duration::from_secs(3));
+///
+///     attempts.set(0);
+///     let result: Result<(), _> = time::retry(policy, || async {
+///         attempts.set(attempts.get() + 1);
+///         Err("down")
+///     })
+///     .await;
+///     assert_eq!(result, Err("down"));
+///     assert_eq!(attempts.get(), 4);
+/// });
+/// ```
+pub async fn retry<T, E, F, Fut>(policy: RetryPolicy, mut operation: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut delay = policy.base.min(policy.max_delay);
+    let mut attempt = 1;
+    loop {
+        let error = match operation().await {
+            Ok(output) => return Ok(output),
+            Err(error) => error,
+        };
+        if attempt == policy.max_attempts {
+            return Err(error);
+        }
+        attempt += 1;
+        let wait = if policy.jitter {
+            delay.mul_f64(0.5 + crate::__private::random_below(1024) as f64 / 2048.0)
+        } else {
+            delay
+        };
+        sleep(wait).await;
+        delay = policy.grow(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{
+        MiniRuntime,
+        time::{now, pause},
+    };
+
+    /// Retries an operation that always fails with its attempt number and
+    /// returns the result, plus when each attempt started after the first.
+    fn run_failing(policy: RetryPolicy) -> (Result<(), usize>, Vec<Duration>) {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async move {
+            pause();
+            let start = now();
+            let attempts = RefCell::new(Vec::new());
+            let result = retry(policy, || {
+                attempts.borrow_mut().push(now() - start);
+                let attempt = attempts.borrow().len();
+                async move { Err(attempt) }
+            })
+            .await;
+            (result, attempts.into_inner())
+        })
+    }
+
+    #[test]
+    fn delays_grow_by_the_multiplier_up_to_the_cap() {
+        let policy = RetryPolicy::new(Duration::from_millis(100))
+            .multiplier(3.0)
+            .max_delay(Duration::from_secs(1))
+            .max_attempts(6);
+        let (result, attempts) = run_failing(policy);
+        let millis: Vec<_> = attempts.iter().map(Duration::as_millis).collect();
+        // Waits of 100, 300, 900, then capped at 1000.
+        assert_eq!(millis, [0, 100, 400, 1300, 2300, 3300]);
+        assert_eq!(result, Err(6));
+    }
+
+    #[test]
+    fn a_single_attempt_returns_its_error_without_waiting() {
+        let policy = RetryPolicy::new(Duration::from_secs(1)).max_attempts(1);
+        let (result, attempts) = run_failing(policy);
+        assert_eq!(attempts, [Duration::ZERO]);
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn jitter_keeps_each_delay_between_half_and_all_of_it() {
+        let policy = RetryPolicy::new(Duration::from_secs(1))
+            .multiplier(1.0)
+            .max_attempts(20)
+            .jitter(true);
+        let (result, attempts) = run_failing(policy);
+        assert_eq!(result, Err(20));
+        for pair in attempts.windows(2) {
+            let wait = pair[1] - pair[0];
+            assert!(wait >= Duration::from_millis(500), "waited {wait:?}");
+            assert!(wait <= Duration::from_secs(1), "waited {wait:?}");
+        }
+    }
+
+    #[test]
+    fn succeeding_stops_the_retries() {
+        let mut rt = MiniRuntime::new();
+        let (result, attempts) = rt.block_on(async {
+            pause();
+            let attempts = RefCell::new(0);
+            let policy = RetryPolicy::new(Duration::from_millis(10));
+            let result = retry(policy, || {
+                *attempts.borrow_mut() += 1;
+                let attempt = *attempts.borrow();
+                async move { if attempt == 2 { Ok("up") } else { Err("down") } }
+            })
+            .await;
+            (result, attempts.into_inner())
+        });
+        assert_eq!(result, Ok("up"));
+        assert_eq!(attempts, 2);
+    }
+}
@@ -0,0 +1,294 @@
+//! A hashed hierarchical timer wheel, for runtimes with many timers.
+//!
+//! Time is counted in ticks of a millisecond since the wheel's origin. Each
+//! level has 64 slots, every slot of a level spanning as many ticks as the
+//! whole level below it. A timer goes in the lowest level where its tick and
+//! the current one differ only within that level, so inserting is O(1), and
+//! a slot's timers move down a level (or fire) once the current tick reaches
+//! it. Deadlines beyond the top level do not fit and are left to the caller.
+
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::Arc,
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+use super::TimerEntry;
+
+/// How many bits of a tick each level resolves.
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+/// Four levels of 64 slots cover 2^24 ticks, a little over four and a half
+/// hours at a millisecond each.
+const LEVELS: usize = 4;
+
+pub(super) struct TimerWheel {
+    origin: Instant,
+    /// The tick up to which timers have been fired.
+    elapsed: u64,
+    levels: [Level; LEVELS],
+    /// Timers whose tick has come, in the order they came due.
+    due: VecDeque<Arc<TimerEntry>>,
+}
+
+struct Level {
+    /// Bit `i` is set when `slots[i]` is not empty.
+    occupied: u64,
+    /// Each timer with its tick, in the order it was inserted.
+    slots: [Vec<(u64, Arc<TimerEntry>)>; SLOTS],
+}
+
+impl TimerWheel {
+    /// Creates a wheel counting ticks from `origin`, which must not be later
+    /// than the current time.
+    pub(super) fn new(origin: Instant) -> Self {
+        Self {
+            origin,
+            elapsed: 0,
+            levels: std::array::from_fn(|_| Level {
+                occupied: 0,
+                slots: std::array::from_fn(|_| Vec::new()),
+            }),
+            due: VecDeque::new(),
+        }
+    }
+
+    /// Adds a timer for `when`, rounded up to the next tick so it never
+    /// fires early. Hands `entry` back if `when` is too far ahead to fit.
+    pub(super) fn insert(
+        &mut self,
+        when: Instant,
+        entry: Arc<TimerEntry>,
+    ) -> Result<(), Arc<TimerEntry>> {
+        let millis = when
+            .saturating_duration_since(self.origin)
+            .as_nanos()
+            .div_ceil(1_000_000);
+        match u64::try_from(millis) {
+            Ok(tick) if level_for(self.elapsed, tick) < LEVELS => {
+                self.place(tick, entry);
+                Ok(())
+            }
+            _ => Err(entry),
+        }
+    }
+
+    /// Returns when the wheel next needs to fire or move timers, dropping
+    /// cancelled timers from the slot that would be next. That may be
+    /// earlier than any remaining deadline, when the next slot is on an
+    /// upper level.
+    pub(super) fn next_deadline(&mut self) -> Option<Instant> {
+        self.due.retain(|entry| !entry.is_cancelled());
+        if !self.due.is_empty() {
+            return Some(self.instant(self.elapsed));
+        }
+        loop {
+            let (level, slot, tick) = self.next_slot()?;
+            let timers = &mut self.levels[level].slots[slot];
+            timers.retain(|(_, entry)| !entry.is_cancelled());
+            if !timers.is_empty() {
+                return Some(self.instant(tick));
+            }
+            self.levels[level].occupied &= !(1 << slot);
+        }
+    }
+
+    /// Advances the wheel to `now` and removes the next timer due by then,
+    /// returning its waker.
+    pub(super) fn pop_due(&mut self, now: Instant) -> Option<Waker> {
+        let now = u64::try_from(now.saturating_duration_since(self.origin).as_millis())
+            .unwrap_or(u64::MAX);
+        loop {
+            while let Some(entry) = self.due.pop_front() {
+                if !entry.is_cancelled() {
+                    return Some(entry.fire());
+                }
+            }
+            match self.next_slot() {
+                Some((level, slot, tick)) if tick <= now => {
+                    self.elapsed = tick;
+                    self.levels[level].occupied &= !(1 << slot);
+                    for (tick, entry) in mem::take(&mut self.levels[level].slots[slot]) {
+                        self.place(tick, entry);
+                    }
+                }
+                _ => {
+                    // Nothing is stored before `now`, so skipping ahead
+                    // leaves every timer in a slot still to come.
+                    self.elapsed = self.elapsed.max(now);
+                    return None;
+                }
+            }
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.due.drain(..).for_each(|entry| entry.retire());
+        for level in &mut self.levels {
+            level.occupied = 0;
+            for slot in &mut level.slots {
+                slot.drain(..).for_each(|(_, entry)| entry.retire());
+            }
+        }
+    }
+
+    /// Stores a timer for `tick`, which must fit the wheel.
+    fn place(&mut self, tick: u64, entry: Arc<TimerEntry>) {
+        if tick <= self.elapsed {
+            self.due.push_back(entry);
+            return;
+        }
+        let level = level_for(self.elapsed, tick);
+        let slot = (tick >> (level as u32 * LEVEL_BITS)) as usize % SLOTS;
+        let level = &mut self.levels[level];
+        level.slots[slot].push((tick, entry));
+        level.occupied |= 1 << slot;
+    }
+
+    /// Finds the first slot the current tick will reach, as its level,
+    /// index, and starting tick.
+    ///
+    /// Every stored timer shares its slot's upper levels with the current
+    /// tick and lies in a later slot of its own level, so the lowest
+    /// occupied level holds the soonest slot, and that is its lowest
+    /// occupied one.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        let (index, level) = self
+            .levels
+            .iter()
+            .enumerate()
+            .find(|(_, level)| level.occupied != 0)?;
+        let slot = level.occupied.trailing_zeros() as usize;
+        let shift = index as u32 * LEVEL_BITS;
+        let start = self.elapsed >> (shift + LEVEL_BITS) << (shift + LEVEL_BITS);
+        Some((index, slot, start + ((slot as u64) << shift)))
+    }
+
+    fn instant(&self, tick: u64) -> Instant {
+        self.origin + Duration::from_millis(tick)
+    }
+}
+
+/// Returns the level a timer for `tick` belongs on when the current tick is
+/// `elapsed`: the highest one whose bits of the two differ.
+fn level_for(elapsed: u64, tick: u64) -> usize {
+    let differing = (elapsed ^ tick) | (SLOTS as u64 - 1);
+    ((63 - differing.leading_zeros()) / LEVEL_BITS) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        task::Wake,
+    };
+
+    use super::*;
+
+    /// Records whether its timer has fired.
+    #[derive(Default)]
+    struct Fired(AtomicBool);
+
+    impl Wake for Fired {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn entry() -> (Arc<TimerEntry>, Arc<Fired>) {
+        let fired = Arc::new(Fired::default());
+        let entry = Arc::new(TimerEntry {
+            waker: Waker::from(fired.clone()),
+            cancelled: AtomicBool::new(false),
+            pending: Arc::new(AtomicUsize::new(1)),
+        });
+        (entry, fired)
+    }
+
+    fn at(origin: Instant, millis: u64) -> Instant {
+        origin + Duration::from_millis(millis)
+    }
+
+    /// The levels that hold at least one timer.
+    fn occupied(wheel: &TimerWheel) -> Vec<usize> {
+        (0..LEVELS)
+            .filter(|&level| wheel.levels[level].occupied != 0)
+            .collect()
+    }
+
+    #[test]
+    fn an_upper_level_timer_cascades_down_and_fires_on_its_tick() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        let (timer, _) = entry();
+        assert!(wheel.insert(at(origin, 100), timer).is_ok());
+        assert_eq!(occupied(&wheel), [1]);
+        // The wheel wakes up at the start of the level 1 slot to cascade.
+        assert_eq!(wheel.next_deadline(), Some(at(origin, 64)));
+
+        assert!(wheel.pop_due(at(origin, 99)).is_none());
+        assert_eq!(occupied(&wheel), [0]);
+        assert_eq!(wheel.next_deadline(), Some(at(origin, 100)));
+        assert!(wheel.pop_due(at(origin, 100)).is_some());
+        assert!(occupied(&wheel).is_empty());
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn deadlines_past_the_top_level_fall_back_to_the_heap() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        let far = at(origin, 1 << (LEVEL_BITS * LEVELS as u32));
+        assert!(wheel.insert(far, entry().0).is_err());
+        assert!(
+            wheel
+                .insert(far - Duration::from_millis(1), entry().0)
+                .is_ok()
+        );
+
+        let mut timers = super::super::Timers::new(Some(origin));
+        timers.push(far, Waker::noop().clone());
+        assert_eq!(timers.heap.next_deadline(), Some(far));
+        assert_eq!(timers.wheel.as_mut().unwrap().next_deadline(), None);
+        assert_eq!(timers.next_deadline(), Some(far));
+        assert!(timers.pop_due(far).is_some());
+        assert_eq!(timers.len(), 0);
+    }
+
+    #[test]
+    fn a_cancelled_timer_never_fires() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        let (cancelled, cancelled_fired) = entry();
+        let (kept, _) = entry();
+        assert!(wheel.insert(at(origin, 10), cancelled.clone()).is_ok());
+        assert!(wheel.insert(at(origin, 20), kept).is_ok());
+        cancelled.retire();
+        assert_eq!(wheel.next_deadline(), Some(at(origin, 20)));
+        let fired = wheel.pop_due(at(origin, 30)).unwrap();
+        fired.wake();
+        assert!(wheel.pop_due(at(origin, 30)).is_none());
+        assert!(!cancelled_fired.0.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn timers_sharing_a_slot_fire_in_deadline_order() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        let (later, later_fired) = entry();
+        let (sooner, sooner_fired) = entry();
+        // Both land in level 1 slot 1, covering ticks 64 to 127.
+        assert!(wheel.insert(at(origin, 70), later).is_ok());
+        assert!(wheel.insert(at(origin, 65), sooner).is_ok());
+        assert_eq!(occupied(&wheel), [1]);
+        assert_eq!(wheel.levels[1].occupied, 1 << 1);
+
+        wheel.pop_due(at(origin, 100)).unwrap().wake();
+        assert!(sooner_fired.0.load(Ordering::SeqCst));
+        assert!(!later_fired.0.load(Ordering::SeqCst));
+        wheel.pop_due(at(origin, 100)).unwrap().wake();
+        assert!(later_fired.0.load(Ordering::SeqCst));
+    }
+}
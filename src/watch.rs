@@ -0,0 +1,244 @@
+//! A single-value channel that tells receivers when the value changes.
+//!
+//! Only the latest value is kept: updates sent while a receiver is not
+//! looking are coalesced, and [`Receiver::changed`] then fires once.
+
+use std::{
+    cell::{Ref, RefCell},
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker, ready},
+};
+
+use crate::coop;
+
+/// Creates a watch channel holding `initial`.
+pub fn channel<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+    let state = Rc::new(RefCell::new(State {
+        value: initial,
+        version: 0,
+        wakers: Vec::new(),
+        sender_alive: true,
+        receivers: 1,
+    }));
+    (
+        Sender {
+            state: state.clone(),
+        },
+        Receiver { state, seen: 0 },
+    )
+}
+
+/// The sending half of a watch channel.
+pub struct Sender<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// A receiving half of a watch channel. Clones start out having seen the
+/// same version as the original.
+pub struct Receiver<T> {
+    state: Rc<RefCell<State<T>>>,
+    /// The version this receiver last observed.
+    seen: u64,
+}
+
+/// Future returned by [`Receiver::changed`].
+pub struct Changed<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+/// Error returned by [`Sender::send`] when every receiver is gone, carrying
+/// the value that was not stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Error returned by [`Receiver::changed`] once the sender is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+struct State<T> {
+    value: T,
+    /// Bumped by every send.
+    version: u64,
+    /// Wakers of receivers waiting for the next change.
+    wakers: Vec<Waker>,
+    sender_alive: bool,
+    receivers: usize,
+}
+
+impl<T> State<T> {
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Replaces the value and notifies every receiver.
+    ///
+    /// Returns the value back if there are no receivers.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.state.borrow_mut();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        state.value = value;
+        state.version += 1;
+        state.wake_all();
+        Ok(())
+    }
+
+    /// Borrows the current value.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref::map(self.state.borrow(), |state| &state.value)
+    }
+
+    /// Creates a receiver that considers the current value seen.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.state.borrow_mut();
+        state.receivers += 1;
+        Receiver {
+            state: self.state.clone(),
+            seen: state.version,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.sender_alive = false;
+        state.wake_all();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Borrows the current value without marking it seen.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref::map(self.state.borrow(), |state| &state.value)
+    }
+
+    /// Returns `true` if a value was sent since this receiver last saw one.
+    pub fn has_changed(&self) -> bool {
+        self.state.borrow().version != self.seen
+    }
+
+    /// Waits for a value this receiver has not seen yet, then marks it seen.
+    /// Completes immediately if one was sent since the last call.
+    ///
+    /// Fails once the sender is dropped and the latest value has been seen.
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed { receiver: self }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.state.borrow_mut().receivers += 1;
+        Self {
+            state: self.state.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.state.borrow_mut().receivers -= 1;
+    }
+}
+
+impl<T> Future for Changed<'_, T> {
+    type Output = Result<(), RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(coop::poll_proceed(cx));
+        let receiver = &mut *self.get_mut().receiver;
+        let mut state = receiver.state.borrow_mut();
+        if state.version != receiver.seen {
+            receiver.seen = state.version;
+            return Poll::Ready(Ok(()));
+        }
+        if !state.sender_alive {
+            return Poll::Ready(Err(RecvError));
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a watch channel with no receivers")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("watch sender dropped")
+    }
+}
+
+impl Error for RecvError {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Handle, MiniRuntime, sleep};
+
+    #[test]
+    fn changed_fires_when_the_value_is_updated() {
+        let mut rt = MiniRuntime::new();
+        rt.block_on(async {
+            let (tx, mut rx) = channel("initial");
+            assert!(!rx.has_changed());
+            let update = Handle::current().spawn(async move {
+                sleep(Duration::from_millis(5)).await;
+                tx.send("reloaded").unwrap();
+                tx
+            });
+            rx.changed().await.unwrap();
+            assert_eq!(*rx.borrow(), "reloaded");
+            drop(update.await.unwrap());
+            assert_eq!(rx.changed().await, Err(RecvError));
+        });
+    }
+
+    #[test]
+    fn every_receiver_observes_only_the_latest_value() {
+        let mut rt = MiniRuntime::new();
+        let seen = rt.block_on(async {
+            let (tx, rx) = channel(0);
+            let watchers: Vec<_> = (0..3)
+                .map(|_| {
+                    let mut rx = rx.clone();
+                    Handle::current().spawn(async move {
+                        rx.changed().await.unwrap();
+                        *rx.borrow()
+                    })
+                })
+                .collect();
+            crate::yield_now().await;
+            // Sent back to back, so the watchers only ever see the last.
+            for value in 1..=3 {
+                tx.send(value).unwrap();
+            }
+            let mut seen = Vec::new();
+            for watcher in watchers {
+                seen.push(watcher.await.unwrap());
+            }
+            seen
+        });
+        assert_eq!(seen, [3, 3, 3]);
+    }
+}
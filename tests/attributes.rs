@@ -0,0 +1,45 @@
+//! Async tests written with `#[mini_rt::test]` itself.
+
+use std::{thread, time::Duration};
+
+use mini_rt::{Handle, oneshot, sleep, time};
+
+#[mini_rt::test]
+async fn sleeps_on_a_fresh_runtime() {
+    time::pause();
+    let start = time::now();
+    sleep(Duration::from_secs(5)).await;
+    assert_eq!(time::now() - start, Duration::from_secs(5));
+}
+
+#[mini_rt::test]
+async fn spawned_tasks_share_the_runtime() {
+    let (tx, rx) = oneshot::channel();
+    Handle::current().spawn(async move { tx.send(7).unwrap() });
+    assert_eq!(rx.await.unwrap(), 7);
+}
+
+#[mini_rt::test]
+async fn returns_ok() -> Result<(), String> {
+    sleep(Duration::from_millis(1)).await;
+    Ok(())
+}
+
+/// Fails if run; `err_is_passed_back_to_the_harness` checks what it returns.
+#[mini_rt::test]
+#[ignore = "returns Err on purpose"]
+async fn returns_err() -> Result<(), String> {
+    sleep(Duration::from_millis(1)).await;
+    Err("boom".to_owned())
+}
+
+#[test]
+fn err_is_passed_back_to_the_harness() {
+    assert_eq!(returns_err(), Err("boom".to_owned()));
+}
+
+#[mini_rt::test(worker_threads = 2)]
+async fn runs_on_the_worker_pool() {
+    let name = thread::current().name().map(str::to_owned);
+    assert!(name.unwrap().starts_with("mini-rt-worker-"));
+}
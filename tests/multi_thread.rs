@@ -0,0 +1,265 @@
+use std::{
+    collections::HashSet,
+    hint::black_box,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    task::{Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mini_rt::{MiniRuntime, future};
+
+/// Burns CPU for a fixed amount of work, independent of the scheduler.
+fn spin(rounds: u64) -> u64 {
+    (0..rounds).fold(0u64, |acc, i| {
+        black_box(acc.wrapping_mul(31).wrapping_add(i))
+    })
+}
+
+#[test]
+fn cpu_bound_tasks_run_faster_on_more_workers() {
+    let cores = thread::available_parallelism().map_or(1, usize::from);
+    if cores < 2 {
+        eprintln!("skipping: needs at least two cores, found {cores}");
+        return;
+    }
+    let workers = cores.min(4);
+    let run = |num_workers: usize| {
+        let rt = MiniRuntime::multi_thread(num_workers);
+        let start = Instant::now();
+        let handles: Vec<_> = (0..workers)
+            .map(|_| rt.spawn(async { spin(50_000_000) }))
+            .collect();
+        rt.block_on(async move {
+            for handle in handles {
+                handle.await.unwrap();
+            }
+        });
+        start.elapsed()
+    };
+
+    let serial = run(1);
+    let parallel = run(workers);
+    // Expect at least a 1.5x speedup, well short of the ideal.
+    assert!(
+        parallel * 3 < serial * 2,
+        "{workers} workers took {parallel:?}, one took {serial:?}"
+    );
+}
+
+#[test]
+fn tasks_resolve_their_handles_across_workers() {
+    let rt = MiniRuntime::multi_thread(4);
+    let handles: Vec<_> = (0..64u64).map(|i| rt.spawn(async move { i * 2 })).collect();
+    let sum = rt.block_on(async move {
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.await.unwrap();
+        }
+        sum
+    });
+    assert_eq!(sum, (0..64).map(|i| i * 2).sum());
+}
+
+#[test]
+fn tasks_run_on_named_worker_threads() {
+    let rt = MiniRuntime::multi_thread(2);
+    let name = rt.block_on(async { thread::current().name().map(str::to_owned) });
+    assert!(name.unwrap().starts_with("mini-rt-worker-"));
+}
+
+#[test]
+fn idle_worker_steals_from_a_blocked_one() {
+    let rt = MiniRuntime::multi_thread(2);
+    let parked: Arc<Mutex<Option<Waker>>> = Arc::default();
+    let (parked_tx, parked_rx) = mpsc::channel();
+    let (ran_tx, ran_rx) = mpsc::channel();
+
+    // A task that parks once, then reports which thread polled it next.
+    let polled = AtomicBool::new(false);
+    let waker_slot = parked.clone();
+    let stolen = rt.spawn(future::poll_fn(move |cx| {
+        if !polled.swap(true, Ordering::SeqCst) {
+            *waker_slot.lock().unwrap() = Some(cx.waker().clone());
+            parked_tx.send(()).unwrap();
+            return Poll::Pending;
+        }
+        ran_tx.send(thread::current().id()).unwrap();
+        Poll::Ready(())
+    }));
+    parked_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    // Waking it from a worker queues it on that worker's own deque, which
+    // the worker never gets back to while it blocks here. Only the other
+    // worker stealing it lets the task run.
+    let stealer = rt.block_on(async move {
+        parked.lock().unwrap().take().unwrap().wake();
+        let stealer = ran_rx.recv_timeout(Duration::from_secs(5));
+        assert_ne!(stealer, Ok(thread::current().id()));
+        stealer
+    });
+    assert!(stealer.is_ok(), "the woken task was never stolen");
+    assert!(rt.block_on(stolen).is_ok());
+}
+
+#[test]
+fn busy_workers_share_the_load() {
+    let rt = MiniRuntime::multi_thread(4);
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let handles: Vec<_> = (0..32)
+        .map(|_| {
+            let threads = threads.clone();
+            rt.spawn(async move {
+                // Hold the worker long enough that the others pick up the
+                // rest of the queue, even on a single core.
+                thread::sleep(Duration::from_millis(5));
+                threads.lock().unwrap().insert(thread::current().id());
+            })
+        })
+        .collect();
+    rt.block_on(async move {
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
+    assert!(threads.lock().unwrap().len() > 1);
+}
+
+#[test]
+fn a_panicking_task_resolves_its_handle_with_the_panic() {
+    let rt = MiniRuntime::multi_thread(2);
+    let handle = rt.spawn(async { panic!("boom") });
+    let err = rt.block_on(async move { handle.await.unwrap_err() });
+    assert!(err.is_panic());
+    assert_eq!(*err.into_panic().downcast::<&str>().unwrap(), "boom");
+}
+
+#[test]
+fn sleep_works_on_worker_threads() {
+    let rt = MiniRuntime::multi_thread(2);
+    let elapsed = rt.block_on(async {
+        let start = Instant::now();
+        mini_rt::sleep(Duration::from_millis(20)).await;
+        start.elapsed()
+    });
+    assert!(elapsed >= Duration::from_millis(20));
+}
+
+#[test]
+fn dropping_the_runtime_cancels_unfinished_tasks() {
+    let rt = MiniRuntime::multi_thread(2);
+    let handle = rt.spawn(future::pending::<()>());
+    drop(rt);
+    assert!(handle.is_finished());
+    let err = MiniRuntime::new().block_on(handle).unwrap_err();
+    assert!(err.is_cancelled());
+}
+
+#[test]
+fn lopsided_submission_still_balances() {
+    const TASKS: usize = 20;
+    let rt = MiniRuntime::multi_thread(2);
+    let ran: Arc<Mutex<Vec<thread::ThreadId>>> = Arc::default();
+    let (parked_tx, parked_rx) = mpsc::channel::<Waker>();
+    let handles: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let (ran, parked_tx) = (ran.clone(), parked_tx.clone());
+            let polled = AtomicBool::new(false);
+            rt.spawn(future::poll_fn(move |cx| {
+                if !polled.swap(true, Ordering::SeqCst) {
+                    parked_tx.send(cx.waker().clone()).unwrap();
+                    return Poll::Pending;
+                }
+                thread::sleep(Duration::from_millis(5));
+                ran.lock().unwrap().push(thread::current().id());
+                Poll::Ready(())
+            }))
+        })
+        .collect();
+    let wakers: Vec<_> = parked_rx.iter().take(TASKS).collect();
+
+    // Woken from one worker, every task lands on that worker's deque.
+    rt.block_on(async move {
+        for waker in wakers {
+            waker.wake();
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
+    let ran = ran.lock().unwrap();
+    let first = ran.iter().filter(|&&id| id == ran[0]).count();
+    let other = TASKS - first;
+    assert!(
+        first.min(other) >= TASKS / 4,
+        "split {first}/{other} between the workers"
+    );
+}
+
+#[test]
+fn block_on_from_a_worker_thread_panics() {
+    let rt = MiniRuntime::multi_thread(2);
+    let handle = rt.spawn(async {
+        MiniRuntime::multi_thread(1).block_on(async {});
+    });
+    let payload = rt.block_on(handle).unwrap_err().into_panic();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or(payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap();
+    assert!(message.starts_with("cannot call block_on from within a runtime"));
+}
+
+#[test]
+fn tasks_keep_running_while_one_is_in_block_in_place() {
+    const TASKS: usize = 8;
+    let rt = MiniRuntime::multi_thread(2);
+    let (parked_tx, parked_rx) = mpsc::channel::<Waker>();
+    let (done_tx, done_rx) = mpsc::channel();
+    let handles: Vec<_> = (0..TASKS)
+        .map(|n| {
+            let (parked_tx, done_tx) = (parked_tx.clone(), done_tx.clone());
+            let polled = AtomicBool::new(false);
+            rt.spawn(future::poll_fn(move |cx| {
+                if !polled.swap(true, Ordering::SeqCst) {
+                    parked_tx.send(cx.waker().clone()).unwrap();
+                    return Poll::Pending;
+                }
+                done_tx.send(n).unwrap();
+                Poll::Ready(())
+            }))
+        })
+        .collect();
+    let wakers: Vec<_> = parked_rx.iter().take(TASKS).collect();
+
+    // Woken from this worker, the tasks queue behind it; the closure only
+    // returns once all of them have run elsewhere.
+    let finished = rt.block_on(async move {
+        for waker in wakers {
+            waker.wake();
+        }
+        mini_rt::block_in_place(|| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut finished = Vec::new();
+            while finished.len() < TASKS {
+                let left = deadline.saturating_duration_since(Instant::now());
+                match done_rx.recv_timeout(left) {
+                    Ok(n) => finished.push(n),
+                    Err(_) => break,
+                }
+            }
+            finished
+        })
+    });
+    assert_eq!(finished.len(), TASKS, "only {finished:?} ran");
+    rt.block_on(async move {
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
+}
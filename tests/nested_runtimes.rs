@@ -0,0 +1,79 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use mini_rt::{Handle, MiniRuntime, StreamExt, channel, time};
+
+#[test]
+fn runtimes_on_two_threads_each_drive_their_own_timers_and_tasks() {
+    let (to_worker, requests) = mpsc::channel::<u64>();
+    let (to_main, replies) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        let mut rt = MiniRuntime::new();
+        let slept = rt.block_on(async move {
+            // Pausing here leaves the main thread's clock running.
+            time::pause();
+            let start = time::now();
+            let mut requests = channel::from_std(requests);
+            while let Some(n) = requests.next().await {
+                let task = Handle::current().spawn(async move {
+                    time::sleep(Duration::from_secs(n)).await;
+                    n * 10
+                });
+                to_main.send(task.await.unwrap()).unwrap();
+            }
+            time::now() - start
+        });
+        (slept, rt.metrics().total_spawned, rt.pending_timers())
+    });
+
+    let mut rt = MiniRuntime::new();
+    let start = Instant::now();
+    let (replies, slept) = rt.block_on(async move {
+        let ticker = Handle::current().spawn(async {
+            let begin = time::now();
+            time::sleep(Duration::from_millis(20)).await;
+            time::now() - begin
+        });
+        for n in 1..=3 {
+            to_worker.send(n).unwrap();
+        }
+        drop(to_worker);
+        let replies: Vec<_> = channel::from_std(replies).collect().await;
+        (replies, ticker.await.unwrap())
+    });
+    assert_eq!(replies, [10, 20, 30]);
+    assert!(slept >= Duration::from_millis(20));
+    // Six seconds of sleeping on the worker took no real time here.
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(rt.metrics().total_spawned, 2);
+
+    let (worker_slept, worker_spawned, worker_timers) = worker.join().unwrap();
+    assert!(worker_slept >= Duration::from_secs(6));
+    // Its root plus one task per request, none of them on the main runtime.
+    assert_eq!(worker_spawned, 4);
+    assert_eq!(worker_timers, 0);
+}
+
+#[test]
+fn block_on_inside_block_on_panics_with_a_clear_message() {
+    let mut outer = MiniRuntime::new();
+    let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+        outer.block_on(async {
+            // Another thread is free to run a runtime of its own meanwhile.
+            let other = thread::spawn(|| MiniRuntime::new().block_on(async { 7 }));
+            assert_eq!(other.join().unwrap(), 7);
+            MiniRuntime::new().block_on(async {});
+        })
+    }))
+    .unwrap_err();
+    let message = payload.downcast::<&str>().unwrap();
+    assert_eq!(
+        *message,
+        "cannot call block_on from within a runtime: a runtime is already running tasks on \
+         this thread"
+    );
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use mini_rt::{
+    AsyncBufReadExt, Handle, MiniRuntime,
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+};
+
+#[test]
+fn tcp_echo_round_trip() {
+    let mut rt = MiniRuntime::new();
+    let reply = rt
+        .block_on_timeout(
+            async {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                let addr = listener.local_addr().unwrap();
+                let server = Handle::current().spawn(async move {
+                    let (stream, _) = listener.accept().await?;
+                    let mut stream = BufReader::new(stream);
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await?;
+                    stream.get_mut().write_all(line.as_bytes()).await?;
+                    Ok::<_, std::io::Error>(line)
+                });
+
+                let mut client = BufReader::new(TcpStream::connect(addr).await.unwrap());
+                client.get_mut().write_all(b"hello, echo\n").await.unwrap();
+                let mut reply = String::new();
+                client.read_line(&mut reply).await.unwrap();
+                assert_eq!(server.await.unwrap().unwrap(), "hello, echo\n");
+                reply
+            },
+            Duration::from_secs(5),
+        )
+        .expect("echo did not finish");
+    assert_eq!(reply, "hello, echo\n");
+}
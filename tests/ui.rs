@@ -0,0 +1,23 @@
+//! Compile tests for the `#[mini_rt::main]` and `#[mini_rt::test]`
+//! attributes. Run with `TRYBUILD=overwrite` to regenerate the expected
+//! errors after changing a message.
+
+#[test]
+fn main_attribute() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/main/unit.rs");
+    t.pass("tests/ui/main/result.rs");
+    t.pass("tests/ui/main/worker_threads.rs");
+    t.compile_fail("tests/ui/main/not_async.rs");
+    t.compile_fail("tests/ui/main/arguments.rs");
+    t.compile_fail("tests/ui/main/bad_worker_threads.rs");
+    t.compile_fail("tests/ui/main/unknown_argument.rs");
+}
+
+#[test]
+fn test_attribute() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/test/not_async.rs");
+    t.compile_fail("tests/ui/test/arguments.rs");
+    t.compile_fail("tests/ui/test/generic.rs");
+}
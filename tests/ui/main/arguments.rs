@@ -0,0 +1,4 @@
+#[mini_rt::main]
+async fn main(count: usize) {
+    let _ = count;
+}
@@ -0,0 +1,2 @@
+#[mini_rt::main(worker_threads = 0)]
+async fn main() {}
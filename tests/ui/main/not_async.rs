@@ -0,0 +1,2 @@
+#[mini_rt::main]
+fn main() {}
@@ -0,0 +1,25 @@
+use std::{fmt, time::Duration};
+
+#[derive(Debug)]
+struct Error;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("error")
+    }
+}
+
+impl std::error::Error for Error {}
+
+async fn step(ok: bool) -> Result<u32, Error> {
+    mini_rt::sleep(Duration::from_millis(1)).await;
+    if ok { Ok(1) } else { Err(Error) }
+}
+
+#[mini_rt::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let value = step(true).await?;
+    assert_eq!(value, 1);
+    assert!(step(false).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,6 @@
+use std::time::Duration;
+
+#[mini_rt::main]
+async fn main() {
+    mini_rt::sleep(Duration::from_millis(1)).await;
+}
@@ -0,0 +1,2 @@
+#[mini_rt::main(flavor = "current_thread")]
+async fn main() {}
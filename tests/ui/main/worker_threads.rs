@@ -0,0 +1,7 @@
+use std::thread;
+
+#[mini_rt::main(worker_threads = 2)]
+async fn main() {
+    let name = thread::current().name().map(str::to_owned);
+    assert!(name.unwrap().starts_with("mini-rt-worker-"));
+}
@@ -0,0 +1,6 @@
+#[mini_rt::test]
+async fn takes_arguments(count: usize) {
+    let _ = count;
+}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[mini_rt::test]
+async fn generic<T>() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[mini_rt::test]
+fn not_async() {}
+
+fn main() {}